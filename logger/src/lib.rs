@@ -44,18 +44,23 @@ impl Log {
             thread::spawn(move || {
                 loop {
                     thread::sleep(Duration::from_millis(50));
-                    let mut buffer_lock = LOG.buffer.lock().unwrap();
-                    for sink in LOG.sinks.lock().unwrap().iter_mut() {
-                        let _ = sink.write_all(buffer_lock.as_bytes());
-                        let _ = sink.flush();
-                    }
-                    buffer_lock.clear();
+                    Log::flush();
                 }
             });
         }
         LOG.sinks.lock().unwrap().push(elem);
     }
 
+    /// Writes the pending messages to the sinks right away, e.g. before exiting the process.
+    pub fn flush() {
+        let mut buffer_lock = LOG.buffer.lock().unwrap();
+        for sink in LOG.sinks.lock().unwrap().iter_mut() {
+            let _ = sink.write_all(buffer_lock.as_bytes());
+            let _ = sink.flush();
+        }
+        buffer_lock.clear();
+    }
+
     pub fn write(priority: Priority, output: &str) {
         LOG.buffer
             .lock()
@@ -1,9 +1,11 @@
-use slint::SharedString;
-use std::thread;
-use vcd_statistical_analysis::{perform_analysis_and_save, Configuration};
+use slint::{SharedString, Weak};
+use std::{cell::RefCell, rc::Rc, sync::Arc, thread};
+use vcd_statistical_analysis::{
+    perform_analysis_and_save, CancelToken, Configuration, Phase, ProgressSink,
+};
 
 slint::slint! {
-    import { Button, GroupBox, LineEdit } from "std-widgets.slint";
+    import { Button, GroupBox, LineEdit, ProgressIndicator } from "std-widgets.slint";
 
     export component MainWindow inherits Window {
         in-out property<string> in_path <=> inpath.text;
@@ -12,8 +14,10 @@ slint::slint! {
         in property<bool> interface_enabled;
         in property<bool> button_enabled;
         in property<string> status_text <=> status_label.text;
+        in property<float> progress <=> progress_bar.progress;
 
         callback button-pressed <=> evaluate_button.clicked;
+        callback cancel-pressed <=> cancel_button.clicked;
         callback browse-source-pressed <=> browse_source_button.clicked;
         callback in-file-edited <=> inpath.edited;
         callback out-file-edited <=> outpath.edited;
@@ -64,10 +68,20 @@ slint::slint! {
                     }
                 }
             }
-            evaluate_button := Button {
-                text: "Perform analysis";
-                enabled: interface_enabled && button_enabled;
-                primary: true;
+            HorizontalLayout {
+                spacing: 32px;
+                evaluate_button := Button {
+                    text: "Perform analysis";
+                    enabled: interface_enabled && button_enabled;
+                    primary: true;
+                }
+                cancel_button := Button {
+                    text: "Cancel";
+                    enabled: !interface_enabled;
+                }
+            }
+            progress_bar := ProgressIndicator {
+                visible: !interface_enabled;
             }
             status_label := Text {
                 vertical-alignment: center;
@@ -77,6 +91,29 @@ slint::slint! {
     }
 }
 
+/// Forwards the progress of the analysis to the window.
+struct GuiProgress {
+    window: Weak<MainWindow>,
+}
+
+impl ProgressSink for GuiProgress {
+    fn phase(&self, phase: Phase) {
+        let _ = self.window.upgrade_in_event_loop(move |window| {
+            window.set_status_text(format!("{}...", phase).into());
+        });
+    }
+
+    fn bytes(&self, read: u64, total: u64) {
+        let progress = match total {
+            0 => 0.0,
+            _ => read as f32 / total as f32,
+        };
+        let _ = self.window.upgrade_in_event_loop(move |window| {
+            window.set_progress(progress);
+        });
+    }
+}
+
 fn main() {
     let window = MainWindow::new().unwrap();
     window.set_interface_enabled(true);
@@ -104,6 +141,11 @@ fn main() {
             );
         });
     }
+    let cancel = Rc::new(RefCell::new(CancelToken::new()));
+    {
+        let cancel = cancel.clone();
+        window.on_cancel_pressed(move || cancel.borrow().cancel());
+    }
     {
         let weak_window = window.as_weak();
 
@@ -120,6 +162,12 @@ fn main() {
                 .next()
                 .unwrap_or(' ');
             in_window.set_status_text("Computing VCD statystical analysis...".into());
+            in_window.set_progress(0.0);
+            let progress = Arc::new(GuiProgress {
+                window: weak_window.clone(),
+            });
+            let analysis_cancel = CancelToken::new();
+            *cancel.borrow_mut() = analysis_cancel.clone();
 
             let weak_window_2 = weak_window.clone();
             thread::spawn(move || {
@@ -127,7 +175,9 @@ fn main() {
                     in_file,
                     out_file,
                     separator,
-                    use_spinner: false,
+                    progress: Some(progress),
+                    cancel: analysis_cancel.clone(),
+                    ..Default::default()
                 }) {
                     slint::invoke_from_event_loop(move || {
                         weak_window_2
@@ -138,10 +188,15 @@ fn main() {
                     })
                     .unwrap();
                 } else {
+                    let status = match analysis_cancel.is_cancelled() {
+                        true => format!("Analysis cancelled. Partial result in {}", out_file_txt),
+                        false => format!("VCD analyzed. Result in {}", out_file_txt),
+                    };
                     slint::invoke_from_event_loop(move || {
-                        weak_window_2.upgrade().unwrap().set_status_text(
-                            format!("VCD analyzed. Result in {}", out_file_txt).into(),
-                        );
+                        weak_window_2
+                            .upgrade()
+                            .unwrap()
+                            .set_status_text(status.into());
                         weak_window_2.upgrade().unwrap().set_interface_enabled(true);
                     })
                     .unwrap();
@@ -0,0 +1,393 @@
+use std::cell::{OnceCell, RefCell};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use crate::wave::Wave;
+use crate::{Configuration, Module, Node, Signal, VCD};
+
+const MAGIC: &[u8; 8] = b"OXYVCDIX";
+/// Version of the database layout, increased on every incompatible change.
+pub const DATABASE_VERSION: u32 = 2;
+/// Bytes of the start and of the end of the dump covered by its checksum.
+const SAMPLE_BYTES: u64 = 1 << 20;
+
+/// Path of the database saved next to `in_file`.
+pub fn database_path(in_file: &str) -> String {
+    format!("{}.idx", in_file)
+}
+
+/// Identifies the content of a dump without reading all of it: its size, modification
+/// time and a checksum of its first and last megabyte.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Source {
+    size: u64,
+    modified: u64,
+    checksum: u64,
+}
+
+impl Source {
+    pub(crate) fn of(in_file: &str) -> Result<Self, String> {
+        let mut file = File::open(in_file).map_err(|err| format!("{}: {}", in_file, err))?;
+        let metadata = file.metadata().map_err(|err| err.to_string())?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |time| time.as_nanos() as u64);
+        let size = metadata.len();
+
+        let mut sample = vec![];
+        (&mut file)
+            .take(SAMPLE_BYTES)
+            .read_to_end(&mut sample)
+            .map_err(|err| err.to_string())?;
+        let tail = size.saturating_sub(SAMPLE_BYTES).max(sample.len() as u64);
+        file.seek(SeekFrom::Start(tail))
+            .and_then(|_| file.read_to_end(&mut sample))
+            .map_err(|err| err.to_string())?;
+        Ok(Source {
+            size,
+            modified,
+            checksum: checksum(&sample),
+        })
+    }
+}
+
+/// FNV-1a hash, used to detect a changed dump or a damaged database.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Where the changes of a wave are in the database.
+#[derive(Debug)]
+struct WaveLocation {
+    offset: u64,
+    len: u64,
+    checksum: u64,
+}
+
+/// Database a [`VCD`] was loaded from, read again for every wave used the first time.
+#[derive(Debug)]
+pub(crate) struct Database {
+    file: RefCell<File>,
+    waves: Vec<WaveLocation>,
+}
+
+impl Database {
+    pub(crate) fn load_wave(&self, index: usize) -> Result<Wave, String> {
+        let location = &self.waves[index];
+        let mut bytes = vec![0; location.len as usize];
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(location.offset))
+            .and_then(|_| file.read_exact(&mut bytes))
+            .map_err(|err| format!("Database: {}", err))?;
+        if checksum(&bytes) != location.checksum {
+            return Err("Database damaged".into());
+        }
+        Wave::decode(&mut Decoder::new(&bytes))
+    }
+}
+
+/// Little endian encoding of the database fields.
+#[derive(Default)]
+pub(crate) struct Encoder {
+    pub(crate) bytes: Vec<u8>,
+}
+
+impl Encoder {
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn usize(&mut self, value: usize) {
+        self.u64(value as u64)
+    }
+
+    pub(crate) fn i64(&mut self, value: i64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Length followed by the bytes.
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.usize(bytes.len());
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn str(&mut self, string: &str) {
+        self.bytes(string.as_bytes())
+    }
+
+    fn option(&mut self, string: &Option<String>) {
+        match string {
+            Some(string) => {
+                self.bytes.push(1);
+                self.str(string);
+            }
+            None => self.bytes.push(0),
+        }
+    }
+}
+
+pub(crate) struct Decoder<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Decoder { bytes, offset: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset.saturating_add(len))
+            .ok_or("Database truncated")?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn usize(&mut self) -> Result<usize, String> {
+        usize::try_from(self.u64()?).map_err(|_| "Database damaged".into())
+    }
+
+    pub(crate) fn i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.usize()?;
+        self.take(len)
+    }
+
+    fn str(&mut self) -> Result<Arc<str>, String> {
+        std::str::from_utf8(self.bytes()?)
+            .map(Arc::from)
+            .map_err(|_| "Database damaged".into())
+    }
+
+    fn option(&mut self) -> Result<Option<String>, String> {
+        match self.u8()? {
+            0 => Ok(None),
+            _ => Ok(Some(self.str()?.to_string())),
+        }
+    }
+
+    /// Index below `len`.
+    fn index(&mut self, len: usize) -> Result<usize, String> {
+        match self.usize()? {
+            index if index < len => Ok(index),
+            _ => Err("Database damaged".into()),
+        }
+    }
+}
+
+impl VCD {
+    /// Writes the index to `database`. The file is replaced only once complete, so that
+    /// an interrupted save leaves the previous database in place.
+    pub fn save(&self, database: &str) -> Result<(), String> {
+        let mut header = Encoder::default();
+        header.u64(self.source.size);
+        header.u64(self.source.modified);
+        header.u64(self.source.checksum);
+        header.u64(self.separator as u64);
+        header.option(&self.date);
+        header.option(&self.version);
+        header.option(&self.timescale);
+        header.usize(self.hierarchy.len());
+        for module in &self.hierarchy {
+            header.str(&module.name);
+            header.usize(module.parent);
+            header.usize(module.children.len());
+            for child in &module.children {
+                match child {
+                    Node::Module(index) => {
+                        header.bytes.push(0);
+                        header.usize(*index);
+                    }
+                    Node::Signal(index) => {
+                        header.bytes.push(1);
+                        header.usize(*index);
+                    }
+                }
+            }
+        }
+        header.usize(self.signals.len());
+        for signal in &self.signals {
+            header.str(&signal.id);
+            header.str(&signal.name);
+            header.usize(signal.width);
+            header.usize(signal.parent_index);
+            header.usize(signal.wave);
+        }
+
+        let mut waves = vec![];
+        header.usize(self.waves.len());
+        for index in 0..self.waves.len() {
+            let mut wave = Encoder::default();
+            self.load_wave(index)?.encode(&mut wave);
+            header.usize(wave.bytes.len());
+            header.u64(checksum(&wave.bytes));
+            waves.push(wave.bytes);
+        }
+
+        let mut out = Encoder::default();
+        out.bytes.extend_from_slice(MAGIC);
+        out.bytes.extend_from_slice(&DATABASE_VERSION.to_le_bytes());
+        out.u64(checksum(&header.bytes));
+        out.bytes(&header.bytes);
+
+        let temporary = format!("{}.tmp", database);
+        let write = || -> std::io::Result<()> {
+            let mut file = File::create(&temporary)?;
+            file.write_all(&out.bytes)?;
+            waves.iter().try_for_each(|wave| file.write_all(wave))?;
+            file.sync_all()?;
+            fs::rename(&temporary, database)
+        };
+        write().map_err(|err| {
+            let _ = fs::remove_file(&temporary);
+            format!("{}: {}", database, err)
+        })
+    }
+
+    /// Reads the index of `configuration.in_file` from `database`, leaving the changes on
+    /// disk until a signal is used. Fails if the database is from another version or if
+    /// the dump changed since it was saved.
+    pub fn load(database: &str, configuration: &Configuration) -> Result<VCD, String> {
+        let mut file = File::open(database).map_err(|err| format!("{}: {}", database, err))?;
+        let mut start = [0; 28];
+        file.read_exact(&mut start)
+            .map_err(|_| "Database truncated")?;
+        let mut decoder = Decoder::new(&start);
+        if decoder.take(8)? != MAGIC {
+            return Err(format!("{} is not a database", database));
+        }
+        let version = decoder.u32()?;
+        if version != DATABASE_VERSION {
+            return Err(format!(
+                "Database version {} instead of {}",
+                version, DATABASE_VERSION
+            ));
+        }
+        let header_checksum = decoder.u64()?;
+        // A damaged length is caught here, as the checksum needs the header read first
+        let length = decoder.u64()?;
+        let size = file.metadata().map_err(|err| err.to_string())?.len();
+        if length > size.saturating_sub(start.len() as u64) {
+            return Err("Database truncated or damaged".into());
+        }
+        let mut header = vec![0; length as usize];
+        file.read_exact(&mut header)
+            .map_err(|_| "Database truncated")?;
+        if checksum(&header) != header_checksum {
+            return Err("Database damaged".into());
+        }
+
+        let mut decoder = Decoder::new(&header);
+        let source = Source {
+            size: decoder.u64()?,
+            modified: decoder.u64()?,
+            checksum: decoder.u64()?,
+        };
+        if source != Source::of(&configuration.in_file)? {
+            return Err(format!(
+                "{} changed since it was indexed",
+                configuration.in_file
+            ));
+        }
+        if decoder.u64()? != configuration.separator as u64 {
+            return Err("Database indexed with another separator".into());
+        }
+        let mut vcd = VCD {
+            source,
+            separator: configuration.separator,
+            date: decoder.option()?,
+            version: decoder.option()?,
+            timescale: decoder.option()?,
+            ..Default::default()
+        };
+
+        // Children are checked once the number of signals is known
+        let modules = decoder.usize()?;
+        let mut children = vec![];
+        for _ in 0..modules {
+            let name = decoder.str()?;
+            let parent = decoder.index(modules)?;
+            let mut module_children = vec![];
+            for _ in 0..decoder.usize()? {
+                let tag = decoder.u8()?;
+                module_children.push((tag, decoder.usize()?));
+            }
+            vcd.hierarchy.push(Module {
+                name,
+                parent,
+                ..Module::default()
+            });
+            children.push(module_children);
+        }
+        let signals = decoder.usize()?;
+        for _ in 0..signals {
+            vcd.signals.push(Signal {
+                id: decoder.str()?,
+                name: decoder.str()?,
+                width: decoder.usize()?,
+                parent_index: decoder.index(modules)?,
+                wave: decoder.usize()?,
+            });
+        }
+        for (module, module_children) in children.into_iter().enumerate() {
+            for (tag, index) in module_children {
+                let (node, name) = match tag {
+                    0 if index < modules => {
+                        (Node::Module(index), vcd.hierarchy[index].name.clone())
+                    }
+                    1 if index < signals => (Node::Signal(index), vcd.signals[index].name.clone()),
+                    _ => return Err("Database damaged".into()),
+                };
+                vcd.hierarchy[module].add_child(name, node);
+            }
+        }
+
+        let waves = decoder.usize()?;
+        let mut offset = 28 + header.len() as u64;
+        let mut locations = vec![];
+        for _ in 0..waves {
+            let len = decoder.u64()?;
+            locations.push(WaveLocation {
+                offset,
+                len,
+                checksum: decoder.u64()?,
+            });
+            offset += len;
+        }
+        for (index, signal) in vcd.signals.iter().enumerate() {
+            if signal.wave >= waves {
+                return Err("Database damaged".into());
+            }
+            vcd.signals_by_id.entry(signal.id.clone()).or_insert(index);
+        }
+        vcd.waves = (0..waves).map(|_| OnceCell::new()).collect();
+        vcd.database = Some(Database {
+            file: RefCell::new(file),
+            waves: locations,
+        });
+        Ok(vcd)
+    }
+}
@@ -0,0 +1,678 @@
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+
+use vcd_reader::SignalValue::{self, DOWN, UP, X, Z};
+
+use crate::wave::Wave;
+use crate::{Node, Signal, Value, PATH_SEPARATOR, VCD};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unary {
+    Not,
+    LogicalNot,
+    Negate,
+    AndReduce,
+    OrReduce,
+    XorReduce,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Binary {
+    LogicalOr,
+    LogicalAnd,
+    Or,
+    Xor,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl Binary {
+    fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            Binary::Equal
+                | Binary::NotEqual
+                | Binary::Less
+                | Binary::LessEqual
+                | Binary::Greater
+                | Binary::GreaterEqual
+        )
+    }
+}
+
+/// Binary operators and their precedence, the ones starting with another one first.
+const BINARY: [(&str, Binary, u8); 18] = [
+    ("||", Binary::LogicalOr, 1),
+    ("&&", Binary::LogicalAnd, 2),
+    ("==", Binary::Equal, 6),
+    ("!=", Binary::NotEqual, 6),
+    ("<=", Binary::LessEqual, 7),
+    (">=", Binary::GreaterEqual, 7),
+    ("<<", Binary::ShiftLeft, 8),
+    (">>", Binary::ShiftRight, 8),
+    ("|", Binary::Or, 3),
+    ("^", Binary::Xor, 4),
+    ("&", Binary::And, 5),
+    ("<", Binary::Less, 7),
+    (">", Binary::Greater, 7),
+    ("+", Binary::Add, 9),
+    ("-", Binary::Subtract, 9),
+    ("*", Binary::Multiply, 10),
+    ("/", Binary::Divide, 10),
+    ("%", Binary::Remainder, 10),
+];
+
+const UNARY: [(char, Unary); 6] = [
+    ('~', Unary::Not),
+    ('!', Unary::LogicalNot),
+    ('-', Unary::Negate),
+    ('&', Unary::AndReduce),
+    ('|', Unary::OrReduce),
+    ('^', Unary::XorReduce),
+];
+
+#[derive(Debug)]
+enum Kind {
+    /// Signal used by the expression, by its position among the inputs
+    Input(usize),
+    Literal(Vec<SignalValue>),
+    Unary(Unary, Box<Expr>),
+    Binary(Binary, Box<Expr>, Box<Expr>),
+    /// Bits `msb` down to `lsb`
+    Slice(Box<Expr>, usize, usize),
+    /// Most significant part first
+    Concat(Vec<Expr>),
+}
+
+#[derive(Debug)]
+struct Expr {
+    kind: Kind,
+    width: usize,
+}
+
+impl Expr {
+    fn unary(op: Unary, operand: Expr) -> Self {
+        let width = match op {
+            Unary::Not | Unary::Negate => operand.width,
+            _ => 1,
+        };
+        Expr {
+            kind: Kind::Unary(op, Box::new(operand)),
+            width,
+        }
+    }
+
+    fn binary(op: Binary, left: Expr, right: Expr) -> Self {
+        let width = match op {
+            Binary::Or
+            | Binary::Xor
+            | Binary::And
+            | Binary::Add
+            | Binary::Subtract
+            | Binary::Multiply
+            | Binary::Divide
+            | Binary::Remainder => left.width.max(right.width),
+            Binary::ShiftLeft | Binary::ShiftRight => left.width,
+            _ => 1,
+        };
+        Expr {
+            kind: Kind::Binary(op, Box::new(left), Box::new(right)),
+            width,
+        }
+    }
+
+    /// Bits of the expression, least significant first, with the inputs at `inputs`. As in
+    /// Verilog, arithmetic and bitwise operators work at the `width` of their context, at
+    /// least their own, while the other operators work at their own width.
+    fn evaluate(&self, inputs: &[Vec<SignalValue>], width: usize) -> Vec<SignalValue> {
+        let own = |expr: &Expr| expr.evaluate(inputs, expr.width);
+        let mut bits = match &self.kind {
+            Kind::Input(input) => inputs[*input].clone(),
+            Kind::Literal(bits) => bits.clone(),
+            Kind::Unary(op @ (Unary::Not | Unary::Negate), operand) => {
+                unary(*op, operand.evaluate(inputs, width))
+            }
+            Kind::Unary(op, operand) => unary(*op, own(operand)),
+            Kind::Binary(op @ (Binary::LogicalOr | Binary::LogicalAnd), left, right) => {
+                binary(*op, own(left), own(right), 1)
+            }
+            Kind::Binary(op @ (Binary::ShiftLeft | Binary::ShiftRight), left, right) => {
+                binary(*op, left.evaluate(inputs, width), own(right), width)
+            }
+            Kind::Binary(op, left, right) if op.is_comparison() => {
+                let operands = left.width.max(right.width);
+                binary(
+                    *op,
+                    left.evaluate(inputs, operands),
+                    right.evaluate(inputs, operands),
+                    1,
+                )
+            }
+            Kind::Binary(op, left, right) => binary(
+                *op,
+                left.evaluate(inputs, width),
+                right.evaluate(inputs, width),
+                width,
+            ),
+            Kind::Slice(operand, msb, lsb) => own(operand)[*lsb..=*msb].to_vec(),
+            Kind::Concat(parts) => parts.iter().rev().flat_map(own).collect(),
+        };
+        bits.resize(width, DOWN);
+        bits
+    }
+}
+
+fn and(a: SignalValue, b: SignalValue) -> SignalValue {
+    match (a, b) {
+        (DOWN, _) | (_, DOWN) => DOWN,
+        (UP, UP) => UP,
+        _ => X,
+    }
+}
+
+fn or(a: SignalValue, b: SignalValue) -> SignalValue {
+    match (a, b) {
+        (UP, _) | (_, UP) => UP,
+        (DOWN, DOWN) => DOWN,
+        _ => X,
+    }
+}
+
+fn xor(a: SignalValue, b: SignalValue) -> SignalValue {
+    match (a, b) {
+        (DOWN | UP, DOWN | UP) if a == b => DOWN,
+        (DOWN | UP, DOWN | UP) => UP,
+        _ => X,
+    }
+}
+
+fn not(a: SignalValue) -> SignalValue {
+    match a {
+        DOWN => UP,
+        UP => DOWN,
+        _ => X,
+    }
+}
+
+fn from_bool(condition: bool) -> SignalValue {
+    if condition {
+        UP
+    } else {
+        DOWN
+    }
+}
+
+/// 1 when any bit is 1, 0 when all are 0, x otherwise.
+fn truth(bits: &[SignalValue]) -> SignalValue {
+    bits.iter().fold(DOWN, |truth, bit| or(truth, *bit))
+}
+
+fn number(bits: &[SignalValue]) -> Option<u128> {
+    bits.iter()
+        .enumerate()
+        .try_fold(0, |number, (bit, value)| match value {
+            DOWN => Some(number),
+            UP if bit < 128 => Some(number | 1 << bit),
+            _ => None,
+        })
+}
+
+fn bits_of(number: u128, width: usize) -> Vec<SignalValue> {
+    (0..width)
+        .map(|bit| from_bool(bit < 128 && number >> bit & 1 == 1))
+        .collect()
+}
+
+/// Order of two known values, zero extended to the same width.
+fn compare(a: &[SignalValue], b: &[SignalValue]) -> Option<Ordering> {
+    if a.iter().chain(b).any(|bit| matches!(bit, X | Z)) {
+        return None;
+    }
+    let bit = |bits: &[SignalValue], index: usize| bits.get(index).copied().unwrap_or(DOWN);
+    Some(
+        (0..a.len().max(b.len()))
+            .rev()
+            .map(|index| (bit(a, index) == UP).cmp(&(bit(b, index) == UP)))
+            .find(|order| order.is_ne())
+            .unwrap_or(Ordering::Equal),
+    )
+}
+
+fn unary(op: Unary, bits: Vec<SignalValue>) -> Vec<SignalValue> {
+    match op {
+        Unary::Not => bits.into_iter().map(not).collect(),
+        Unary::LogicalNot => vec![not(truth(&bits))],
+        Unary::Negate => match number(&bits) {
+            Some(number) => bits_of(number.wrapping_neg(), bits.len()),
+            None => vec![X; bits.len()],
+        },
+        Unary::AndReduce => vec![bits.into_iter().fold(UP, and)],
+        Unary::OrReduce => vec![truth(&bits)],
+        Unary::XorReduce => vec![bits.into_iter().fold(DOWN, xor)],
+    }
+}
+
+fn binary(
+    op: Binary,
+    mut left: Vec<SignalValue>,
+    right: Vec<SignalValue>,
+    width: usize,
+) -> Vec<SignalValue> {
+    let bitwise = |operation: fn(SignalValue, SignalValue) -> SignalValue,
+                   mut left: Vec<SignalValue>,
+                   mut right: Vec<SignalValue>| {
+        left.resize(width, DOWN);
+        right.resize(width, DOWN);
+        left.into_iter()
+            .zip(right)
+            .map(|(a, b)| operation(a, b))
+            .collect()
+    };
+    let order = |left: &[SignalValue], right: &[SignalValue], accept: fn(Ordering) -> bool| {
+        vec![compare(left, right).map_or(X, |order| from_bool(accept(order)))]
+    };
+    match op {
+        Binary::LogicalOr => vec![or(truth(&left), truth(&right))],
+        Binary::LogicalAnd => vec![and(truth(&left), truth(&right))],
+        Binary::Or => bitwise(or, left, right),
+        Binary::Xor => bitwise(xor, left, right),
+        Binary::And => bitwise(and, left, right),
+        Binary::Equal => order(&left, &right, Ordering::is_eq),
+        Binary::NotEqual => order(&left, &right, Ordering::is_ne),
+        Binary::Less => order(&left, &right, Ordering::is_lt),
+        Binary::LessEqual => order(&left, &right, Ordering::is_le),
+        Binary::Greater => order(&left, &right, Ordering::is_gt),
+        Binary::GreaterEqual => order(&left, &right, Ordering::is_ge),
+        Binary::ShiftLeft | Binary::ShiftRight => {
+            let Some(shift) = number(&right) else {
+                return vec![X; width];
+            };
+            let shift = shift.min(width as u128) as usize;
+            if op == Binary::ShiftLeft {
+                left.truncate(width - shift);
+                let mut bits = vec![DOWN; shift];
+                bits.append(&mut left);
+                bits
+            } else {
+                left.drain(..shift);
+                left.resize(width, DOWN);
+                left
+            }
+        }
+        _ => {
+            let (Some(a), Some(b)) = (number(&left), number(&right)) else {
+                return vec![X; width];
+            };
+            let result = match op {
+                Binary::Add => Some(a.wrapping_add(b)),
+                Binary::Subtract => Some(a.wrapping_sub(b)),
+                Binary::Multiply => Some(a.wrapping_mul(b)),
+                Binary::Divide => a.checked_div(b),
+                _ => a.checked_rem(b),
+            };
+            result.map_or(vec![X; width], |result| bits_of(result, width))
+        }
+    }
+}
+
+/// Value of a literal: a decimal number, `0x` or `0b` digits, or a Verilog literal like
+/// `8'hff` or `4'b10xz`. Unsized decimal numbers take the bits they need.
+fn literal(text: &str) -> Option<Vec<SignalValue>> {
+    let text = text.replace('_', "").to_ascii_lowercase();
+    let (size, radix, digits): (Option<usize>, u32, String) = match text.split_once('\'') {
+        Some((size, based)) => {
+            let size = match size {
+                "" => None,
+                size => Some(size.parse().ok().filter(|size| *size > 0)?),
+            };
+            let radix = match based.get(..1)? {
+                "b" => 2,
+                "o" => 8,
+                "h" => 16,
+                "d" => 10,
+                _ => return None,
+            };
+            (size, radix, based[1..].to_string())
+        }
+        None => match (text.strip_prefix("0x"), text.strip_prefix("0b")) {
+            (Some(digits), _) => (None, 16, digits.to_string()),
+            (_, Some(digits)) => (None, 2, digits.to_string()),
+            _ => (None, 10, text),
+        },
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    let mut bits = match radix {
+        10 => {
+            let number: u128 = digits.parse().ok()?;
+            bits_of(number, (128 - number.leading_zeros() as usize).max(1))
+        }
+        _ => {
+            let digit_bits = radix.trailing_zeros() as usize;
+            let mut bits = vec![];
+            for digit in digits.chars().rev() {
+                match digit {
+                    'x' => bits.extend(vec![X; digit_bits]),
+                    'z' | '?' => bits.extend(vec![Z; digit_bits]),
+                    _ => bits.extend(bits_of(digit.to_digit(radix)? as u128, digit_bits)),
+                }
+            }
+            bits
+        }
+    };
+    if let Some(size) = size {
+        // Extended like the values of a dump
+        let fill = match bits.last() {
+            Some(bit @ (X | Z)) => *bit,
+            _ => DOWN,
+        };
+        bits.resize(size, fill);
+    }
+    Some(bits)
+}
+
+struct Parser<'a> {
+    vcd: &'a VCD,
+    text: &'a str,
+    position: usize,
+    /// Signals used, in order of appearance
+    inputs: Vec<usize>,
+}
+
+impl Parser<'_> {
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        Err(format!("{} at column {}", message, self.position + 1))
+    }
+
+    fn rest(&self) -> &str {
+        &self.text[self.position..]
+    }
+
+    fn skip_spaces(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skips `token` if next.
+    fn accept(&mut self, token: &str) -> bool {
+        self.skip_spaces();
+        let found = self.rest().starts_with(token);
+        if found {
+            self.position += token.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        match self.accept(token) {
+            true => Ok(()),
+            false => self.error(&format!("Expected {}", token)),
+        }
+    }
+
+    /// Characters from the current position while `accept` holds.
+    fn take_while(&mut self, accept: impl Fn(char) -> bool) -> &str {
+        let start = self.position;
+        let len = self
+            .rest()
+            .find(|c: char| !accept(c))
+            .unwrap_or(self.rest().len());
+        self.position += len;
+        &self.text[start..self.position]
+    }
+
+    fn expression(&mut self, precedence: u8) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        loop {
+            self.skip_spaces();
+            let Some((token, op, op_precedence)) = BINARY
+                .iter()
+                .find(|(token, _, _)| self.rest().starts_with(token))
+                .copied()
+            else {
+                return Ok(left);
+            };
+            if op_precedence < precedence {
+                return Ok(left);
+            }
+            self.position += token.len();
+            let right = self.expression(op_precedence + 1)?;
+            left = Expr::binary(op, left, right);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.skip_spaces();
+        match UNARY
+            .iter()
+            .find(|(token, _)| self.rest().starts_with(*token))
+        {
+            Some((token, op)) => {
+                self.position += token.len_utf8();
+                Ok(Expr::unary(*op, self.unary()?))
+            }
+            None => self.slices(),
+        }
+    }
+
+    /// A value followed by any number of `[msb:lsb]` or `[bit]`.
+    fn slices(&mut self) -> Result<Expr, String> {
+        let mut value = self.value()?;
+        while self.accept("[") {
+            let msb = self.index()?;
+            let lsb = match self.accept(":") {
+                true => self.index()?,
+                false => msb,
+            };
+            if lsb > msb || msb >= value.width {
+                return self.error(&format!(
+                    "Bits [{}:{}] of a {} bit value",
+                    msb, lsb, value.width
+                ));
+            }
+            self.expect("]")?;
+            value = Expr {
+                kind: Kind::Slice(Box::new(value), msb, lsb),
+                width: msb - lsb + 1,
+            };
+        }
+        Ok(value)
+    }
+
+    fn index(&mut self) -> Result<usize, String> {
+        self.skip_spaces();
+        match self.take_while(|c| c.is_ascii_digit()).parse() {
+            Ok(index) => Ok(index),
+            Err(_) => self.error("Expected a bit number"),
+        }
+    }
+
+    fn value(&mut self) -> Result<Expr, String> {
+        self.skip_spaces();
+        let start = self.position;
+        match self.rest().chars().next() {
+            Some('(') => {
+                self.position += 1;
+                let value = self.expression(0)?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            Some('{') => {
+                self.position += 1;
+                let mut parts = vec![self.expression(0)?];
+                while self.accept(",") {
+                    parts.push(self.expression(0)?);
+                }
+                self.expect("}")?;
+                Ok(Expr {
+                    width: parts.iter().map(|part| part.width).sum(),
+                    kind: Kind::Concat(parts),
+                })
+            }
+            Some(c) if c.is_ascii_digit() || c == '\'' => {
+                let text = self.take_while(|c| c.is_ascii_alphanumeric() || "_'?".contains(c));
+                match literal(text) {
+                    Some(bits) => Ok(Expr {
+                        width: bits.len(),
+                        kind: Kind::Literal(bits),
+                    }),
+                    None => {
+                        let message = format!("Invalid number {}", text);
+                        self.position = start;
+                        self.error(&message)
+                    }
+                }
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => self.signal(),
+            _ => self.error("Expected a value"),
+        }
+    }
+
+    fn signal(&mut self) -> Result<Expr, String> {
+        let start = self.position;
+        let path = self
+            .take_while(|c| c.is_alphanumeric() || "_$".contains(c) || c == PATH_SEPARATOR)
+            .to_string();
+        // Names like `count[1]` are signals of their own rather than slices
+        let bracketed = self
+            .rest()
+            .strip_prefix('[')
+            .and_then(|rest| rest.find(']'))
+            .map(|end| format!("{}{}", path, &self.rest()[..end + 2]));
+        let node = match bracketed
+            .as_deref()
+            .and_then(|path| self.vcd.find_by_path(path))
+        {
+            Some(node) => {
+                self.position += bracketed.unwrap().len() - path.len();
+                node
+            }
+            None => match self.vcd.find_by_path(&path) {
+                Some(node) => node,
+                None => {
+                    self.position = start;
+                    return self.error(&format!("Unknown signal {}", path));
+                }
+            },
+        };
+        let Node::Signal(signal) = node else {
+            self.position = start;
+            return self.error(&format!("{} is a scope", path));
+        };
+        let input = match self.inputs.iter().position(|input| *input == signal) {
+            Some(input) => input,
+            None => {
+                self.inputs.push(signal);
+                self.inputs.len() - 1
+            }
+        };
+        Ok(Expr {
+            kind: Kind::Input(input),
+            width: self.vcd.signals[signal].width,
+        })
+    }
+}
+
+impl VCD {
+    /// Adds the signal computed by `expression` from the signals of the dump, as `name`
+    /// in the root scope, and returns its index for the queries.
+    ///
+    /// Signals are named by their path, e.g. `top.cpu.valid && top.cpu.ready`. The
+    /// operators are the ones of Verilog with the same precedence: `~ ! - & | ^` before a
+    /// value, then `* / %`, `+ -`, `<< >>`, `< <= > >=`, `== !=`, `&`, `^`, `|`, `&&` and
+    /// `||`, along with `value[msb:lsb]`, `value[bit]` and `{high, low}`. Numbers are written
+    /// `42`, `0xdead`, `0b1010` or `8'hff`, `4'b10xz`. Bits are 0, 1, x or z as in Verilog:
+    /// `0 & x` is 0 while arithmetic and comparisons give x for any x or z bit. Results are
+    /// as wide as their widest operand, 1 bit for comparisons and logical operators, and
+    /// arithmetic covers up to 128 bits.
+    ///
+    /// The signal changes when the expression does, at the changes of the signals it uses,
+    /// which are loaded or extracted first. Its id is the expression, and it is not in
+    /// [`VCD::signals_by_id`].
+    pub fn add_derived(&mut self, name: &str, expression: &str) -> Result<usize, String> {
+        if name.is_empty() || name.contains(PATH_SEPARATOR) {
+            return Err(format!("Invalid signal name {}", name));
+        }
+        if self.hierarchy[0].child(name).is_some() {
+            return Err(format!("{} already exists", name));
+        }
+        let mut parser = Parser {
+            vcd: self,
+            text: expression,
+            position: 0,
+            inputs: vec![],
+        };
+        let expr = parser.expression(0)?;
+        parser.skip_spaces();
+        if parser.position < expression.len() {
+            return parser.error("Unexpected text");
+        }
+        let inputs = parser.inputs;
+        if inputs.is_empty() {
+            return Err(format!("{} uses no signal", expression));
+        }
+        self.wait_for_signals(&inputs)?;
+        let wave = self.derive(&expr, &inputs)?;
+
+        let index = self.signals.len();
+        self.waves.push(OnceCell::from(wave));
+        self.signals.push(Signal {
+            id: expression.into(),
+            name: name.into(),
+            width: expr.width,
+            parent_index: 0,
+            wave: self.waves.len() - 1,
+        });
+        self.hierarchy[0].add_child(name.into(), Node::Signal(index));
+        Ok(index)
+    }
+
+    /// Evaluates `expr` at every change of its inputs.
+    fn derive(&self, expr: &Expr, inputs: &[usize]) -> Result<Wave, String> {
+        let waves = inputs
+            .iter()
+            .map(|signal| self.load_wave(self.signals[*signal].wave))
+            .collect::<Result<Vec<_>, String>>()?;
+        let mut cursors: Vec<_> = waves
+            .iter()
+            .map(|wave| wave.times_from(0).peekable())
+            .collect();
+        // Unknown until their first change
+        let mut values: Vec<Vec<SignalValue>> =
+            waves.iter().map(|wave| vec![X; wave.width()]).collect();
+        let mut derived = Wave::new(expr.width);
+        let mut last = None;
+        while let Some(time) = cursors
+            .iter_mut()
+            .filter_map(|cursor| cursor.peek().map(|(_, time)| *time))
+            .min()
+        {
+            for (input, cursor) in cursors.iter_mut().enumerate() {
+                while let Some((index, _)) = cursor.next_if(|(_, change)| *change == time) {
+                    let value = waves[input].value(index);
+                    values[input] = (0..value.width()).map(|bit| value.bit(bit)).collect();
+                }
+            }
+            let value = Value::from_bits(&expr.evaluate(&values, expr.width));
+            if last.as_ref() != Some(&value) {
+                derived.push(time, &value)?;
+                last = Some(value);
+            }
+        }
+        derived.shrink_to_fit();
+        Ok(derived)
+    }
+}
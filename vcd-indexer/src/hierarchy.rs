@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use regex::Regex;
+
+use crate::{Module, Node, VCD};
+
+/// Separator of the scopes in hierarchical names, e.g. `top.cpu.alu.result`.
+pub const PATH_SEPARATOR: char = '.';
+
+impl Module {
+    /// The scope or signal called `name` in this scope.
+    pub fn child(&self, name: &str) -> Option<Node> {
+        self.children_by_name
+            .get(name)
+            .map(|index| self.children[*index])
+    }
+
+    pub(crate) fn add_child(&mut self, name: Arc<str>, node: Node) {
+        self.children_by_name.insert(name, self.children.len());
+        self.children.push(node);
+    }
+}
+
+impl VCD {
+    /// The scope or signal at `path`, e.g. `top.cpu.alu.result` or `top.cnt.count[1]`.
+    pub fn find_by_path(&self, path: &str) -> Option<Node> {
+        path.split(PATH_SEPARATOR)
+            .try_fold(Node::Module(0), |node, name| match node {
+                Node::Module(module) => self.hierarchy[module].child(name),
+                Node::Signal(_) => None,
+            })
+            .filter(|node| *node != Node::Module(0))
+    }
+
+    /// Hierarchical name of a scope or signal.
+    pub fn path(&self, node: Node) -> String {
+        let (mut names, mut module) = match node {
+            Node::Module(module) => (vec![], module),
+            Node::Signal(signal) => (
+                vec![&*self.signals[signal].name],
+                self.signals[signal].parent_index,
+            ),
+        };
+        while module != 0 {
+            names.push(&self.hierarchy[module].name);
+            module = self.hierarchy[module].parent;
+        }
+        names.reverse();
+        names.join(&PATH_SEPARATOR.to_string())
+    }
+
+    /// Signals, in declaration order, whose hierarchical name matches `pattern`: `*` matches
+    /// any text and `?` any character, everything else matches itself.
+    pub fn search_glob(&self, pattern: &str) -> Vec<usize> {
+        let pattern: Vec<char> = pattern.chars().collect();
+        self.search(|path| glob_match(&pattern, &path.chars().collect::<Vec<_>>()))
+    }
+
+    /// Signals, in declaration order, whose hierarchical name matches the regular expression
+    /// `pattern` anywhere.
+    pub fn search_regex(&self, pattern: &str) -> Result<Vec<usize>, String> {
+        let regex = Regex::new(pattern).map_err(|err| err.to_string())?;
+        Ok(self.search(|path| regex.is_match(path)))
+    }
+
+    fn search(&self, matches: impl Fn(&str) -> bool) -> Vec<usize> {
+        (0..self.signals.len())
+            .filter(|signal| matches(&self.path(Node::Signal(*signal))))
+            .collect()
+    }
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    // Position after the last `*` and the text it is matched up to, to backtrack
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((after, matched)) => {
+                    p = after;
+                    t = matched + 1;
+                    star = Some((after, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
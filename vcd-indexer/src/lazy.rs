@@ -0,0 +1,330 @@
+use std::cell::{OnceCell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::OnceLock;
+use std::thread;
+
+use vcd_reader::{parse_timestamp, VCDFile, VcdVisitor};
+
+use crate::database::Source;
+use crate::wave::Wave;
+use crate::{Configuration, Indexer, Value, VCD};
+
+/// Bytes of value changes between checkpoints.
+pub const CHECKPOINT_BYTES: u64 = 1 << 20;
+
+/// Parts of the dump extracted by each thread, so that a thread done early takes another.
+const PARTS_PER_THREAD: usize = 4;
+
+/// Start of a line of the value changes, where reading can resume. The first checkpoint is
+/// the start of the value changes, the others timestamps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Time of the changes after the checkpoint, -1 before the first timestamp
+    pub time: i64,
+    pub offset: u64,
+    /// Lines before the checkpoint
+    pub line: usize,
+}
+
+/// Changes of a signal to extract.
+struct Request {
+    wave: usize,
+    id: String,
+    width: usize,
+}
+
+/// Waves extracted together, or why they could not be.
+type Batch = (Vec<usize>, Result<Vec<Wave>, String>);
+
+/// Background thread extracting the changes of the signals requested from the dump.
+#[derive(Debug)]
+pub(crate) struct Extractor {
+    requests: Sender<Vec<Request>>,
+    batches: Receiver<Batch>,
+    /// Waves requested and not received yet
+    pending: RefCell<HashSet<usize>>,
+}
+
+/// Indexes the scopes and signals of the dump and the times of its value changes, leaving
+/// the changes themselves on disk. The changes of a signal are then extracted by a background
+/// thread when requested with [`VCD::request_signals`] or [`VCD::wait_for_signals`], so that
+/// opening even a huge dump takes little more than reading through it once. The parts of
+/// the dump between [`VCD::checkpoints`] are extracted in parallel.
+pub fn index_lazy(configuration: Configuration) -> Result<VCD, String> {
+    let reader_config = vcd_reader::Configuration {
+        in_file: &configuration.in_file,
+        separator: configuration.separator,
+    };
+    let mut file = VCDFile::new(reader_config)?;
+    let mut indexer = Indexer {
+        definitions_only: true,
+        ..Indexer::default()
+    };
+    indexer.vcd.source = Source::of(&configuration.in_file)?;
+    indexer.vcd.separator = configuration.separator;
+    file.visit(&mut indexer)?;
+
+    let mut vcd = indexer.vcd;
+    let start = Checkpoint {
+        time: -1,
+        offset: file.bytes_read(),
+        line: file.line_number(),
+    };
+    vcd.checkpoints = scan_timestamps(&configuration.in_file, start)?;
+    vcd.waves = vcd.waves.iter().map(|_| OnceCell::new()).collect();
+
+    let (requests, requested) = mpsc::channel::<Vec<Request>>();
+    let (extracted, batches) = mpsc::channel();
+    let checkpoints = vcd.checkpoints.clone();
+    thread::spawn(move || {
+        while let Ok(mut batch) = requested.recv() {
+            // Requests made meanwhile are served by the same reading
+            batch.extend(requested.try_iter().flatten());
+            let waves = batch.iter().map(|request| request.wave).collect();
+            let result = extract(&configuration, &checkpoints, &batch);
+            if extracted.send((waves, result)).is_err() {
+                break;
+            }
+        }
+    });
+    vcd.extractor = Some(Extractor {
+        requests,
+        batches,
+        pending: RefCell::default(),
+    });
+    Ok(vcd)
+}
+
+/// Checkpoints of the value changes from `start`, one at the first timestamp after every
+/// [`CHECKPOINT_BYTES`] bytes.
+fn scan_timestamps(in_file: &str, start: Checkpoint) -> Result<Vec<Checkpoint>, String> {
+    let mut file = File::open(in_file).map_err(|err| err.to_string())?;
+    file.seek(SeekFrom::Start(start.offset))
+        .map_err(|err| err.to_string())?;
+    let mut reader = BufReader::new(file);
+    let mut checkpoints = vec![start];
+    let mut line = vec![];
+    let (mut offset, mut lines) = (start.offset, start.line);
+    let mut next = start.offset + CHECKPOINT_BYTES;
+    loop {
+        line.clear();
+        let read = reader
+            .read_until(b'\n', &mut line)
+            .map_err(|err| err.to_string())?;
+        if read == 0 {
+            return Ok(checkpoints);
+        }
+        if let Some(time) = line.trim_ascii().strip_prefix(b"#") {
+            let time = parse_timestamp(&String::from_utf8_lossy(time))
+                .map_err(|err| format!("Line {}: {}", lines + 1, err))?;
+            if offset >= next {
+                checkpoints.push(Checkpoint {
+                    time: time as i64,
+                    offset,
+                    line: lines,
+                });
+                next = offset + CHECKPOINT_BYTES;
+            }
+        }
+        offset += read as u64;
+        lines += 1;
+    }
+}
+
+/// Reads the changes of the signals of `batch`, splitting the dump at the checkpoints into
+/// a few parts per core, read by a thread per core, then joining the parts in order.
+fn extract(
+    configuration: &Configuration,
+    checkpoints: &[Checkpoint],
+    batch: &[Request],
+) -> Result<Vec<Wave>, String> {
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let size = checkpoints.len().div_ceil(threads * PARTS_PER_THREAD);
+    let starts: Vec<usize> = (0..checkpoints.len()).step_by(size).collect();
+    let parts: Vec<OnceLock<Result<Vec<Wave>, String>>> =
+        starts.iter().map(|_| OnceLock::new()).collect();
+    let next = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for _ in 0..threads.min(starts.len()) {
+            scope.spawn(|| loop {
+                let part = next.fetch_add(1, Ordering::Relaxed);
+                let Some(first) = starts.get(part) else {
+                    break;
+                };
+                let end = starts.get(part + 1).map(|next| checkpoints[*next].offset);
+                let waves = extract_part(configuration, checkpoints[*first], end, batch);
+                let _ = parts[part].set(waves);
+            });
+        }
+    });
+    let mut parts = parts
+        .into_iter()
+        .map(|part| part.into_inner().expect("Every part is read"));
+    let mut waves = parts.next().expect("There is a checkpoint at the start")?;
+    for part in parts {
+        for ((wave, part), request) in waves.iter_mut().zip(part?).zip(batch) {
+            wave.append(&part)
+                .map_err(|err| format!("{} for signal {}", err, request.id))?;
+        }
+    }
+    waves.iter_mut().for_each(Wave::shrink_to_fit);
+    Ok(waves)
+}
+
+/// Reads the changes of the signals of `batch` from `start` to the `end` offset, or to the
+/// end of the dump.
+fn extract_part(
+    configuration: &Configuration,
+    start: Checkpoint,
+    end: Option<u64>,
+    batch: &[Request],
+) -> Result<Vec<Wave>, String> {
+    let reader_config = vcd_reader::Configuration {
+        in_file: &configuration.in_file,
+        separator: configuration.separator,
+    };
+    let mut file = VCDFile::new(reader_config)?;
+    file.seek_values(start.offset, start.line)?;
+    let mut extraction = Extraction {
+        slots: HashMap::new(),
+        waves: batch
+            .iter()
+            .map(|request| Wave::new(request.width))
+            .collect(),
+        current_timestamp: start.time,
+        end: end.unwrap_or(u64::MAX),
+    };
+    for (slot, request) in batch.iter().enumerate() {
+        extraction.slots.insert(request.id.as_str(), slot);
+    }
+    file.visit(&mut extraction)?;
+    Ok(extraction.waves)
+}
+
+/// Records the changes of some signals, skipping the others.
+struct Extraction<'a> {
+    slots: HashMap<&'a str, usize>,
+    waves: Vec<Wave>,
+    current_timestamp: i64,
+    /// Offset where the next part starts
+    end: u64,
+}
+
+impl VcdVisitor for Extraction<'_> {
+    fn on_progress(&mut self, read: u64, _total: u64) -> bool {
+        read < self.end
+    }
+
+    fn on_timestamp(&mut self, time: usize) -> Result<(), String> {
+        self.current_timestamp = time as i64;
+        Ok(())
+    }
+
+    fn on_change(&mut self, signal_id: &str, values: &[u8]) -> Result<(), String> {
+        let Some(slot) = self.slots.get(signal_id) else {
+            return Ok(());
+        };
+        let wave = &mut self.waves[*slot];
+        let value = Value::from_digits(values, wave.width())
+            .map_err(|err| format!("{} of signal {}", err, signal_id))?;
+        wave.push(self.current_timestamp, &value)
+            .map_err(|err| format!("{} for signal {}", err, signal_id))
+    }
+}
+
+impl VCD {
+    /// Where reading the value changes can resume, for an index made by [`index_lazy`].
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    /// True when the changes of `signal` can be queried.
+    pub fn is_loaded(&self, signal: usize) -> bool {
+        self.signals
+            .get(signal)
+            .is_some_and(|signal| self.waves[signal.wave].get().is_some())
+    }
+
+    /// Asks for the changes of `signals` to be extracted in the background, see
+    /// [`VCD::receive_signals`]. Does nothing for signals loaded or already requested.
+    pub fn request_signals(&self, signals: &[usize]) {
+        let Some(extractor) = &self.extractor else {
+            return;
+        };
+        let mut pending = extractor.pending.borrow_mut();
+        let batch: Vec<Request> = signals
+            .iter()
+            .filter_map(|signal| self.signals.get(*signal))
+            .filter(|signal| self.waves[signal.wave].get().is_none())
+            .filter(|signal| pending.insert(signal.wave))
+            .map(|signal| Request {
+                wave: signal.wave,
+                id: signal.id.to_string(),
+                width: signal.width,
+            })
+            .collect();
+        if !batch.is_empty() {
+            let _ = extractor.requests.send(batch);
+        }
+    }
+
+    /// Signals extracted since the last call, without waiting. The first extraction error is
+    /// returned once, after which its signals can be requested again.
+    pub fn receive_signals(&self) -> Result<Vec<usize>, String> {
+        let Some(extractor) = &self.extractor else {
+            return Ok(vec![]);
+        };
+        let mut signals = vec![];
+        let mut error = None;
+        for batch in extractor.batches.try_iter() {
+            match self.store(batch) {
+                Ok(mut received) => signals.append(&mut received),
+                Err(err) => {
+                    error.get_or_insert(err);
+                }
+            }
+        }
+        error.map_or(Ok(signals), Err)
+    }
+
+    /// Extracts the changes of `signals` if not loaded yet, waiting for them.
+    pub fn wait_for_signals(&self, signals: &[usize]) -> Result<(), String> {
+        self.request_signals(signals);
+        let Some(extractor) = &self.extractor else {
+            return Ok(());
+        };
+        while !signals
+            .iter()
+            .filter(|signal| **signal < self.signals.len())
+            .all(|signal| self.is_loaded(*signal))
+        {
+            let batch = extractor
+                .batches
+                .recv()
+                .map_err(|_| "Extraction stopped".to_string())?;
+            self.store(batch)?;
+        }
+        Ok(())
+    }
+
+    /// Makes the waves of a batch available, returning the signals using them.
+    fn store(&self, (waves, result): Batch) -> Result<Vec<usize>, String> {
+        if let Some(extractor) = &self.extractor {
+            let mut pending = extractor.pending.borrow_mut();
+            waves.iter().for_each(|wave| {
+                pending.remove(wave);
+            });
+        }
+        for (index, wave) in waves.iter().zip(result?) {
+            let _ = self.waves[*index].set(wave);
+        }
+        let waves: HashSet<usize> = waves.into_iter().collect();
+        Ok((0..self.signals.len())
+            .filter(|signal| waves.contains(&self.signals[*signal].wave))
+            .collect())
+    }
+}
@@ -1,39 +1,102 @@
-use std::rc::Rc;
+use std::cell::OnceCell;
+use std::sync::Arc;
 use std::thread::JoinHandle;
-use std::{
-    collections::HashMap,
-    sync::mpsc::{self, Receiver},
-    thread,
-};
-use vcd_reader::{Change, LineInfo, SignalValue, VCDFile};
+use std::{collections::HashMap, thread};
+use vcd_reader::{VCDFile, VcdVisitor};
+
+mod database;
+mod expr;
+mod hierarchy;
+mod lazy;
+mod live;
+mod lod;
+mod query;
+mod value;
+mod wave;
+
+pub use database::{database_path, DATABASE_VERSION};
+use database::{Database, Source};
+pub use hierarchy::PATH_SEPARATOR;
+use lazy::Extractor;
+pub use lazy::{index_lazy, Checkpoint, CHECKPOINT_BYTES};
+pub use live::{index_live, LiveIndex};
+pub use lod::Summary;
+pub use query::Edge;
+pub use value::Value;
+use wave::Wave;
+pub use wave::{MemoryUsage, BLOCK_CHANGES};
 
 pub struct Configuration {
     pub in_file: String,
     pub separator: char,
 }
 
-unsafe impl Send for VCD {}
-
+/// Indexes the dump on a new thread, so that the caller can keep running meanwhile.
 pub fn start_indexing(configuration: Configuration) -> JoinHandle<Result<VCD, String>> {
-    let (tx, rx) = mpsc::sync_channel(1000000);
-    let th = thread::spawn(move || translate_infos(rx));
+    thread::spawn(move || index(configuration))
+}
+
+/// Indexes the whole dump, stopping at the first error.
+pub fn index(configuration: Configuration) -> Result<VCD, String> {
     let reader_config = vcd_reader::Configuration {
         in_file: &configuration.in_file,
         separator: configuration.separator,
     };
-    VCDFile::new(reader_config).for_each(|info| {
-        tx.send(info).unwrap();
-    });
-    drop(tx);
-    th
+    let mut file = VCDFile::new(reader_config)?;
+    let mut indexer = Indexer::default();
+    // Taken before reading, so that a dump written meanwhile is indexed again
+    indexer.vcd.source = Source::of(&configuration.in_file)?;
+    indexer.vcd.separator = configuration.separator;
+    file.visit(&mut indexer)?;
+    indexer
+        .vcd
+        .waves
+        .iter_mut()
+        .filter_map(OnceCell::get_mut)
+        .for_each(Wave::shrink_to_fit);
+    Ok(indexer.vcd)
 }
 
-#[derive(Debug, Default)]
-struct InfoTranslator {
+/// Opens the database next to the dump, see [`database_path`]. The dump is indexed again
+/// when the database is missing, from another version or older than the dump, and the new
+/// index saved for the next time. An index that cannot be saved is still returned.
+pub fn open(configuration: Configuration) -> Result<VCD, String> {
+    let database = database_path(&configuration.in_file);
+    if let Ok(vcd) = VCD::load(&database, &configuration) {
+        return Ok(vcd);
+    }
+    let vcd = index(configuration)?;
+    let _ = vcd.save(&database);
+    Ok(vcd)
+}
+
+/// Builds the index from the lines of the dump.
+struct Indexer {
+    vcd: VCD,
     current_module_index: usize,
+    current_timestamp: i64,
+    /// Stop at the end of the definitions
+    definitions_only: bool,
+    definitions_done: bool,
 }
 
-#[derive(Debug)]
+impl Default for Indexer {
+    fn default() -> Self {
+        Indexer {
+            vcd: VCD {
+                // Root of the hierarchy, holding the top level scopes
+                hierarchy: vec![Module::default()],
+                ..Default::default()
+            },
+            current_module_index: 0,
+            current_timestamp: -1,
+            definitions_only: false,
+            definitions_done: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Node {
     Module(usize),
     Signal(usize),
@@ -41,178 +104,200 @@ pub enum Node {
 
 #[derive(Debug, Default)]
 pub struct Module {
+    pub name: Arc<str>,
     pub parent: usize,
-    pub children: HashMap<Rc<str>, Node>,
+    /// Scopes and signals in declaration order
+    pub children: Vec<Node>,
+    children_by_name: HashMap<Arc<str>, usize>,
 }
 
+/// A variable of the dump. Variables declared with the same id code share their changes.
 #[derive(Debug)]
 pub struct Signal {
-    pub id: Rc<str>,
-    pub sub_id: u16,
-    pub name: Rc<str>,
+    pub id: Arc<str>,
+    /// Name in its scope
+    pub name: Arc<str>,
+    pub width: usize,
     pub parent_index: usize,
-    pub states: Vec<State>,
+    wave: usize,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct State {
-    pub value: SignalValue,
+    pub value: Value,
     pub time: i64,
 }
 
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Default, Debug)]
 pub struct VCD {
+    /// Scopes of the dump. The first one is the root, parent of the top level scopes.
     pub hierarchy: Vec<Module>,
     pub signals: Vec<Signal>,
-    pub signals_by_id: HashMap<Rc<str>, usize>,
+    /// First signal declared with each id code
+    pub signals_by_id: HashMap<Arc<str>, usize>,
+    /// Changes of the signals, read from the database on first use when loaded from one
+    waves: Vec<OnceCell<Wave>>,
+    source: Source,
+    separator: char,
+    database: Option<Database>,
+    checkpoints: Vec<Checkpoint>,
+    extractor: Option<Extractor>,
+    pub date: Option<String>,
+    pub version: Option<String>,
+    pub timescale: Option<String>,
 }
 
-impl Default for State {
-    fn default() -> Self {
-        State {
-            value: Default::default(),
-            time: -1,
-        }
+impl VCD {
+    fn push(&mut self, signal: vcd_reader::Signal, parent_index: usize) -> Result<(), String> {
+        let (id, name): (Arc<str>, Arc<str>) = (Arc::from(&*signal.id), Arc::from(&*signal.name));
+        let wave = match self.signals_by_id.get(&id) {
+            Some(alias) if self.signals[*alias].width != signal.num_values => {
+                return Err(format!(
+                    "Signal {} declared with {} bits and {} bits",
+                    signal.id, self.signals[*alias].width, signal.num_values
+                ))
+            }
+            Some(alias) => self.signals[*alias].wave,
+            None => {
+                self.waves
+                    .push(OnceCell::from(Wave::new(signal.num_values)));
+                self.signals_by_id.insert(id.clone(), self.signals.len());
+                self.waves.len() - 1
+            }
+        };
+        let index = self.signals.len();
+        self.signals.push(Signal {
+            id,
+            name: name.clone(),
+            width: signal.num_values,
+            parent_index,
+            wave,
+        });
+        self.hierarchy[parent_index].add_child(name, Node::Signal(index));
+        Ok(())
     }
-}
 
-impl Signal {
-    fn add_change(&mut self, s: State) {
-        self.states.push(s);
+    fn add_change(&mut self, signal_id: &str, values: &[u8], time: i64) -> Result<(), String> {
+        let signal = self
+            .signals_by_id
+            .get(signal_id)
+            .ok_or_else(|| format!("Unknown signal id {}", signal_id))?;
+        let wave = self.waves[self.signals[*signal].wave]
+            .get_mut()
+            .expect("Waves are loaded while indexing");
+        let value = Value::from_digits(values, wave.width())
+            .map_err(|err| format!("{} of signal {}", err, signal_id))?;
+        wave.push(time, &value)
+            .map_err(|err| format!("{} for signal {}", err, signal_id))
     }
-}
 
-impl VCD {
-    fn push(&mut self, signal: vcd_reader::Signal, translator: &InfoTranslator) {
-        for sub_id in 0..signal.num_values {
-            let mut name = String::default();
-            if signal.num_values > 1 {
-                name = String::from(&*name) + &format!("[{}]", sub_id);
-            }
-            let s = Signal {
-                id: String::from(&*signal.id).into(),
-                sub_id: sub_id.try_into().unwrap(),
-                name: name.into(),
-                states: Default::default(),
-                parent_index: translator.current_module_index,
-            };
-            let index = self.signals.len();
-            self.signals.push(s);
-            let id = self.signals.last().unwrap().id.clone();
-            let name = self.signals.last().unwrap().id.clone();
-            if sub_id == 0 {
-                self.signals_by_id.insert(id.clone(), index);
-            }
-            self.hierarchy[translator.current_module_index]
-                .children
-                .insert(name, Node::Signal(index));
+    /// Memory taken by the changes of every loaded signal.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        self.waves
+            .iter()
+            .filter_map(OnceCell::get)
+            .for_each(|wave| usage += wave.memory_usage());
+        usage
+    }
+
+    /// Memory taken by the changes of `signal`, shared with its aliases.
+    pub fn signal_memory_usage(&self, signal: usize) -> Option<MemoryUsage> {
+        self.wave(signal).ok().map(Wave::memory_usage)
+    }
+
+    /// Reads the changes of `signal` from the database if not done yet. Queries load the
+    /// signals they use by themselves, treating a signal that fails to load as unknown; their
+    /// `try_` variants return the error instead.
+    pub fn load_signal(&self, signal: usize) -> Result<(), String> {
+        self.wave(signal).map(|_| ())
+    }
+
+    pub(crate) fn load_wave(&self, index: usize) -> Result<&Wave, String> {
+        let cell = &self.waves[index];
+        if let Some(wave) = cell.get() {
+            return Ok(wave);
         }
+        let database = self.database.as_ref().ok_or("Signal not loaded")?;
+        let wave = database.load_wave(index)?;
+        Ok(cell.get_or_init(|| wave))
     }
 
-    fn add_change(&mut self, change: Change, time: i64) {
-        let signal_index = self.signals_by_id.get(&*change.signal_id).unwrap();
-        change
-            .values
-            .into_iter()
-            .enumerate()
-            .for_each(|(sub_id, state)| {
-                self.signals[signal_index + sub_id].add_change(State {
-                    value: SignalValue::from(state),
-                    time,
-                })
-            })
+    fn wave(&self, signal: usize) -> Result<&Wave, String> {
+        let signal = self
+            .signals
+            .get(signal)
+            .ok_or_else(|| format!("Unknown signal {}", signal))?;
+        self.load_wave(signal.wave)
     }
 }
 
-fn translate_changes(vcd: &mut VCD, infos: Receiver<LineInfo>) -> Result<(), String> {
-    let mut current_timestamp: i64 = -1;
-    for info in infos.into_iter() {
-        match info {
-            LineInfo::Signal(_) => unreachable!("Error: Signal declaration in initialization"),
-            LineInfo::DateInfo(_) => unreachable!("Error: Date info not expected here"),
-            LineInfo::VersionInfo(_) => unreachable!("Error: Version info not expected here"),
-            LineInfo::TimeScaleInfo(_) => {
-                unreachable!("Error: Time scale info not expected here")
-            }
-            LineInfo::InScope(_) => unreachable!("Error: Scope definitions not expected here"),
-            LineInfo::UpScope => unreachable!("Error: Upscope not expected here"),
-            LineInfo::EndDefinitions => {
-                unreachable!("Error: Definitions should have already ended")
-            }
-            LineInfo::EndInitializations => {}
-            LineInfo::Useless => {}
-            LineInfo::Dumpports => {}
-            LineInfo::ParsingError(s) => {
-                return Err(format!("ERROR: found unrecognized symbol: {}", s))
-            }
+impl VcdVisitor for Indexer {
+    fn on_date(&mut self, date: &str) -> Result<(), String> {
+        self.vcd.date = Some(date.into());
+        Ok(())
+    }
+
+    fn on_version(&mut self, version: &str) -> Result<(), String> {
+        self.vcd.version = Some(version.into());
+        Ok(())
+    }
 
-            LineInfo::Timestamp(t) => current_timestamp = t as i64,
-            LineInfo::Change(c) => vcd.add_change(c, current_timestamp),
+    fn on_timescale(&mut self, timescale: &str) -> Result<(), String> {
+        self.vcd.timescale = Some(timescale.into());
+        Ok(())
+    }
+
+    fn on_scope(&mut self, name: &str) -> Result<(), String> {
+        // A scope opened again continues the one declared before
+        if let Some(Node::Module(index)) = self.vcd.hierarchy[self.current_module_index].child(name)
+        {
+            self.current_module_index = index;
+            return Ok(());
         }
+        // Create and push the new module
+        let index = self.vcd.hierarchy.len();
+        self.vcd.hierarchy.push(Module {
+            name: name.into(),
+            parent: self.current_module_index,
+            ..Module::default()
+        });
+        // Update old module children
+        self.vcd.hierarchy[self.current_module_index].add_child(name.into(), Node::Module(index));
+        // Update current module
+        self.current_module_index = index;
+        Ok(())
     }
-    Ok(())
-}
 
-fn translate_definitions(
-    vcd: &mut VCD,
-    infos: Receiver<LineInfo>,
-) -> Result<Receiver<LineInfo>, String> {
-    let mut translator = InfoTranslator::default();
-    for info in infos.iter() {
-        match info {
-            LineInfo::Signal(s) => vcd.push(s, &translator),
-            LineInfo::DateInfo(s) => println!("Date: {}", s.trim().replace("$end", "").trim()),
-            LineInfo::VersionInfo(s) => {
-                println!("Tool: {}", s.trim().replace("$end", "").trim())
-            }
-            LineInfo::TimeScaleInfo(s) => {
-                println!("Time scale: {}", s.trim().replace("$end", "").trim())
-            }
-            LineInfo::InScope(module) => {
-                // Gather last value index
-                let last_value = vcd.hierarchy.len();
-                // Create and push the new module
-                let m = Module {
-                    parent: translator.current_module_index,
-                    ..Module::default()
-                };
-                vcd.hierarchy.push(m);
-                // Update old module children
-                vcd.hierarchy[translator.current_module_index]
-                    .children
-                    .insert(module.into(), Node::Module(last_value));
-                // Update current module
-                translator.current_module_index = last_value;
-            }
-            LineInfo::UpScope => {
-                translator.current_module_index =
-                    vcd.hierarchy[translator.current_module_index].parent;
-            }
-            LineInfo::ParsingError(s) => {
-                return Err(format!("ERROR: unrecognized symbol {}", s));
-            }
-            LineInfo::EndDefinitions => break,
-            LineInfo::Useless => {}
-            LineInfo::Dumpports => panic!("Not expected dumpports here!"),
-            LineInfo::Timestamp(t) => panic!("Unexpected timestamp: {:?}", t),
-            LineInfo::Change(c) => panic!("Unexpected change: {:?}", c),
-            LineInfo::EndInitializations => {
-                panic!("End initializations found before the beginning!")
-            }
+    fn on_upscope(&mut self) -> Result<(), String> {
+        if self.current_module_index == 0 {
+            return Err("$upscope without a matching $scope".into());
         }
+        self.current_module_index = self.vcd.hierarchy[self.current_module_index].parent;
+        Ok(())
     }
-    Ok(infos)
-}
 
-fn translate_infos(mut infos: Receiver<LineInfo>) -> Result<VCD, String> {
-    let mut vcd = VCD::default();
-    match translate_definitions(&mut vcd, infos) {
-        Ok(info) => infos = info,
-        Err(s) => return Err(s),
+    fn on_signal(&mut self, signal: vcd_reader::Signal) -> Result<(), String> {
+        self.vcd.push(signal, self.current_module_index)
     }
-    match translate_changes(&mut vcd, infos) {
-        Ok(_) => Ok(vcd),
-        Err(s) => Err(s),
+
+    fn on_end_definitions(&mut self) -> Result<(), String> {
+        self.definitions_done = true;
+        Ok(())
+    }
+
+    fn on_timestamp(&mut self, time: usize) -> Result<(), String> {
+        self.current_timestamp = time as i64;
+        Ok(())
+    }
+
+    fn on_change(&mut self, signal_id: &str, values: &[u8]) -> Result<(), String> {
+        self.vcd
+            .add_change(signal_id, values, self.current_timestamp)
+    }
+
+    fn on_progress(&mut self, _read: u64, _total: u64) -> bool {
+        !(self.definitions_only && self.definitions_done)
     }
 }
@@ -0,0 +1,75 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use vcd_reader::VCDFile;
+
+use crate::{database::Source, Configuration, Indexer, VCD};
+
+/// Index of a dump still being written, extended with [`LiveIndex::update`]. A last line
+/// without its newline is left for the next update, so a change is never read half written.
+pub struct LiveIndex {
+    in_file: String,
+    file: VCDFile,
+    indexer: Indexer,
+    subscribers: Vec<Sender<i64>>,
+}
+
+/// Indexes what is already written of the dump, see [`LiveIndex`]. A `$date`, `$version` or
+/// `$timescale` section not written to its `$end` yet is left for the next update.
+pub fn index_live(configuration: Configuration) -> Result<LiveIndex, String> {
+    let reader_config = vcd_reader::Configuration {
+        in_file: &configuration.in_file,
+        separator: configuration.separator,
+    };
+    let mut file = VCDFile::new(reader_config)?;
+    file.follow();
+    let mut indexer = Indexer::default();
+    indexer.vcd.source = Source::of(&configuration.in_file)?;
+    indexer.vcd.separator = configuration.separator;
+    file.visit(&mut indexer)?;
+    Ok(LiveIndex {
+        in_file: configuration.in_file,
+        file,
+        indexer,
+        subscribers: vec![],
+    })
+}
+
+impl LiveIndex {
+    pub fn vcd(&self) -> &VCD {
+        &self.indexer.vcd
+    }
+
+    pub fn into_vcd(self) -> VCD {
+        self.indexer.vcd
+    }
+
+    /// Time of the last timestamp read, None before the first one.
+    pub fn end_time(&self) -> Option<i64> {
+        Some(self.indexer.current_timestamp).filter(|time| *time >= 0)
+    }
+
+    /// Indexes the lines written since the last update. Returns the new end time, also sent
+    /// to the subscribers, when it moved. After an error, the lines before it stay indexed
+    /// and the next update continues after it.
+    pub fn update(&mut self) -> Result<Option<i64>, String> {
+        let before = self.end_time();
+        // Taken before reading, so that a saved index of a dump written meanwhile is not used
+        self.indexer.vcd.source = Source::of(&self.in_file)?;
+        self.file.visit(&mut self.indexer)?;
+        match self.end_time() {
+            Some(end) if Some(end) != before => {
+                self.subscribers
+                    .retain(|subscriber| subscriber.send(end).is_ok());
+                Ok(Some(end))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Receives the end time after every update moving it, until dropped.
+    pub fn subscribe(&mut self) -> Receiver<i64> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+}
@@ -0,0 +1,270 @@
+use std::ops::Range;
+
+use crate::database::{Decoder, Encoder};
+use crate::wave::{Wave, BLOCK_CHANGES};
+use crate::{Value, VCD};
+
+/// Extents merged by each level of a pyramid from the level below.
+const FANOUT: usize = 4;
+
+/// Values of a signal during a bucket of [`VCD::summarize`], including the one held from
+/// before the bucket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    pub range: Range<i64>,
+    /// Changes in the bucket
+    pub transitions: usize,
+    /// Smallest known value, among the ones fitting 128 bits
+    pub min: Option<u128>,
+    /// Largest known value, among the ones fitting 128 bits
+    pub max: Option<u128>,
+    /// Some value has x or z bits
+    pub unknown: bool,
+    /// Value at the end of the bucket
+    pub last: Option<Value>,
+}
+
+impl Summary {
+    pub fn has_transitions(&self) -> bool {
+        self.transitions > 0
+    }
+}
+
+/// Smallest and largest known value and presence of x or z among some changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Extent {
+    min: u128,
+    max: u128,
+    unknown: bool,
+}
+
+impl Extent {
+    const EMPTY: Extent = Extent {
+        min: u128::MAX,
+        max: 0,
+        unknown: false,
+    };
+
+    fn of(value: &Value) -> Self {
+        match value.to_u128() {
+            Some(number) => Extent {
+                min: number,
+                max: number,
+                unknown: false,
+            },
+            None => Extent {
+                unknown: !value.is_known(),
+                ..Extent::EMPTY
+            },
+        }
+    }
+
+    fn merge(&mut self, other: &Extent) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.unknown |= other.unknown;
+    }
+
+    fn bounds(&self) -> Option<(u128, u128)> {
+        (self.min <= self.max).then_some((self.min, self.max))
+    }
+}
+
+/// Extents of the groups of `extents`, making the level above them.
+fn merged(extents: &[Extent]) -> Vec<Extent> {
+    extents
+        .chunks(FANOUT)
+        .map(|group| {
+            let mut extent = Extent::EMPTY;
+            group.iter().for_each(|child| extent.merge(child));
+            extent
+        })
+        .collect()
+}
+
+/// Extents of the blocks of a wave, then of groups of [`FANOUT`] of them, and so on up to a
+/// single one for the whole wave. The extent of any run of blocks is merged from a few of
+/// them.
+#[derive(Debug, Default)]
+pub(crate) struct Pyramid {
+    levels: Vec<Vec<Extent>>,
+}
+
+impl Pyramid {
+    /// Adds `value` to `block`, which is the last block or the one after it.
+    pub(crate) fn add(&mut self, block: usize, value: &Value) {
+        let extent = Extent::of(value);
+        if self.levels.is_empty() {
+            self.levels.push(vec![]);
+        }
+        let mut index = block;
+        for level in 0.. {
+            let top = level + 1 == self.levels.len();
+            let extents = &mut self.levels[level];
+            if index == extents.len() {
+                extents.push(Extent::EMPTY);
+            }
+            extents[index].merge(&extent);
+            if extents.len() == 1 {
+                break;
+            }
+            if top {
+                // Merges what the new level covers, the new value included
+                let above = merged(extents);
+                self.levels.push(above);
+                break;
+            }
+            index /= FANOUT;
+        }
+    }
+
+    /// Adds the levels above the blocks.
+    fn build(&mut self) {
+        while self.levels.last().is_some_and(|level| level.len() > 1) {
+            let level = merged(self.levels.last().unwrap());
+            self.levels.push(level);
+        }
+    }
+
+    /// Extent of the blocks in `start..end`.
+    fn extent(&self, mut start: usize, mut end: usize) -> Extent {
+        let mut extent = Extent::EMPTY;
+        for level in &self.levels {
+            while start < end && !start.is_multiple_of(FANOUT) {
+                extent.merge(&level[start]);
+                start += 1;
+            }
+            while start < end && !end.is_multiple_of(FANOUT) {
+                end -= 1;
+                extent.merge(&level[end]);
+            }
+            if start >= end {
+                break;
+            }
+            start /= FANOUT;
+            end /= FANOUT;
+        }
+        extent
+    }
+
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.levels
+            .iter()
+            .map(|level| level.capacity() * std::mem::size_of::<Extent>())
+            .sum()
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.levels.iter_mut().for_each(Vec::shrink_to_fit);
+    }
+
+    /// Only the blocks are saved, the levels above are merged again when decoding.
+    pub(crate) fn encode(&self, out: &mut Encoder) {
+        for extent in self.levels.first().into_iter().flatten() {
+            out.u64(extent.min as u64);
+            out.u64((extent.min >> 64) as u64);
+            out.u64(extent.max as u64);
+            out.u64((extent.max >> 64) as u64);
+        }
+        for extent in self.levels.first().into_iter().flatten() {
+            out.bytes.push(extent.unknown as u8);
+        }
+    }
+
+    pub(crate) fn decode(input: &mut Decoder, blocks: usize) -> Result<Self, String> {
+        let mut number =
+            || -> Result<u128, String> { Ok(input.u64()? as u128 | (input.u64()? as u128) << 64) };
+        let mut extents = vec![];
+        for _ in 0..blocks {
+            extents.push(Extent {
+                min: number()?,
+                max: number()?,
+                unknown: false,
+            });
+        }
+        for extent in &mut extents {
+            extent.unknown = input.u8()? != 0;
+        }
+        let mut pyramid = Pyramid {
+            levels: vec![extents],
+        };
+        pyramid.build();
+        Ok(pyramid)
+    }
+}
+
+impl Wave {
+    /// Extent of the changes in `start..end`, merging whole blocks from the pyramid.
+    fn extent(&self, start: usize, end: usize) -> Extent {
+        let first_block = start.div_ceil(BLOCK_CHANGES);
+        let last_block = end / BLOCK_CHANGES;
+        let mut extent = Extent::EMPTY;
+        let mut add = |range: Range<usize>| {
+            range.for_each(|index| extent.merge(&Extent::of(&self.value(index))))
+        };
+        if first_block >= last_block {
+            add(start..end);
+        } else {
+            add(start..first_block * BLOCK_CHANGES);
+            add(last_block * BLOCK_CHANGES..end);
+            extent.merge(&self.lod().extent(first_block, last_block));
+        }
+        extent
+    }
+
+    fn time(&self, index: usize) -> Option<i64> {
+        self.times_from(index).next().map(|(_, time)| time)
+    }
+}
+
+impl VCD {
+    /// Summaries of `signal` over `buckets` equal parts of `range`, for drawing a wave
+    /// zoomed out. A bucket costs a search and the decoding of at most two blocks, however
+    /// many changes it holds. Empty for an unknown signal.
+    pub fn summarize(&self, signal: usize, range: Range<i64>, buckets: usize) -> Vec<Summary> {
+        self.try_summarize(signal, range, buckets)
+            .unwrap_or_default()
+    }
+
+    /// Same as [`VCD::summarize`], failing for an unknown signal or one that cannot be loaded.
+    pub fn try_summarize(
+        &self,
+        signal: usize,
+        range: Range<i64>,
+        buckets: usize,
+    ) -> Result<Vec<Summary>, String> {
+        let wave = self.wave(signal)?;
+        let length = (range.end as i128 - range.start as i128).max(0);
+        let bound = |bucket: usize| {
+            (range.start as i128 + length * bucket as i128 / buckets.max(1) as i128) as i64
+        };
+        // Times and first changes of the buckets, each shared with the one before
+        let bounds: Vec<(i64, usize)> = (0..buckets + 1)
+            .map(|bucket| {
+                let time = bound(bucket);
+                (time, wave.partition_point(|change| change < time))
+            })
+            .collect();
+        Ok(bounds
+            .windows(2)
+            .map(|pair| {
+                let ((start, first), (end, last)) = (pair[0], pair[1]);
+                // The value held at the start counts, unless replaced right then
+                let held = match first.checked_sub(1) {
+                    Some(before) if wave.time(first) != Some(start) || first == last => before,
+                    _ => first,
+                };
+                let extent = wave.extent(held, last);
+                let bounds = extent.bounds();
+                Summary {
+                    range: start..end,
+                    transitions: last - first,
+                    min: bounds.map(|(min, _)| min),
+                    max: bounds.map(|(_, max)| max),
+                    unknown: extent.unknown,
+                    last: last.checked_sub(1).map(|index| wave.value(index)),
+                }
+            })
+            .collect())
+    }
+}
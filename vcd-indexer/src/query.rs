@@ -0,0 +1,126 @@
+use std::ops::Range;
+
+use vcd_reader::SignalValue;
+
+use crate::{wave::Wave, State, Value, VCD};
+
+/// Transitions searched by [`VCD::next_edge`] and [`VCD::prev_edge`]. Rising and falling
+/// edges of a vector are the ones of its least significant bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    /// Any change to 1
+    Rising,
+    /// Any change to 0
+    Falling,
+    /// Any change of value
+    Any,
+}
+
+impl Edge {
+    fn matches(self, before: &Value, after: &Value) -> bool {
+        match self {
+            Edge::Rising => before.bit(0) != SignalValue::UP && after.bit(0) == SignalValue::UP,
+            Edge::Falling => {
+                before.bit(0) != SignalValue::DOWN && after.bit(0) == SignalValue::DOWN
+            }
+            Edge::Any => before != after,
+        }
+    }
+}
+
+impl Wave {
+    /// Number of changes at or before `time`.
+    fn changes_until(&self, time: i64) -> usize {
+        self.partition_point(|change| change <= time)
+    }
+
+    fn is_edge(&self, index: usize, edge: Edge) -> bool {
+        index > 0 && edge.matches(&self.value(index - 1), &self.value(index))
+    }
+}
+
+impl VCD {
+    /// Value of `signal` at `time`, after every change at `time`. None before the first value
+    /// or for an unknown signal.
+    pub fn value_at(&self, signal: usize, time: i64) -> Option<Value> {
+        self.try_value_at(signal, time).ok().flatten()
+    }
+
+    /// Same as [`VCD::value_at`], failing for an unknown signal or one that cannot be loaded.
+    pub fn try_value_at(&self, signal: usize, time: i64) -> Result<Option<Value>, String> {
+        let wave = self.wave(signal)?;
+        let index = wave.changes_until(time).checked_sub(1);
+        Ok(index.map(|index| wave.value(index)))
+    }
+
+    /// Changes of `signal` with a time in `range`, in time order.
+    pub fn changes_in(&self, signal: usize, range: Range<i64>) -> impl Iterator<Item = State> + '_ {
+        self.try_changes_in(signal, range).into_iter().flatten()
+    }
+
+    /// Same as [`VCD::changes_in`], failing for an unknown signal or one that cannot be
+    /// loaded.
+    pub fn try_changes_in(
+        &self,
+        signal: usize,
+        range: Range<i64>,
+    ) -> Result<impl Iterator<Item = State> + '_, String> {
+        let wave = self.wave(signal)?;
+        let start = wave.partition_point(|time| time < range.start);
+        let end = wave.partition_point(|time| time < range.end);
+        Ok(wave
+            .times_from(start)
+            .take(end.saturating_sub(start))
+            .map(|(index, time)| State {
+                value: wave.value(index),
+                time,
+            }))
+    }
+
+    /// Time of the first `edge` of `signal` after `time`.
+    pub fn next_edge(&self, signal: usize, time: i64, edge: Edge) -> Option<i64> {
+        self.try_next_edge(signal, time, edge).ok().flatten()
+    }
+
+    /// Same as [`VCD::next_edge`], failing for an unknown signal or one that cannot be loaded.
+    pub fn try_next_edge(
+        &self,
+        signal: usize,
+        time: i64,
+        edge: Edge,
+    ) -> Result<Option<i64>, String> {
+        let wave = self.wave(signal)?;
+        Ok(wave
+            .times_from(wave.changes_until(time))
+            .find(|(index, _)| wave.is_edge(*index, edge))
+            .map(|(_, time)| time))
+    }
+
+    /// Time of the last `edge` of `signal` before `time`.
+    pub fn prev_edge(&self, signal: usize, time: i64, edge: Edge) -> Option<i64> {
+        self.try_prev_edge(signal, time, edge).ok().flatten()
+    }
+
+    /// Same as [`VCD::prev_edge`], failing for an unknown signal or one that cannot be loaded.
+    pub fn try_prev_edge(
+        &self,
+        signal: usize,
+        time: i64,
+        edge: Edge,
+    ) -> Result<Option<i64>, String> {
+        let wave = self.wave(signal)?;
+        let before = wave.partition_point(|change| change < time);
+        Ok(wave
+            .times_before(before)
+            .find(|(index, _)| wave.is_edge(*index, edge))
+            .map(|(_, time)| time))
+    }
+
+    /// Values of `signals` at `time`, in the same order.
+    pub fn snapshot(&self, signals: &[usize], time: i64) -> Vec<Option<Value>> {
+        signals
+            .iter()
+            .map(|signal| self.value_at(*signal, time))
+            .collect()
+    }
+}
@@ -0,0 +1,214 @@
+use std::fmt::Display;
+
+use vcd_reader::SignalValue;
+
+/// Value of a signal, packed two bits per bit: 00 is 0, 01 is 1, 10 is x and 11 is z.
+/// Bit 0 is the least significant one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Value {
+    width: usize,
+    packed: Vec<u8>,
+}
+
+fn code(value: SignalValue) -> u8 {
+    match value {
+        SignalValue::DOWN => 0,
+        SignalValue::UP => 1,
+        SignalValue::X => 2,
+        SignalValue::Z => 3,
+    }
+}
+
+fn from_code(code: u8) -> SignalValue {
+    match code & 3 {
+        0 => SignalValue::DOWN,
+        1 => SignalValue::UP,
+        2 => SignalValue::X,
+        _ => SignalValue::Z,
+    }
+}
+
+/// Bytes taken by a packed value of `width` bits.
+pub(crate) fn packed_len(width: usize) -> usize {
+    width.div_ceil(4)
+}
+
+impl Value {
+    /// Value of `width` bits from the digits of a change, most significant first. Shorter
+    /// values are extended on the left as VCD requires: with x or z when their leftmost
+    /// digit is x or z, with 0 otherwise.
+    pub fn from_digits(digits: &[u8], width: usize) -> Result<Self, String> {
+        if digits.len() > width {
+            return Err(format!("{} values for {} bits", digits.len(), width));
+        }
+        let fill = match digits.first().map(|digit| SignalValue::from(*digit)) {
+            Some(value @ (SignalValue::X | SignalValue::Z)) => value,
+            _ => SignalValue::DOWN,
+        };
+        let mut value = Value::filled(width, fill);
+        for (bit, digit) in digits.iter().rev().enumerate() {
+            value.set_bit(bit, SignalValue::from(*digit));
+        }
+        Ok(value)
+    }
+
+    /// Value of `width` bits, all equal to `bit`.
+    pub fn filled(width: usize, bit: SignalValue) -> Self {
+        let byte = code(bit) * 0b01010101;
+        let mut value = Value {
+            width,
+            packed: vec![byte; packed_len(width)],
+        };
+        value.clear_padding();
+        value
+    }
+
+    /// Value of the bits of `bits`, least significant first.
+    pub fn from_bits(bits: &[SignalValue]) -> Self {
+        let mut value = Value::filled(bits.len(), SignalValue::DOWN);
+        for (bit, bit_value) in bits.iter().enumerate() {
+            value.set_bit(bit, *bit_value);
+        }
+        value
+    }
+
+    pub(crate) fn from_packed(width: usize, packed: &[u8]) -> Self {
+        Value {
+            width,
+            packed: packed.to_vec(),
+        }
+    }
+
+    pub(crate) fn packed(&self) -> &[u8] {
+        &self.packed
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Bit `bit`, 0 being the least significant one.
+    pub fn bit(&self, bit: usize) -> SignalValue {
+        assert!(
+            bit < self.width,
+            "bit {} of a {} bit value",
+            bit,
+            self.width
+        );
+        from_code(self.packed[bit / 4] >> (bit % 4 * 2))
+    }
+
+    fn set_bit(&mut self, bit: usize, value: SignalValue) {
+        let shift = bit % 4 * 2;
+        self.packed[bit / 4] = self.packed[bit / 4] & !(3 << shift) | code(value) << shift;
+    }
+
+    pub(crate) fn clear_padding(&mut self) {
+        if !self.width.is_multiple_of(4) {
+            let last = self.packed.len() - 1;
+            self.packed[last] &= (1 << (self.width % 4 * 2)) - 1;
+        }
+    }
+
+    /// Bits `msb` down to `lsb`, as in `data[msb:lsb]`.
+    pub fn slice(&self, msb: usize, lsb: usize) -> Value {
+        assert!(
+            lsb <= msb && msb < self.width,
+            "bits [{}:{}] of a {} bit value",
+            msb,
+            lsb,
+            self.width
+        );
+        let mut value = Value::filled(msb - lsb + 1, SignalValue::DOWN);
+        (lsb..=msb).for_each(|bit| value.set_bit(bit - lsb, self.bit(bit)));
+        value
+    }
+
+    /// True when no bit is x or z.
+    pub fn is_known(&self) -> bool {
+        // x and z are the codes with the high bit set, padding is 0
+        self.packed.iter().all(|byte| byte & 0b10101010 == 0)
+    }
+
+    /// The value as a number, if it is known and fits.
+    pub fn to_u128(&self) -> Option<u128> {
+        if !self.is_known() {
+            return None;
+        }
+        self.packed
+            .iter()
+            .enumerate()
+            .filter(|(_, byte)| **byte != 0)
+            .try_fold(0, |number, (index, byte)| {
+                let bits = (0..4)
+                    .filter(|field| byte >> (field * 2) & 1 == 1)
+                    .fold(0u128, |bits, field| bits | 1 << field);
+                (index < 32).then(|| number | bits << (index * 4))
+            })
+    }
+
+    /// Hexadecimal digits, most significant first. A digit is z when its bits are all z
+    /// and x when any of them is unknown.
+    pub fn to_hex_string(&self) -> String {
+        (0..self.width.div_ceil(4))
+            .rev()
+            .map(|digit| {
+                let bits: Vec<SignalValue> = (digit * 4..(digit * 4 + 4).min(self.width))
+                    .map(|bit| self.bit(bit))
+                    .collect();
+                if bits.iter().all(|bit| *bit == SignalValue::Z) {
+                    'z'
+                } else if bits
+                    .iter()
+                    .any(|bit| matches!(bit, SignalValue::X | SignalValue::Z))
+                {
+                    'x'
+                } else {
+                    let nibble = bits
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, bit)| **bit == SignalValue::UP)
+                        .fold(0, |nibble, (index, _)| nibble | 1 << index);
+                    char::from_digit(nibble, 16).unwrap()
+                }
+            })
+            .collect()
+    }
+
+    /// Unsigned decimal value of any width, or x when a bit is unknown.
+    pub fn to_decimal_string(&self) -> String {
+        if !self.is_known() {
+            return "x".into();
+        }
+        if let Some(number) = self.to_u128() {
+            return number.to_string();
+        }
+        // Repeated division by 10 of 32 bit limbs, least significant first
+        let mut limbs = vec![0u32; self.width.div_ceil(32)];
+        (0..self.width)
+            .filter(|bit| self.bit(*bit) == SignalValue::UP)
+            .for_each(|bit| limbs[bit / 32] |= 1 << (bit % 32));
+        let mut digits = vec![];
+        while limbs.iter().any(|limb| *limb != 0) {
+            let mut remainder = 0u64;
+            for limb in limbs.iter_mut().rev() {
+                let current = remainder << 32 | *limb as u64;
+                *limb = (current / 10) as u32;
+                remainder = current % 10;
+            }
+            digits.push(char::from_digit(remainder as u32, 10).unwrap());
+        }
+        digits.iter().rev().collect()
+    }
+}
+
+/// Binary digits, most significant first.
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits: String = (0..self.width)
+            .rev()
+            .map(|bit| char::from(self.bit(bit)))
+            .collect();
+        write!(f, "{}", digits)
+    }
+}
@@ -0,0 +1,284 @@
+use crate::database::{Decoder, Encoder};
+use crate::lod::Pyramid;
+use crate::value::{packed_len, Value};
+
+/// Changes per block. A block is the unit of seeking: finding a time decodes one block.
+pub const BLOCK_CHANGES: usize = 256;
+
+/// Where the changes of a block start.
+#[derive(Debug, Clone, Copy)]
+struct Block {
+    first_time: i64,
+    times_offset: usize,
+}
+
+/// Changes of the signals sharing an id code, in time order. The times are stored as
+/// LEB128 differences from the previous change of their block, the values as a single
+/// stream of two bit fields, `width` fields per change.
+#[derive(Debug)]
+pub(crate) struct Wave {
+    width: usize,
+    len: usize,
+    last_time: i64,
+    blocks: Vec<Block>,
+    times: Vec<u8>,
+    values: Vec<u8>,
+    lod: Pyramid,
+}
+
+/// Bytes used by the changes of an index, see [`crate::VCD::memory_usage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub changes: usize,
+    /// Encoded times
+    pub time_bytes: usize,
+    /// Packed values
+    pub value_bytes: usize,
+    /// Block headers and the fixed size of the signals
+    pub overhead_bytes: usize,
+    /// Size of the same changes stored as one 16 byte state per bit
+    pub unpacked_bytes: usize,
+}
+
+impl MemoryUsage {
+    pub fn total_bytes(&self) -> usize {
+        self.time_bytes + self.value_bytes + self.overhead_bytes
+    }
+
+    /// Average bytes per change.
+    pub fn bytes_per_change(&self) -> f64 {
+        match self.changes {
+            0 => 0.0,
+            changes => self.total_bytes() as f64 / changes as f64,
+        }
+    }
+}
+
+impl std::ops::AddAssign for MemoryUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.changes += other.changes;
+        self.time_bytes += other.time_bytes;
+        self.value_bytes += other.value_bytes;
+        self.overhead_bytes += other.overhead_bytes;
+        self.unpacked_bytes += other.unpacked_bytes;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], offset: &mut usize) -> u64 {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*offset];
+        *offset += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+impl Wave {
+    pub(crate) fn new(width: usize) -> Self {
+        Wave {
+            width,
+            len: 0,
+            last_time: i64::MIN,
+            blocks: vec![],
+            times: vec![],
+            values: vec![],
+            lod: Pyramid::default(),
+        }
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Appends a change. `time` must not be before the previous change.
+    pub(crate) fn push(&mut self, time: i64, value: &Value) -> Result<(), String> {
+        if time < self.last_time {
+            return Err(format!("Time {} before {}", time, self.last_time));
+        }
+        if self.len.is_multiple_of(BLOCK_CHANGES) {
+            self.blocks.push(Block {
+                first_time: time,
+                times_offset: self.times.len(),
+            });
+            self.last_time = time;
+        }
+        write_varint(&mut self.times, (time - self.last_time) as u64);
+        self.last_time = time;
+
+        let shift = self.len * self.width % 4 * 2;
+        match shift {
+            0 => self.values.extend_from_slice(value.packed()),
+            _ => {
+                for byte in value.packed() {
+                    *self.values.last_mut().unwrap() |= byte << shift;
+                    self.values.push(byte >> (8 - shift));
+                }
+            }
+        }
+        self.lod.add(self.len / BLOCK_CHANGES, value);
+        self.len += 1;
+        self.values.truncate(packed_len(self.len * self.width));
+        Ok(())
+    }
+
+    /// Appends the changes of `other`, which must not be before the last change.
+    pub(crate) fn append(&mut self, other: &Wave) -> Result<(), String> {
+        other
+            .times_from(0)
+            .try_for_each(|(index, time)| self.push(time, &other.value(index)))
+    }
+
+    /// Times of the changes of block `block`.
+    fn block_times(&self, block: usize) -> Vec<i64> {
+        let count = BLOCK_CHANGES.min(self.len - block * BLOCK_CHANGES);
+        let mut offset = self.blocks[block].times_offset;
+        let mut time = self.blocks[block].first_time;
+        (0..count)
+            .map(|_| {
+                time += read_varint(&self.times, &mut offset) as i64;
+                time
+            })
+            .collect()
+    }
+
+    pub(crate) fn value(&self, index: usize) -> Value {
+        let first_field = index * self.width;
+        let start = first_field / 4;
+        let shift = first_field % 4 * 2;
+        let packed: Vec<u8> = (0..packed_len(self.width))
+            .map(|byte| match shift {
+                0 => self.values[start + byte],
+                _ => {
+                    let next = self.values.get(start + byte + 1).copied().unwrap_or(0);
+                    self.values[start + byte] >> shift | next << (8 - shift)
+                }
+            })
+            .collect();
+        let mut value = Value::from_packed(self.width, &packed);
+        value.clear_padding();
+        value
+    }
+
+    /// Number of changes before the first one for which `before` is false.
+    pub(crate) fn partition_point(&self, before: impl Fn(i64) -> bool) -> usize {
+        match self
+            .blocks
+            .partition_point(|block| before(block.first_time))
+        {
+            0 => 0,
+            blocks => {
+                let block = blocks - 1;
+                let times = self.block_times(block);
+                block * BLOCK_CHANGES + times.partition_point(|time| before(*time))
+            }
+        }
+    }
+
+    /// Indexes and times of the changes from `start` on.
+    pub(crate) fn times_from(&self, start: usize) -> impl Iterator<Item = (usize, i64)> + '_ {
+        (start / BLOCK_CHANGES..self.blocks.len()).flat_map(move |block| {
+            let first = block * BLOCK_CHANGES;
+            self.block_times(block)
+                .into_iter()
+                .enumerate()
+                .map(move |(index, time)| (first + index, time))
+                .filter(move |(index, _)| *index >= start)
+        })
+    }
+
+    /// Indexes and times of the changes before `end`, last first.
+    pub(crate) fn times_before(&self, end: usize) -> impl Iterator<Item = (usize, i64)> + '_ {
+        (0..end.div_ceil(BLOCK_CHANGES))
+            .rev()
+            .flat_map(move |block| {
+                let first = block * BLOCK_CHANGES;
+                self.block_times(block)
+                    .into_iter()
+                    .enumerate()
+                    .rev()
+                    .map(move |(index, time)| (first + index, time))
+                    .filter(move |(index, _)| *index < end)
+            })
+    }
+
+    /// Releases the space reserved for further changes.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.blocks.shrink_to_fit();
+        self.times.shrink_to_fit();
+        self.values.shrink_to_fit();
+        self.lod.shrink_to_fit();
+    }
+
+    pub(crate) fn lod(&self) -> &Pyramid {
+        &self.lod
+    }
+
+    pub(crate) fn encode(&self, out: &mut Encoder) {
+        out.usize(self.width);
+        out.usize(self.len);
+        out.i64(self.last_time);
+        for block in &self.blocks {
+            out.i64(block.first_time);
+            out.usize(block.times_offset);
+        }
+        out.bytes(&self.times);
+        out.bytes(&self.values);
+        self.lod.encode(out);
+    }
+
+    pub(crate) fn decode(input: &mut Decoder) -> Result<Self, String> {
+        let width = input.usize()?;
+        let len = input.usize()?;
+        let last_time = input.i64()?;
+        let blocks = (0..len.div_ceil(BLOCK_CHANGES))
+            .map(|_| {
+                Ok(Block {
+                    first_time: input.i64()?,
+                    times_offset: input.usize()?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let times = input.bytes()?.to_vec();
+        let values = input.bytes()?.to_vec();
+        let lod = Pyramid::decode(input, blocks.len())?;
+        if values.len() != packed_len(len * width)
+            || blocks.iter().any(|block| block.times_offset >= times.len())
+        {
+            return Err("Database damaged".into());
+        }
+        Ok(Wave {
+            width,
+            len,
+            last_time,
+            blocks,
+            times,
+            values,
+            lod,
+        })
+    }
+
+    pub(crate) fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            changes: self.len,
+            time_bytes: self.times.capacity(),
+            value_bytes: self.values.capacity(),
+            overhead_bytes: std::mem::size_of::<Self>()
+                + self.blocks.capacity() * std::mem::size_of::<Block>()
+                + self.lod.memory_usage(),
+            unpacked_bytes: self.len * self.width * 16,
+        }
+    }
+}
@@ -0,0 +1,811 @@
+use std::fs;
+
+use vcd_indexer::*;
+use vcd_reader::SignalValue;
+
+fn index_file(file: &str) -> Result<VCD, String> {
+    index(Configuration {
+        in_file: format!("tests/files/{}", file),
+        separator: ' ',
+    })
+}
+
+/// Dump written for a test, removed with its database when dropped. Named after the
+/// process, so that test runs of several checkouts do not share it.
+struct TempDump(String);
+
+impl TempDump {
+    fn new(name: &str, content: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("oxyvcd_{}_{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        TempDump(path.to_str().unwrap().into())
+    }
+
+    fn path(&self) -> String {
+        self.0.clone()
+    }
+}
+
+impl Drop for TempDump {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+        let _ = fs::remove_file(database_path(&self.0));
+    }
+}
+
+#[test]
+fn test_index() {
+    let vcd = index_file("counter.vcd").unwrap();
+    assert_eq!(vcd.timescale.as_deref(), Some("1ns"));
+    assert_eq!(vcd.version.as_deref(), Some("oxyvcd test bench"));
+    assert_eq!(vcd.signals.len(), 4);
+    let clk = vcd.signals_by_id["!"];
+    let values: Vec<(i64, String)> = vcd
+        .changes_in(clk, 0..11)
+        .map(|state| (state.time, state.value.to_string()))
+        .collect();
+    assert_eq!(
+        values,
+        vec![(0, "0".into()), (5, "1".into()), (10, "0".into())]
+    );
+    let count = vcd.signals_by_id["#"];
+    assert_eq!(vcd.signals[count].width, 2);
+    let values: Vec<String> = vcd
+        .changes_in(count, 0..100)
+        .map(|state| state.value.to_string())
+        .collect();
+    assert_eq!(values, vec!["xx", "00", "01", "10", "11", "00"]);
+}
+
+#[test]
+fn test_values() {
+    let value = Value::from_digits(b"1010x1z0", 8).unwrap();
+    assert_eq!(value.to_string(), "1010x1z0");
+    assert_eq!(value.width(), 8);
+    assert_eq!(value.bit(0), SignalValue::DOWN);
+    assert_eq!(value.bit(1), SignalValue::Z);
+    assert_eq!(value.bit(3), SignalValue::X);
+    assert_eq!(value.slice(7, 4).to_string(), "1010");
+    assert_eq!(value.slice(7, 4).to_hex_string(), "a");
+    assert_eq!(value.to_hex_string(), "ax");
+    assert_eq!(value.to_decimal_string(), "x");
+    assert_eq!(value.to_u128(), None);
+    // Shorter values are extended with 0, or with their leftmost x or z
+    assert_eq!(Value::from_digits(b"11", 6).unwrap().to_string(), "000011");
+    assert_eq!(Value::from_digits(b"z1", 4).unwrap().to_string(), "zzz1");
+    assert_eq!(Value::from_digits(b"z", 5).unwrap().to_hex_string(), "zz");
+    assert!(Value::from_digits(b"111", 2).is_err());
+    let wide = Value::from_digits(&[b'1'; 130], 130).unwrap();
+    assert_eq!(wide.to_u128(), None);
+    assert_eq!(
+        wide.to_decimal_string(),
+        "1361129467683753853853498429727072845823"
+    );
+    assert_eq!(wide.to_hex_string(), format!("3{}", "f".repeat(32)));
+    assert_eq!(
+        Value::from_digits(b"11111111", 8).unwrap().to_u128(),
+        Some(255)
+    );
+}
+
+#[test]
+fn test_hierarchy() {
+    let vcd = index_file("counter.vcd").unwrap();
+    // Root, top and top.cnt
+    assert_eq!(vcd.hierarchy.len(), 3);
+    let Some(Node::Module(top)) = vcd.find_by_path("top") else {
+        panic!("top not found");
+    };
+    let Some(Node::Module(cnt)) = vcd.find_by_path("top.cnt") else {
+        panic!("top.cnt not found");
+    };
+    assert_eq!(vcd.hierarchy[cnt].parent, top);
+    assert_eq!(&*vcd.hierarchy[cnt].name, "cnt");
+    let count = vcd.signals_by_id["#"];
+    assert_eq!(vcd.find_by_path("top.cnt.count"), Some(Node::Signal(count)));
+    assert_eq!(
+        vcd.find_by_path("top.clk"),
+        Some(Node::Signal(vcd.signals_by_id["!"]))
+    );
+    assert_eq!(vcd.find_by_path("top.clk.x"), None);
+    assert_eq!(vcd.find_by_path("top.cpu"), None);
+    assert_eq!(vcd.find_by_path(""), None);
+    assert_eq!(vcd.path(Node::Signal(count)), "top.cnt.count");
+    assert_eq!(vcd.path(Node::Module(cnt)), "top.cnt");
+    assert_eq!(vcd.signals[count].parent_index, cnt);
+
+    let children: Vec<String> = vcd.hierarchy[top]
+        .children
+        .iter()
+        .map(|node| vcd.path(*node))
+        .collect();
+    assert_eq!(children, vec!["top.clk", "top.rst_n", "top.cnt"]);
+}
+
+#[test]
+fn test_search() {
+    let vcd = index_file("counter.vcd").unwrap();
+    let paths = |signals: Vec<usize>| -> Vec<String> {
+        signals
+            .into_iter()
+            .map(|signal| vcd.path(Node::Signal(signal)))
+            .collect()
+    };
+    assert_eq!(
+        paths(vcd.search_glob("top.cnt.*")),
+        vec!["top.cnt.count", "top.cnt.overflow"]
+    );
+    assert_eq!(paths(vcd.search_glob("*.?ou*")), vec!["top.cnt.count"]);
+    assert_eq!(paths(vcd.search_glob("top.c?k")), vec!["top.clk"]);
+    assert!(vcd.search_glob("top.cnt").is_empty());
+    assert_eq!(
+        paths(vcd.search_regex(r"^top\.[a-z_]+$").unwrap()),
+        vec!["top.clk", "top.rst_n"]
+    );
+    assert_eq!(vcd.search_regex("count").unwrap().len(), 1);
+    assert!(vcd.search_regex("(").is_err());
+}
+
+#[test]
+fn test_reopened_scope() {
+    let vcd = index_file("reopened_scope.vcd").unwrap();
+    assert_eq!(vcd.hierarchy.len(), 2);
+    assert_eq!(vcd.search_glob("top.*").len(), 3);
+    // Aliases share the changes of their id code
+    let Some(Node::Signal(alias)) = vcd.find_by_path("top.a_alias") else {
+        panic!("top.a_alias not found");
+    };
+    assert_eq!(vcd.value_at(alias, 5).unwrap().to_string(), "1");
+}
+
+#[test]
+fn test_start_indexing() {
+    let vcd = start_indexing(Configuration {
+        in_file: "tests/files/counter.vcd".into(),
+        separator: ' ',
+    })
+    .join()
+    .unwrap()
+    .unwrap();
+    assert_eq!(vcd.signals.len(), 4);
+}
+
+#[test]
+fn test_errors() {
+    assert!(index_file("missing.vcd").is_err());
+    assert_eq!(
+        index_file("unknown_id.vcd").err(),
+        Some("Line 10: Unknown signal id ?".into())
+    );
+    assert_eq!(
+        index_file("too_wide.vcd").err(),
+        Some("Line 10: 3 values for 2 bits of signal !".into())
+    );
+    assert_eq!(
+        index_file("bad_timestamp.vcd").err(),
+        Some("Line 9: Invalid timestamp #5x".into())
+    );
+    assert_eq!(
+        index_file("unterminated_date.vcd").err(),
+        Some("Line 3: Unexpected end of file".into())
+    );
+}
+
+#[test]
+fn test_queries() {
+    let vcd = index_file("counter.vcd").unwrap();
+    let clk = vcd.signals_by_id["!"];
+    let rst_n = vcd.signals_by_id["\""];
+    let overflow = vcd.signals_by_id["$"];
+    assert_eq!(vcd.value_at(clk, -1).map(|value| value.to_string()), None);
+    assert_eq!(
+        vcd.value_at(clk, 0).map(|value| value.to_string()),
+        Some("0".into())
+    );
+    assert_eq!(
+        vcd.value_at(clk, 7).map(|value| value.to_string()),
+        Some("1".into())
+    );
+    assert_eq!(
+        vcd.value_at(clk, 10).map(|value| value.to_string()),
+        Some("0".into())
+    );
+    assert_eq!(
+        vcd.value_at(rst_n, 43).map(|value| value.to_string()),
+        Some("x".into())
+    );
+    // The last change at a time wins
+    assert_eq!(
+        vcd.value_at(overflow, 48).map(|value| value.to_string()),
+        Some("1".into())
+    );
+    assert_eq!(vcd.value_at(vcd.signals.len(), 0), None);
+
+    let times: Vec<i64> = vcd.changes_in(clk, 5..20).map(|state| state.time).collect();
+    assert_eq!(times, vec![5, 10, 15]);
+    assert_eq!(vcd.changes_in(clk, 20..20).count(), 0);
+    assert_eq!(vcd.changes_in(vcd.signals.len(), 0..100).count(), 0);
+    assert_eq!(vcd.try_value_at(clk, -1), Ok(None));
+    assert!(vcd.try_value_at(vcd.signals.len(), 0).is_err());
+    assert!(vcd.try_changes_in(vcd.signals.len(), 0..100).is_err());
+    assert!(vcd.try_next_edge(vcd.signals.len(), 0, Edge::Any).is_err());
+    assert!(vcd.try_summarize(vcd.signals.len(), 0..100, 4).is_err());
+
+    assert_eq!(vcd.next_edge(clk, 5, Edge::Rising), Some(15));
+    assert_eq!(vcd.next_edge(clk, 4, Edge::Rising), Some(5));
+    assert_eq!(vcd.next_edge(clk, 5, Edge::Falling), Some(10));
+    assert_eq!(vcd.prev_edge(clk, 15, Edge::Rising), Some(5));
+    assert_eq!(vcd.prev_edge(clk, 5, Edge::Any), None);
+    // 1 -> x is neither rising nor falling, x -> 1 is rising
+    assert_eq!(vcd.next_edge(rst_n, 10, Edge::Any), Some(42));
+    assert_eq!(vcd.next_edge(rst_n, 10, Edge::Rising), Some(44));
+    assert_eq!(vcd.next_edge(rst_n, 44, Edge::Any), None);
+    // Rewriting the same value is not an edge
+    assert_eq!(vcd.next_edge(overflow, 45, Edge::Any), Some(48));
+    assert_eq!(vcd.next_edge(overflow, 48, Edge::Rising), None);
+
+    let count = vcd.signals_by_id["#"];
+    let snapshot: Vec<String> = vcd
+        .snapshot(&[clk, rst_n, overflow, count], 45)
+        .into_iter()
+        .map(|value| value.unwrap().to_hex_string())
+        .collect();
+    assert_eq!(snapshot, vec!["1", "1", "1", "0"]);
+    // Edges of a vector are the ones of its least significant bit
+    assert_eq!(vcd.next_edge(count, 10, Edge::Rising), Some(15));
+    assert_eq!(vcd.next_edge(count, 15, Edge::Rising), Some(35));
+    assert_eq!(vcd.next_edge(count, 15, Edge::Any), Some(25));
+}
+
+#[test]
+fn test_compressed_storage() {
+    // 1000 changes span several blocks, and 3 bit values straddle bytes
+    let mut content = String::from(
+        "$scope module top $end\n$var wire 1 ! clk $end\n$var wire 3 \" bus $end\n\
+         $upscope $end\n$enddefinitions $end\n#0\n$dumpvars\nb0 !\nb000 \"\n$end\n",
+    );
+    for t in 1..1000 {
+        content += &format!("#{}\nb{} !\nb{:03b} \"\n", t * 7, t % 2, t % 8);
+    }
+    let dump = TempDump::new("blocks.vcd", &content);
+    let path = dump.path();
+    let vcd = index(Configuration {
+        in_file: path,
+        separator: ' ',
+    })
+    .unwrap();
+    let clk = vcd.signals_by_id["!"];
+    let bus = vcd.signals_by_id["\""];
+    for t in [0, 1, 7, 1791, 1792, 1793, 3584, 6993, 10000] {
+        let change = (t / 7).min(999);
+        assert_eq!(
+            vcd.value_at(bus, t).unwrap().to_u128(),
+            Some(change as u128 % 8),
+            "bus at {}",
+            t
+        );
+        assert_eq!(
+            vcd.value_at(clk, t).unwrap().to_u128(),
+            Some(change as u128 % 2)
+        );
+    }
+    assert_eq!(vcd.changes_in(bus, 1700..1900).count(), 29);
+    assert_eq!(vcd.next_edge(clk, 1785, Edge::Rising), Some(1799));
+    assert_eq!(vcd.prev_edge(clk, 1799, Edge::Rising), Some(1785));
+    assert_eq!(vcd.prev_edge(clk, 1800, Edge::Falling), Some(1792));
+    assert_eq!(vcd.next_edge(clk, 6993, Edge::Any), None);
+
+    let usage = vcd.memory_usage();
+    assert_eq!(usage.changes, 2000);
+    // One byte per time difference, a quarter of a byte per bit
+    assert_eq!(usage.time_bytes, 2000);
+    assert_eq!(usage.value_bytes, 250 + 750);
+    assert_eq!(usage.unpacked_bytes, 1000 * 16 + 3000 * 16);
+    assert!(usage.bytes_per_change() < 2.0);
+    assert_eq!(vcd.signal_memory_usage(bus).unwrap().value_bytes, 750);
+}
+
+#[test]
+fn test_database() {
+    let content = fs::read_to_string("tests/files/counter.vcd").unwrap();
+    let dump = TempDump::new("database.vcd", &content);
+    let path = dump.path();
+    let database = database_path(&path);
+    let configuration = || Configuration {
+        in_file: path.clone(),
+        separator: ' ',
+    };
+
+    // The first open indexes the dump and saves the database
+    let indexed = open(configuration()).unwrap();
+    assert!(std::path::Path::new(&database).exists());
+    assert!(indexed.memory_usage().changes > 0);
+
+    // The next one reads it, loading the changes of a signal on first use
+    let loaded = open(configuration()).unwrap();
+    assert_eq!(loaded.memory_usage().changes, 0);
+    assert_eq!(loaded.timescale, indexed.timescale);
+    assert_eq!(loaded.signals.len(), indexed.signals.len());
+    let count = loaded.find_by_path("top.cnt.count").unwrap();
+    assert_eq!(count, indexed.find_by_path("top.cnt.count").unwrap());
+    let Node::Signal(count) = count else {
+        panic!("count is not a signal")
+    };
+    assert_eq!(
+        loaded.changes_in(count, 0..100).collect::<Vec<_>>(),
+        indexed.changes_in(count, 0..100).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        loaded.memory_usage().changes,
+        indexed.signal_memory_usage(count).unwrap().changes
+    );
+    for signal in 0..indexed.signals.len() {
+        loaded.load_signal(signal).unwrap();
+        assert_eq!(loaded.value_at(signal, 30), indexed.value_at(signal, 30));
+    }
+    assert!(loaded.load_signal(loaded.signals.len()).is_err());
+
+    // A database saved from a loaded index is the same
+    loaded.save(&database).unwrap();
+    let reloaded = VCD::load(&database, &configuration()).unwrap();
+    assert_eq!(reloaded.path(Node::Signal(count)), "top.cnt.count");
+    assert_eq!(reloaded.value_at(count, 30), indexed.value_at(count, 30));
+
+    // A changed dump is indexed again
+    fs::write(&path, content.replace("1ns", "1ps")).unwrap();
+    assert!(VCD::load(&database, &configuration())
+        .unwrap_err()
+        .contains("changed"));
+    assert_eq!(
+        open(configuration()).unwrap().timescale.as_deref(),
+        Some("1ps")
+    );
+    assert_eq!(
+        VCD::load(&database, &configuration())
+            .unwrap()
+            .timescale
+            .as_deref(),
+        Some("1ps")
+    );
+
+    // So is one with a damaged database
+    let mut bytes = fs::read(&database).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    fs::write(&database, &bytes).unwrap();
+    let damaged = VCD::load(&database, &configuration()).unwrap();
+    assert!((0..damaged.signals.len()).any(|signal| damaged.load_signal(signal).is_err()));
+    bytes[12] ^= 0xff;
+    fs::write(&database, &bytes).unwrap();
+    assert_eq!(
+        VCD::load(&database, &configuration()).unwrap_err(),
+        "Database damaged"
+    );
+    // Nothing is allocated from a damaged header length
+    for byte in 20..28 {
+        let mut damaged = bytes.clone();
+        damaged[byte] ^= 0x80;
+        fs::write(&database, &damaged).unwrap();
+        assert!(VCD::load(&database, &configuration()).is_err());
+    }
+    fs::write(&database, &bytes).unwrap();
+    bytes[8] = 0;
+    fs::write(&database, &bytes).unwrap();
+    assert!(VCD::load(&database, &configuration())
+        .unwrap_err()
+        .starts_with("Database version"));
+    let reopened = open(configuration()).unwrap();
+    assert!(reopened.memory_usage().changes > 0);
+    assert_eq!(reopened.value_at(count, 30), indexed.value_at(count, 30));
+}
+
+#[test]
+fn test_summaries() {
+    // A counter with x every 100 changes and a 70 bit bus, over several pyramid levels
+    let mut content = String::from(
+        "$scope module top $end\n$var wire 8 ! cnt $end\n$var wire 70 \" wide $end\n\
+         $upscope $end\n$enddefinitions $end\n#0\n$dumpvars\nb0 !\nb1 \"\n$end\n",
+    );
+    for t in 1..20000 {
+        match t % 100 {
+            0 => content += &format!("#{}\nbx !\n", t * 10),
+            _ => content += &format!("#{}\nb{:b} !\n", t * 10, (t * 7) % 256),
+        }
+    }
+    content += "#199995\nb1 \"\n";
+    content += &format!("b1{} \"\n", "0".repeat(69));
+    let dump = TempDump::new("summaries.vcd", &content);
+    let path = dump.path();
+    let configuration = || Configuration {
+        in_file: path.clone(),
+        separator: ' ',
+    };
+    let vcd = index(configuration()).unwrap();
+    let cnt = vcd.signals_by_id["!"];
+    let wide = vcd.signals_by_id["\""];
+
+    // Same as walking every change
+    let expected = |range: std::ops::Range<i64>| {
+        let mut held: Vec<Value> = vcd.value_at(cnt, range.start).into_iter().collect();
+        let changes: Vec<State> = vcd.changes_in(cnt, range.clone()).collect();
+        if changes
+            .first()
+            .is_some_and(|change| change.time == range.start)
+        {
+            held.clear();
+        }
+        held.extend(changes.iter().map(|change| change.value.clone()));
+        let numbers: Vec<u128> = held.iter().filter_map(Value::to_u128).collect();
+        (
+            changes.len(),
+            numbers.iter().min().copied(),
+            numbers.iter().max().copied(),
+            held.iter().any(|value| !value.is_known()),
+        )
+    };
+    for (range, buckets) in [
+        (0..200000, 1),
+        (0..200000, 7),
+        (-500..3000, 9),
+        (12345..187654, 3),
+        (1000..1010, 10),
+        (5000..5010, 1),
+    ] {
+        let summaries = vcd.summarize(cnt, range.clone(), buckets);
+        assert_eq!(summaries.len(), buckets);
+        assert_eq!(summaries[0].range.start, range.start);
+        assert_eq!(summaries[buckets - 1].range.end, range.end);
+        for summary in summaries {
+            let (transitions, min, max, unknown) = expected(summary.range.clone());
+            assert_eq!(summary.transitions, transitions, "{:?}", summary.range);
+            assert_eq!(summary.has_transitions(), transitions > 0);
+            assert_eq!(
+                (summary.min, summary.max),
+                (min, max),
+                "{:?}",
+                summary.range
+            );
+            assert_eq!(summary.unknown, unknown, "{:?}", summary.range);
+            assert_eq!(summary.last, vcd.value_at(cnt, summary.range.end - 1));
+        }
+    }
+    // Before the dump there is nothing to show
+    let before = &vcd.summarize(cnt, -100..0, 1)[0];
+    assert_eq!(
+        (before.transitions, before.min, before.last.clone()),
+        (0, None, None)
+    );
+    // A value held through the bucket
+    let quiet = &vcd.summarize(wide, 1000..2000, 1)[0];
+    assert_eq!(
+        (quiet.transitions, quiet.min, quiet.max),
+        (0, Some(1), Some(1))
+    );
+    // Values over 128 bits are not numbers, but still transitions
+    let late = &vcd.summarize(wide, 199990..200000, 1)[0];
+    assert_eq!(
+        (late.transitions, late.min, late.max),
+        (2, Some(1), Some(1 << 69))
+    );
+    assert!(vcd.summarize(cnt, 0..100, 0).is_empty());
+    assert!(vcd.summarize(vcd.signals.len(), 0..100, 4).is_empty());
+
+    // The pyramids are saved with the database
+    let database = database_path(&path);
+    vcd.save(&database).unwrap();
+    let loaded = VCD::load(&database, &configuration()).unwrap();
+    assert_eq!(
+        loaded.summarize(cnt, 0..200000, 13),
+        vcd.summarize(cnt, 0..200000, 13)
+    );
+}
+
+#[test]
+fn test_lazy() {
+    let configuration = || Configuration {
+        in_file: "tests/files/counter.vcd".into(),
+        separator: ' ',
+    };
+    let indexed = index(configuration()).unwrap();
+    let vcd = index_lazy(configuration()).unwrap();
+    assert_eq!(vcd.signals.len(), indexed.signals.len());
+    assert_eq!(vcd.timescale, indexed.timescale);
+    assert_eq!(vcd.checkpoints().len(), 1);
+    assert_eq!(vcd.checkpoints()[0].time, -1);
+
+    // Nothing is read before being requested
+    let Some(Node::Signal(count)) = vcd.find_by_path("top.cnt.count") else {
+        panic!("count not found")
+    };
+    assert!(!vcd.is_loaded(count));
+    assert_eq!(vcd.value_at(count, 30), None);
+    assert_eq!(vcd.try_value_at(count, 30), Err("Signal not loaded".into()));
+    assert_eq!(vcd.memory_usage().changes, 0);
+    vcd.wait_for_signals(&[count, 0]).unwrap();
+    assert!(vcd.is_loaded(count) && vcd.is_loaded(0));
+    for time in -1..60 {
+        assert_eq!(vcd.value_at(count, time), indexed.value_at(count, time));
+        assert_eq!(vcd.value_at(0, time), indexed.value_at(0, time));
+    }
+    assert_eq!(vcd.memory_usage().changes, {
+        let count = indexed.signal_memory_usage(count).unwrap().changes;
+        count + indexed.signal_memory_usage(0).unwrap().changes
+    });
+
+    // The others arrive in the background
+    let rest: Vec<usize> = (0..vcd.signals.len()).collect();
+    vcd.request_signals(&rest);
+    let mut received = vec![];
+    while received.len() < rest.len() - 2 {
+        received.extend(vcd.receive_signals().unwrap());
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    assert!(rest.iter().all(|signal| vcd.is_loaded(*signal)));
+    assert!(vcd.receive_signals().unwrap().is_empty());
+    for signal in rest {
+        assert_eq!(
+            vcd.changes_in(signal, 0..100).collect::<Vec<_>>(),
+            indexed.changes_in(signal, 0..100).collect::<Vec<_>>()
+        );
+    }
+
+    // Derived signals extract what they use
+    let mut vcd = index_lazy(configuration()).unwrap();
+    let two = vcd.add_derived("two", "top.cnt.count == 2").unwrap();
+    assert!(vcd.is_loaded(count));
+    for time in 0..60 {
+        let expected = indexed
+            .value_at(count, time)
+            .map(|value| value.to_u128() == Some(2));
+        let derived = vcd
+            .value_at(two, time)
+            .map(|value| value.to_u128() == Some(1));
+        assert_eq!(derived, expected);
+    }
+}
+
+#[test]
+fn test_lazy_checkpoints() {
+    let mut content = String::from(
+        "$scope module top $end\n$var wire 1 ! clk $end\n$var wire 17 \" bus $end\n\
+         $upscope $end\n$enddefinitions $end\n$dumpvars\n0!\nb0 \"\n$end\n",
+    );
+    for t in 1..100000 {
+        content += &format!("#{}\n{}!\nb{:b} \"\n", t * 5, t % 2, t);
+    }
+    content += "#500000\nb11 !\n";
+    let dump = TempDump::new("lazy.vcd", &content);
+    let path = dump.path();
+    let vcd = index_lazy(Configuration {
+        in_file: path,
+        separator: ' ',
+    })
+    .unwrap();
+
+    // A checkpoint is the start of a timestamp line after every megabyte
+    let checkpoints = vcd.checkpoints();
+    assert_eq!(
+        checkpoints.len(),
+        content.len() / CHECKPOINT_BYTES as usize + 1
+    );
+    for pair in checkpoints.windows(2) {
+        assert!(pair[1].time > pair[0].time);
+        assert!(pair[1].offset >= pair[0].offset + CHECKPOINT_BYTES);
+    }
+    for checkpoint in &checkpoints[1..] {
+        let offset = checkpoint.offset as usize;
+        assert!(content[offset..].starts_with(&format!("#{}\n", checkpoint.time)));
+        assert_eq!(content[..offset].matches('\n').count(), checkpoint.line);
+    }
+
+    let bus = vcd.signals_by_id["\""];
+    vcd.wait_for_signals(&[bus]).unwrap();
+    assert_eq!(vcd.value_at(bus, 0).unwrap().to_u128(), Some(0));
+    assert_eq!(vcd.value_at(bus, 250003).unwrap().to_u128(), Some(50000));
+    assert_eq!(vcd.changes_in(bus, -1..1000000).count(), 100000);
+    // The parts read from every checkpoint are joined without a seam
+    for (t, state) in vcd.changes_in(bus, -1..1000000).enumerate() {
+        let time = if t == 0 { -1 } else { t as i64 * 5 };
+        assert_eq!((state.time, state.value.to_u128()), (time, Some(t as u128)));
+    }
+    assert!(!vcd.is_loaded(vcd.signals_by_id["!"]));
+
+    // Errors come with their line, and the signal can be asked for again
+    let clk = vcd.signals_by_id["!"];
+    let line = content.lines().count();
+    let error = format!("Line {}: 2 values for 1 bits of signal !", line);
+    assert_eq!(vcd.wait_for_signals(&[clk]), Err(error.clone()));
+    assert!(!vcd.is_loaded(clk));
+    vcd.request_signals(&[clk]);
+    loop {
+        match vcd.receive_signals() {
+            Ok(signals) if signals.is_empty() => std::thread::yield_now(),
+            result => {
+                assert_eq!(result, Err(error));
+                break;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_live() {
+    let dump = TempDump::new(
+        "live.vcd",
+        "$timescale 1ns $end\n$scope module top $end\n$var wire 1 ! clk $end\n\
+         $var wire 4 \" bus $end\n$upscope $end\n$enddefinitions $end\n\
+         #0\n0!\nb0000 \"\n#10\n1!\nb10",
+    );
+    let path = dump.path();
+    let append = |text: &str| {
+        use std::io::Write;
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    };
+    let mut live = index_live(Configuration {
+        in_file: path.clone(),
+        separator: ' ',
+    })
+    .unwrap();
+    let end_times = live.subscribe();
+    let bus = live.vcd().signals_by_id["\""];
+    let clk = live.vcd().signals_by_id["!"];
+    assert_eq!(live.end_time(), Some(10));
+    assert_eq!(live.vcd().value_at(clk, 10).unwrap().to_string(), "1");
+    // The half written change is not read yet
+    assert_eq!(live.vcd().value_at(bus, 10).unwrap().to_u128(), Some(0));
+    assert_eq!(live.update(), Ok(None));
+
+    append("11 \"\n#2");
+    assert_eq!(live.update(), Ok(None));
+    assert_eq!(live.vcd().value_at(bus, 10).unwrap().to_u128(), Some(11));
+    // Nor is the half written timestamp
+    append("0\n0!\n");
+    assert_eq!(live.update(), Ok(Some(20)));
+    assert_eq!(live.vcd().value_at(clk, 25).unwrap().to_string(), "0");
+    append("#30\n#40\n1!\n");
+    assert_eq!(live.update(), Ok(Some(40)));
+    assert_eq!(end_times.try_iter().collect::<Vec<_>>(), vec![20, 40]);
+    assert_eq!(live.vcd().changes_in(clk, 0..100).count(), 4);
+
+    // A bad line is reported once, the lines around it are kept
+    append("b11111 \"\n#50\n0!\n");
+    assert!(live.update().unwrap_err().contains("5 values for 4 bits"));
+    assert_eq!(live.update(), Ok(Some(50)));
+    drop(end_times);
+    append("#60\n");
+    assert_eq!(live.update(), Ok(Some(60)));
+    let vcd = live.into_vcd();
+    assert_eq!(vcd.value_at(clk, 60).unwrap().to_string(), "0");
+    assert_eq!(vcd.timescale.as_deref(), Some("1ns"));
+
+    // The saved index is used until the dump grows again
+    let configuration = || Configuration {
+        in_file: path.clone(),
+        separator: ' ',
+    };
+    vcd.save(&database_path(&path)).unwrap();
+    assert!(VCD::load(&database_path(&path), &configuration()).is_ok());
+    append("#70\n");
+    assert!(VCD::load(&database_path(&path), &configuration()).is_err());
+
+    // A header section is read once written to its end
+    let dump = TempDump::new("live_date.vcd", "$date\n    Mon Oct");
+    let mut live = index_live(Configuration {
+        in_file: dump.path(),
+        separator: ' ',
+    })
+    .unwrap();
+    assert_eq!(live.vcd().date, None);
+    fs::write(
+        dump.path(),
+        "$date\n    Mon Oct 12 10:00:00 2026\n$end\n$scope module top $end\n",
+    )
+    .unwrap();
+    assert_eq!(live.update(), Ok(None));
+    assert_eq!(live.vcd().date.as_deref(), Some("Mon Oct 12 10:00:00 2026"));
+    assert_eq!(live.vcd().hierarchy.len(), 2);
+}
+
+#[test]
+fn test_derived() {
+    let mut vcd = index_file("derived.vcd").unwrap();
+    let mut derive = |name: &str, expression: &str| {
+        let signal = vcd.add_derived(name, expression).unwrap();
+        let changes: Vec<(i64, String)> = vcd
+            .changes_in(signal, 0..100)
+            .map(|state| (state.time, state.value.to_hex_string()))
+            .collect();
+        (vcd.signals[signal].width, changes)
+    };
+    let changes = |changes: &[(i64, &str)]| -> Vec<(i64, String)> {
+        changes
+            .iter()
+            .map(|(time, value)| (*time, value.to_string()))
+            .collect()
+    };
+
+    // Both inputs changing at 50 make a single change
+    assert_eq!(
+        derive("handshake", "top.valid && top.ready"),
+        (1, changes(&[(0, "0"), (20, "1"), (50, "0")]))
+    );
+    assert_eq!(
+        derive("page", "top.addr[31:12]"),
+        (20, changes(&[(0, "12345")]))
+    );
+    assert_eq!(
+        derive("magic", "top.data == 0xDEAD"),
+        (1, changes(&[(0, "0"), (20, "1"), (40, "x")]))
+    );
+    // Arithmetic wraps at the width of the widest operand
+    assert_eq!(
+        derive("next", "top.count + 1"),
+        (8, changes(&[(0, "01"), (30, "00")]))
+    );
+    assert_eq!(
+        derive("flags", "{top.valid, top.ready, 2'b0x}"),
+        (4, changes(&[(0, "x"), (10, "x"), (20, "x"), (50, "x")]))
+    );
+    // 0 and 1 win over x in bitwise and logical operators
+    assert_eq!(
+        derive("masked", "top.data & 16'h00ff | 16'hff00"),
+        (16, changes(&[(0, "ff00"), (20, "ffad"), (40, "ffxx")]))
+    );
+    assert_eq!(
+        derive("any", "top.bus[1] || top.valid"),
+        (1, changes(&[(0, "x"), (10, "1"), (50, "x")]))
+    );
+    assert_eq!(derive("bit", "top.bus[1]"), (1, changes(&[(0, "x")])));
+    // Precedence and widths follow Verilog
+    assert_eq!(
+        derive("seven", "top.valid + 2 * 3 == 7 && ~top.ready"),
+        (1, changes(&[(0, "0"), (10, "1"), (20, "0")]))
+    );
+    assert_eq!(
+        derive("shifted", "(top.count >> 4) - 1 + -top.valid"),
+        (8, changes(&[(0, "ff"), (10, "fe"), (30, "0d"), (50, "0e")]))
+    );
+    assert_eq!(
+        derive(
+            "parity",
+            "^top.addr[7:0] ^ &top.count[1:0] ^ !|top.data[3:0]"
+        ),
+        (1, changes(&[(0, "1"), (20, "0"), (30, "1"), (40, "x")]))
+    );
+
+    // The derived signals answer every query
+    let next = vcd.find_by_path("next").unwrap();
+    assert_eq!(vcd.path(next), "next");
+    let Node::Signal(next) = next else {
+        panic!("next is not a signal")
+    };
+    assert_eq!(vcd.value_at(next, 35).unwrap().to_u128(), Some(0));
+    let Some(Node::Signal(handshake)) = vcd.find_by_path("handshake") else {
+        panic!("handshake not found")
+    };
+    assert_eq!(vcd.next_edge(handshake, 0, Edge::Rising), Some(20));
+    assert_eq!(vcd.summarize(handshake, 0..60, 2)[1].transitions, 1);
+    assert_eq!(vcd.search_glob("ha*").len(), 1);
+
+    for (expression, error) in [
+        ("top.valid &&", "Expected a value at column 13"),
+        ("top.nothing + 1", "Unknown signal top.nothing at column 1"),
+        ("top + 1", "top is a scope at column 1"),
+        (
+            "top.addr[32:0]",
+            "Bits [32:0] of a 32 bit value at column 14",
+        ),
+        ("(top.valid", "Expected ) at column 11"),
+        ("top.valid 1", "Unexpected text at column 11"),
+        ("4'q1 & top.valid", "Invalid number 4'q1 at column 1"),
+        ("1 + 2", "1 + 2 uses no signal"),
+    ] {
+        assert_eq!(vcd.add_derived("error", expression), Err(error.into()));
+    }
+    assert_eq!(
+        vcd.add_derived("next", "top.valid"),
+        Err("next already exists".into())
+    );
+    assert!(vcd.add_derived("a.b", "top.valid").is_err());
+}
@@ -2,7 +2,7 @@ use futures::executor::block_on;
 use std::{
     collections::HashMap,
     fs::File,
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, Seek, SeekFrom},
     rc::Rc,
     str::SplitAsciiWhitespace,
 };
@@ -21,9 +21,13 @@ pub struct VCDFile {
     reader: BufReader<File>,
     line: String,
     lineno: usize,
+    bytes_read: u64,
+    total_bytes: u64,
     part: Part,
     separator: char,
     signals: HashMap<Rc<str>, Signal>,
+    /// Leave a last line without its newline for later
+    follow: bool,
 }
 
 enum Part {
@@ -80,27 +84,75 @@ pub struct LineInfo {
 
 impl VCDFile {
     pub fn new(configuration: Configuration) -> Result<Self, String> {
-        let reader =
-            BufReader::new(File::open(configuration.in_file).map_err(|err| err.to_string())?);
+        let file = File::open(configuration.in_file).map_err(|err| err.to_string())?;
+        let total_bytes = file.metadata().map_or(0, |metadata| metadata.len());
         Ok(VCDFile {
-            reader,
+            reader: BufReader::new(file),
             line: Default::default(),
             lineno: 0,
+            bytes_read: 0,
+            total_bytes,
             part: Part::Declarations,
             separator: configuration.separator,
             signals: HashMap::new(),
+            follow: false,
         })
     }
 
+    /// Bytes of the file read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Size of the file when it was opened.
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    /// Lines of the file read so far.
+    pub fn line_number(&self) -> usize {
+        self.lineno
+    }
+
+    /// Continues reading the value changes at `offset`, the start of a line after `lines`
+    /// lines, as found with [`VCDFile::bytes_read`] and [`VCDFile::line_number`] earlier.
+    pub fn seek_values(&mut self, offset: u64, lines: usize) -> Result<(), String> {
+        self.reader
+            .seek(SeekFrom::Start(offset))
+            .map_err(|err| err.to_string())?;
+        self.bytes_read = offset;
+        self.lineno = lines;
+        self.part = Part::Changes;
+        Ok(())
+    }
+
+    /// Makes [`VCDFile::visit`] stop before a last line without its newline, or before a
+    /// `$date`, `$version` or `$timescale` section without its `$end`, as happens while the
+    /// dump is being written, so that the next call reads it once complete.
+    pub fn follow(&mut self) {
+        self.follow = true;
+    }
+
+    /// Goes back to `offset`, the start of a line read earlier after `lines` lines.
+    fn rewind(&mut self, offset: u64, lines: usize) -> Result<(), String> {
+        self.reader
+            .seek_relative(offset as i64 - self.bytes_read as i64)
+            .map_err(|err| err.to_string())?;
+        self.bytes_read = offset;
+        self.lineno = lines;
+        Ok(())
+    }
+
     fn read_line(&mut self) -> Result<usize, std::io::Error> {
         self.line.clear();
-        self.lineno += 1;
-        self.reader.read_line(&mut self.line)
+        self.read_line_noclear()
     }
 
     fn read_line_noclear(&mut self) -> Result<usize, std::io::Error> {
         self.lineno += 1;
-        self.reader.read_line(&mut self.line)
+        let read = self.reader.read_line(&mut self.line)?;
+        self.bytes_read += read as u64;
+        Ok(read)
     }
 
     fn unrecognized_symbol(symbol: &str, lineno: usize) -> LineInfo {
@@ -224,48 +276,56 @@ impl VCDFile {
         }
     }
 
-    fn next_declarations(&mut self, line_slice: String) -> Result<LineInfo, std::io::Error> {
+    /// Reads the lines of the section opened by `keyword` up to its `$end`. False when the
+    /// file ends before.
+    fn read_section(&mut self, keyword: &str) -> Result<bool, std::io::Error> {
+        self.line = self.line.replace(keyword, "");
+        while !self.line.contains("$end") {
+            if self.read_line_noclear()? == 0 {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// None for a section cut short by the end of the file while following it.
+    fn next_declarations(
+        &mut self,
+        line_slice: String,
+    ) -> Result<Option<LineInfo>, std::io::Error> {
         let mut split_line = line_slice.split_ascii_whitespace();
-        Ok(LineInfo {
+        let keyword = split_line.next();
+        if let Some(keyword @ ("$date" | "$version" | "$timescale")) = keyword {
+            if !self.read_section(keyword)? {
+                return Ok((!self.follow).then(|| Self::unexpected_eof(self.lineno)));
+            }
+        }
+        Ok(Some(LineInfo {
             line_number: self.lineno,
-            value: match split_line.next() {
+            value: match keyword {
                 Some(string) => match string {
-                    "$date" => {
-                        self.line = self.line.replace("$date", "");
-                        while !self.line.contains("$end") {
-                            self.read_line_noclear()?;
-                        }
-                        LineValue::DateInfo(self.line.clone())
-                    }
-                    "$version" => {
-                        self.line = self.line.replace("$version", "");
-                        while !self.line.contains("$end") {
-                            self.read_line_noclear()?;
-                        }
-                        LineValue::VersionInfo(self.line.clone())
-                    }
-                    "$timescale" => {
-                        self.line = self.line.replace("$timescale", "");
-                        while !self.line.contains("$end") {
-                            self.read_line_noclear()?;
-                        }
-                        LineValue::TimeScaleInfo(self.line.clone())
-                    }
+                    "$date" => LineValue::DateInfo(self.line.clone()),
+                    "$version" => LineValue::VersionInfo(self.line.clone()),
+                    "$timescale" => LineValue::TimeScaleInfo(self.line.clone()),
                     "$scope" => match split_line.next() {
-                        Some(scope_type) => return Ok(self.manage_in_scope(scope_type, split_line)),
-                        None => return Ok(Self::unexpected_eof(self.lineno)),
+                        Some(scope_type) => {
+                            return Ok(Some(self.manage_in_scope(scope_type, split_line)))
+                        }
+                        None => return Ok(Some(Self::unexpected_eof(self.lineno))),
                     },
                     "$upscope" => LineValue::UpScope,
                     "$var" => match split_line.next() {
-                        Some(var_type) => return Ok(self.manage_var_type(var_type, split_line)),
-                        None => return Ok(Self::unexpected_eof(self.lineno)),
+                        Some(var_type) => {
+                            return Ok(Some(self.manage_var_type(var_type, split_line)))
+                        }
+                        None => return Ok(Some(Self::unexpected_eof(self.lineno))),
                     },
                     "$enddefinitions" => {
                         self.part = Part::Initializations;
                         LineValue::EndDefinitions
                     }
                     "$end" => LineValue::Useless,
-                    _ => return Ok(Self::unrecognized_symbol(string, self.lineno)),
+                    _ => return Ok(Some(Self::unrecognized_symbol(string, self.lineno))),
                 },
                 None => {
                     unreachable!(
@@ -273,7 +333,7 @@ impl VCDFile {
                     )
                 }
             },
-        })
+        }))
     }
 
     fn next_initializations(&mut self, line_slice: String) -> LineInfo {
@@ -285,41 +345,7 @@ impl VCDFile {
                     self.part = Part::Changes;
                     LineValue::EndInitializations
                 }
-                _ => {
-                    if let Some(time_str) = line_slice.strip_prefix('#') {
-                        LineValue::Timestamp(time_str.parse().unwrap())
-                    } else {
-                        let mut starts_p = false;
-                        let mut values = &line_slice[0..];
-                        if line_slice.starts_with('b') {
-                            values = values.strip_prefix('b').unwrap();
-                        } else if line_slice.starts_with('p') {
-                            starts_p = true;
-                            values = values.strip_prefix('p').unwrap();
-                        }
-                        if self.separator == ' ' && line_slice.find(' ').is_none() {
-                            // In this case there is only 1 value, the rest is the ID
-                            let signal_id = &line_slice[1..];
-                            let value = &line_slice[0..0];
-                            LineValue::Change(Change {
-                                signal_id: String::from(signal_id),
-                                values: value.into(),
-                            })
-                        } else {
-                            let mut line_parts = values.split(self.separator);
-                            let values = line_parts.next().unwrap();
-                            if starts_p && self.separator == ' ' {
-                                line_parts.next().unwrap();
-                                line_parts.next().unwrap();
-                            }
-                            let signal_id = line_parts.next().unwrap();
-                            LineValue::Change(Change {
-                                signal_id: String::from(signal_id),
-                                values: values.into(),
-                            })
-                        }
-                    }
-                }
+                _ => self.next_value(&line_slice),
             },
         }
     }
@@ -327,40 +353,71 @@ impl VCDFile {
     fn next_changes(&mut self, line_slice: String) -> LineInfo {
         LineInfo {
             line_number: self.lineno,
-            value: if let Some(time_str) = line_slice.strip_prefix('#') {
-                LineValue::Timestamp(time_str.parse().unwrap())
-            } else {
-                let mut starts_p = false;
-                let mut values = &line_slice[0..];
-                if line_slice.starts_with('b') {
-                    values = values.strip_prefix('b').unwrap();
-                } else if line_slice.starts_with('b') {
-                    starts_p = true;
-                    values = values.strip_prefix('p').unwrap();
+            value: self.next_value(&line_slice),
+        }
+    }
+
+    fn next_value(&self, line_slice: &str) -> LineValue {
+        let value = match line_slice.strip_prefix('#') {
+            Some(time_str) => parse_timestamp(time_str).map(LineValue::Timestamp),
+            None if line_slice.starts_with('$') => Ok(LineValue::Useless),
+            None => parse_change(line_slice, self.separator).map(|(signal_id, values)| {
+                LineValue::Change(Change {
+                    signal_id: String::from(signal_id),
+                    values: values.into(),
+                })
+            }),
+        };
+        value.unwrap_or_else(LineValue::ParsingError)
+    }
+
+    /// Reads the rest of the file, calling `visitor` for every line in order. Stops at the
+    /// first error, which is returned with its line number, or when
+    /// `VcdVisitor::on_progress` returns false.
+    pub fn visit<V: VcdVisitor>(&mut self, visitor: &mut V) -> Result<(), String> {
+        while visitor.on_progress(self.bytes_read, self.total_bytes) {
+            let read = self.read_line().map_err(|err| err.to_string())?;
+            if read == 0 {
+                break;
+            }
+            let start = (self.bytes_read - read as u64, self.lineno - 1);
+            if self.follow && !self.line.ends_with('\n') {
+                self.rewind(start.0, start.1)?;
+                break;
+            }
+            let line_slice = self.line.trim();
+            if line_slice.is_empty() {
+                continue;
+            }
+            let result = match self.part {
+                Part::Declarations => {
+                    let line_slice = line_slice.to_owned();
+                    let info = self
+                        .next_declarations(line_slice)
+                        .map_err(|err| err.to_string())?;
+                    let Some(info) = info else {
+                        // Read again once written to its end
+                        self.rewind(start.0, start.1)?;
+                        break;
+                    };
+                    info.value.visit(visitor)
                 }
-                if self.separator == ' ' && line_slice.find(' ').is_none() {
-                    // In this case there is only 1 value, the rest is the ID
-                    let signal_id = &line_slice[1..];
-                    let value = &line_slice[0..0];
-                    LineValue::Change(Change {
-                        signal_id: String::from(signal_id),
-                        values: value.into(),
-                    })
-                } else {
-                    let mut line_parts = values.split(self.separator);
-                    let values = line_parts.next().unwrap();
-                    if starts_p && self.separator == ' ' {
-                        line_parts.next().unwrap();
-                        line_parts.next().unwrap();
-                    }
-                    let signal_id = line_parts.next().unwrap();
-                    LineValue::Change(Change {
-                        signal_id: String::from(signal_id),
-                        values: values.into(),
-                    })
+                Part::Initializations if line_slice == "$end" => {
+                    self.part = Part::Changes;
+                    visitor.on_end_initializations()
                 }
-            },
+                Part::Initializations | Part::Changes => match line_slice.strip_prefix('#') {
+                    Some(time_str) => {
+                        parse_timestamp(time_str).and_then(|time| visitor.on_timestamp(time))
+                    }
+                    None if line_slice.starts_with('$') => Ok(()),
+                    None => parse_change(line_slice, self.separator)
+                        .and_then(|(signal_id, values)| visitor.on_change(signal_id, values)),
+                },
+            };
+            result.map_err(|err| format!("Line {}: {}", self.lineno, err))?;
         }
+        Ok(())
     }
 
     async fn next_line(&mut self) -> Result<Option<String>, std::io::Error> {
@@ -384,9 +441,13 @@ impl Iterator for VCDFile {
             Ok(line_slice) => {
                 if let Some(slice) = line_slice {
                     match self.part {
-                        Part::Declarations => {
-                            Some(self.next_declarations(slice).map_err(|err| err.to_string()))
-                        }
+                        Part::Declarations => Some(
+                            self.next_declarations(slice)
+                                .map(|info| {
+                                    info.unwrap_or_else(|| Self::unexpected_eof(self.lineno))
+                                })
+                                .map_err(|err| err.to_string()),
+                        ),
                         Part::Initializations => Some(Ok(self.next_initializations(slice))),
                         Part::Changes => Some(Ok(self.next_changes(slice))),
                     }
@@ -399,6 +460,108 @@ impl Iterator for VCDFile {
     }
 }
 
+/// Consumer of the lines of a dump, driven by [`VCDFile::visit`]. Every method has an
+/// empty default, so visitors only implement what they use. An error stops the reading.
+pub trait VcdVisitor {
+    fn on_date(&mut self, _date: &str) -> Result<(), String> {
+        Ok(())
+    }
+    fn on_version(&mut self, _version: &str) -> Result<(), String> {
+        Ok(())
+    }
+    fn on_timescale(&mut self, _timescale: &str) -> Result<(), String> {
+        Ok(())
+    }
+    fn on_scope(&mut self, _name: &str) -> Result<(), String> {
+        Ok(())
+    }
+    fn on_upscope(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+    fn on_signal(&mut self, _signal: Signal) -> Result<(), String> {
+        Ok(())
+    }
+    fn on_end_definitions(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+    fn on_end_initializations(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+    fn on_timestamp(&mut self, _time: usize) -> Result<(), String> {
+        Ok(())
+    }
+    /// `values` holds one character per bit, most significant first.
+    fn on_change(&mut self, _signal_id: &str, _values: &[u8]) -> Result<(), String> {
+        Ok(())
+    }
+    /// Called before every line with the bytes read so far. Returning false stops the
+    /// reading without an error.
+    fn on_progress(&mut self, _read: u64, _total: u64) -> bool {
+        true
+    }
+}
+
+impl LineValue {
+    fn visit<V: VcdVisitor>(self, visitor: &mut V) -> Result<(), String> {
+        match self {
+            LineValue::Signal(signal) => visitor.on_signal(signal),
+            LineValue::Timestamp(time) => visitor.on_timestamp(time),
+            LineValue::Change(change) => visitor.on_change(&change.signal_id, &change.values),
+            LineValue::DateInfo(date) => visitor.on_date(header_text(&date)),
+            LineValue::VersionInfo(version) => visitor.on_version(header_text(&version)),
+            LineValue::TimeScaleInfo(timescale) => visitor.on_timescale(header_text(&timescale)),
+            LineValue::InScope(name) => visitor.on_scope(&name),
+            LineValue::UpScope => visitor.on_upscope(),
+            LineValue::ParsingError(err) => Err(err),
+            LineValue::EndDefinitions => visitor.on_end_definitions(),
+            LineValue::EndInitializations => visitor.on_end_initializations(),
+            LineValue::Dumpports | LineValue::Useless => Ok(()),
+        }
+    }
+}
+
+/// Text of a `$date`, `$version` or `$timescale` section, without the keywords.
+fn header_text(text: &str) -> &str {
+    text.trim().trim_end_matches("$end").trim()
+}
+
+/// Time of a timestamp line, given without its `#`.
+pub fn parse_timestamp(time_str: &str) -> Result<usize, String> {
+    time_str
+        .parse()
+        .map_err(|_| format!("Invalid timestamp #{}", time_str))
+}
+
+/// Splits a value change into the signal id and its values, e.g. `b01 #` or `1!`.
+fn parse_change(line_slice: &str, separator: char) -> Result<(&str, &[u8]), String> {
+    if separator == ' ' && !line_slice.contains(' ') {
+        // In this case there is only 1 value, the rest is the ID
+        let value_len = line_slice.chars().next().map_or(0, char::len_utf8);
+        let (value, signal_id) = line_slice.split_at(value_len);
+        if signal_id.is_empty() {
+            return Err(format!("Missing signal id in {}", line_slice));
+        }
+        return Ok((signal_id, value.as_bytes()));
+    }
+    let (values, starts_p) = match line_slice.strip_prefix('b') {
+        Some(values) => (values, false),
+        None => match line_slice.strip_prefix('p') {
+            Some(values) => (values, true),
+            None => (line_slice, false),
+        },
+    };
+    let mut line_parts = values.split(separator);
+    let values = line_parts.next().unwrap_or_default();
+    if starts_p && separator == ' ' {
+        line_parts.next();
+        line_parts.next();
+    }
+    match line_parts.next() {
+        Some(signal_id) if !signal_id.is_empty() => Ok((signal_id, values.as_bytes())),
+        _ => Err(format!("Missing signal id in {}", line_slice)),
+    }
+}
+
 impl From<u8> for SignalValue {
     fn from(val: u8) -> Self {
         match val {
@@ -0,0 +1,120 @@
+//! Throughput of the analysis on a generated dump, against the channel the translator used
+//! to receive the lines from. Run with `cargo bench -p vcd-statistical-analysis`; set
+//! `OXYVCD_BENCH_DUMP` to a path to keep the generated dump.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use vcd_reader::{VCDFile, VcdVisitor};
+use vcd_statistical_analysis::{perform_analysis, Configuration};
+
+const TIMESTAMPS: usize = 500_000;
+const RUNS: usize = 3;
+
+fn generate(path: &str) -> u64 {
+    let mut out = BufWriter::new(File::create(path).unwrap());
+    writeln!(out, "$timescale 1ns $end").unwrap();
+    writeln!(out, "$scope module top $end").unwrap();
+    writeln!(out, "$var wire 1 ! clk $end").unwrap();
+    writeln!(out, "$var wire 1 \" rst_n $end").unwrap();
+    writeln!(out, "$var wire 16 # count $end").unwrap();
+    writeln!(out, "$var wire 1 $ overflow $end").unwrap();
+    writeln!(out, "$upscope $end").unwrap();
+    writeln!(out, "$enddefinitions $end").unwrap();
+    writeln!(out, "#0\n$dumpvars\nb0 !\nb0 \"\nb{:016b} #\nb0 $\n$end", 0).unwrap();
+    for t in 1..TIMESTAMPS {
+        writeln!(out, "#{}\nb{} !", t * 5, t % 2).unwrap();
+        if t % 2 == 0 {
+            writeln!(out, "b{:016b} #", (t / 2) % 65536).unwrap();
+        }
+        if t == 3 {
+            writeln!(out, "b1 \"").unwrap();
+        }
+    }
+    out.flush().unwrap();
+    std::fs::metadata(path).unwrap().len()
+}
+
+fn best_of(name: &str, bytes: u64, run: impl Fn()) {
+    let best = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or(Duration::ZERO);
+    println!(
+        "{:<40} {:>8.3} s {:>8.1} MB/s",
+        name,
+        best.as_secs_f64(),
+        bytes as f64 / best.as_secs_f64() / 1e6
+    );
+}
+
+struct Count(usize);
+
+impl VcdVisitor for Count {
+    fn on_change(&mut self, _: &str, _: &[u8]) -> Result<(), String> {
+        self.0 += 1;
+        Ok(())
+    }
+}
+
+fn main() {
+    // The dump is kept when written to OXYVCD_BENCH_DUMP, to time the CLI on it
+    let kept = std::env::var("OXYVCD_BENCH_DUMP").ok();
+    let path = kept.clone().unwrap_or_else(|| {
+        let name = format!("oxyvcd_bench_{}.vcd", std::process::id());
+        std::env::temp_dir().join(name).to_str().unwrap().into()
+    });
+    let path = path.as_str();
+    let bytes = generate(path);
+    let reader_config = || vcd_reader::Configuration {
+        in_file: path,
+        separator: ' ',
+    };
+
+    best_of("reader, channel to a thread", bytes, || {
+        let (tx, rx) = mpsc::sync_channel(1000000);
+        let th = thread::spawn(move || rx.into_iter().count());
+        for info in VCDFile::new(reader_config()).unwrap() {
+            tx.send(info.unwrap()).unwrap();
+        }
+        drop(tx);
+        th.join().unwrap();
+    });
+    best_of("reader, visitor", bytes, || {
+        let mut count = Count(0);
+        VCDFile::new(reader_config())
+            .unwrap()
+            .visit(&mut count)
+            .unwrap();
+    });
+    best_of("analysis", bytes, || {
+        perform_analysis(Configuration {
+            in_file: path.into(),
+            separator: ' ',
+            ..Default::default()
+        })
+        .unwrap();
+    });
+    best_of("analysis with statistics", bytes, || {
+        perform_analysis(Configuration {
+            in_file: path.into(),
+            separator: ' ',
+            statistics: true,
+            xz_analysis: true,
+            ..Default::default()
+        })
+        .unwrap();
+    });
+    if kept.is_none() {
+        let _ = std::fs::remove_file(path);
+    }
+}
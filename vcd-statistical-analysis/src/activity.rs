@@ -0,0 +1,122 @@
+use vcd_reader::SignalValue;
+
+use crate::State;
+
+/// Switching activity of a single bit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Activity {
+    /// Number of 0->1 and 1->0 transitions
+    pub toggles: u64,
+    pub high_time: i64,
+    pub low_time: i64,
+    pub x_time: i64,
+    pub z_time: i64,
+    /// Shortest and longest pulse delimited by two changes. Zero-width pulses are glitches.
+    pub min_pulse: Option<i64>,
+    pub max_pulse: Option<i64>,
+    /// Changes happening at the same timestamp as the previous change of the bit
+    pub glitches: u64,
+    value: SignalValue,
+    since: i64,
+    changed: bool,
+}
+
+impl Activity {
+    pub(crate) fn new(state: State) -> Self {
+        Activity {
+            toggles: 0,
+            high_time: 0,
+            low_time: 0,
+            x_time: 0,
+            z_time: 0,
+            min_pulse: None,
+            max_pulse: None,
+            glitches: 0,
+            value: state.value,
+            since: state.time.max(0),
+            changed: false,
+        }
+    }
+
+    /// Accounts the time spent in the current value up to `time`.
+    fn accumulate(&mut self, time: i64) {
+        let elapsed = time - self.since;
+        match self.value {
+            SignalValue::UP => self.high_time += elapsed,
+            SignalValue::DOWN => self.low_time += elapsed,
+            SignalValue::X => self.x_time += elapsed,
+            SignalValue::Z => self.z_time += elapsed,
+        }
+        self.since = time;
+    }
+
+    pub(crate) fn add_change(&mut self, state: State) {
+        if state.value == self.value {
+            return;
+        }
+        let width = state.time - self.since;
+        if self.changed && width == 0 {
+            self.glitches += 1;
+        } else if self.changed && matches!(self.value, SignalValue::UP | SignalValue::DOWN) {
+            self.min_pulse = Some(self.min_pulse.map_or(width, |min| min.min(width)));
+            self.max_pulse = Some(self.max_pulse.map_or(width, |max| max.max(width)));
+        }
+        if matches!(
+            (self.value, state.value),
+            (SignalValue::UP, SignalValue::DOWN) | (SignalValue::DOWN, SignalValue::UP)
+        ) {
+            self.toggles += 1;
+        }
+        self.accumulate(state.time);
+        self.value = state.value;
+        self.changed = true;
+    }
+
+    /// Closes the last interval at `time`, the end of the dump or of the window.
+    pub(crate) fn finish(&mut self, time: i64) {
+        if time > self.since {
+            self.accumulate(time);
+        }
+    }
+
+    /// Fraction of the known time the bit was high.
+    pub fn duty_cycle(&self) -> Option<f64> {
+        match self.high_time + self.low_time {
+            0 => None,
+            known => Some(self.high_time as f64 / known as f64),
+        }
+    }
+
+    pub(crate) fn to_result_string(&self) -> String {
+        let optional = |value: Option<i64>| value.map_or("-".into(), |value| value.to_string());
+        format!(
+            "{} {} {} {} {} {} {}",
+            self.toggles,
+            self.high_time,
+            self.low_time,
+            self.duty_cycle()
+                .map_or("-".into(), |duty| format!("{:.3}", duty)),
+            optional(self.min_pulse),
+            optional(self.max_pulse),
+            self.glitches
+        )
+    }
+
+    pub(crate) fn to_json_string(&self) -> String {
+        let optional = |value: Option<String>| value.unwrap_or("null".into());
+        format!(
+            "{{\"toggles\":{},\"high_time\":{},\"low_time\":{},\"duty_cycle\":{},\"min_pulse\":{},\"max_pulse\":{},\"glitches\":{}}}",
+            self.toggles,
+            self.high_time,
+            self.low_time,
+            optional(self.duty_cycle().map(|duty| format!("{:.4}", duty))),
+            optional(self.min_pulse.map(|min| min.to_string())),
+            optional(self.max_pulse.map(|max| max.to_string())),
+            self.glitches
+        )
+    }
+
+    pub(crate) fn result_explanation() -> &'static str {
+        ", toggles, high time, low time, duty cycle, min pulse, max pulse, glitches"
+    }
+}
@@ -0,0 +1,744 @@
+use std::{collections::HashMap, fs};
+
+use vcd_reader::SignalValue;
+
+use crate::{json_escape, values::parse_value, window::WindowState, BusValue, VCD};
+
+/// Boolean expression over the sampled values. Leaves refer to signals by `T`: paths
+/// once parsed, operand indices once resolved against a dump.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr<T> {
+    /// True when the signal is known and non-zero
+    Signal(T),
+    Compare {
+        signal: T,
+        value: u128,
+        equal: bool,
+    },
+    Not(Box<Expr<T>>),
+    And(Box<Expr<T>>, Box<Expr<T>>),
+    Or(Box<Expr<T>>, Box<Expr<T>>),
+    /// The least significant bit became 1 since the previous sample
+    Rose(T),
+    /// The least significant bit became 0 since the previous sample
+    Fell(T),
+    Stable(T),
+    IsUnknown(T),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edge {
+    Posedge,
+    Negedge,
+}
+
+/// A property like `req_ack: @(posedge top/clk) $rose(top/req) |-> ##[1:10] top/ack`.
+/// Clocked properties sample the values right before the clock edge; unclocked ones are
+/// evaluated whenever one of their signals changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Property {
+    pub name: String,
+    pub clock: Option<(Edge, String)>,
+    pub disable: Option<Expr<String>>,
+    /// `None` checks the consequent at every sample
+    pub antecedent: Option<Expr<String>>,
+    /// Samples after the antecedent within which the consequent must hold, both included
+    pub delay: (u32, u32),
+    pub consequent: Expr<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub time: i64,
+    /// Time the antecedent matched
+    pub start: i64,
+    /// Sampled values of the signals of the property, by hierarchical name
+    pub values: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+struct Operand {
+    path: String,
+    /// Indices of the bits in the dump, most significant first
+    bits: Vec<usize>,
+    /// Position of the first bit in the samples
+    offset: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Obligation {
+    start: i64,
+    elapsed: u32,
+}
+
+/// Evaluation of a property over the dump.
+#[derive(Debug, Clone)]
+pub struct PropertyChecker {
+    pub property: Property,
+    /// Samples where the antecedent matched
+    pub attempts: u64,
+    pub violations: Vec<Violation>,
+    operands: Vec<Operand>,
+    clock: Option<(Edge, usize)>,
+    disable: Option<Expr<usize>>,
+    antecedent: Option<Expr<usize>>,
+    consequent: Expr<usize>,
+    committed: Vec<SignalValue>,
+    last_sample: Option<Vec<SignalValue>>,
+    obligations: Vec<Obligation>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Path(String),
+    Number(String),
+    Function(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 14] = [
+    "|->", "|=>", "##", "&&", "||", "==", "!=", "!", "(", ")", "[", "]", ":", "@",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let rest: String = chars[i..].iter().take(3).collect();
+        if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            i += symbol.len();
+            continue;
+        }
+        let start = i;
+        if c == '$' || c.is_ascii_digit() {
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            tokens.push(match c {
+                '$' => Token::Function(word),
+                _ => Token::Number(word),
+            });
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            while i < chars.len() {
+                match chars[i] {
+                    c if c.is_ascii_alphanumeric() || matches!(c, '_' | '/' | '.') => i += 1,
+                    // Bit of a bus, e.g. top/cnt/count/[0]
+                    '[' if chars[i - 1] == '/' => {
+                        while i < chars.len() && chars[i] != ']' {
+                            i += 1;
+                        }
+                        i += 1;
+                    }
+                    _ => break,
+                }
+            }
+            tokens.push(Token::Path(
+                chars[start..i.min(chars.len())].iter().collect(),
+            ));
+        } else {
+            return Err(format!("unexpected character {}", c));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn accept(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn accept_word(&mut self, word: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Path(w)) if w == word);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        match self.accept(symbol) {
+            true => Ok(()),
+            false => Err(format!("expected {}", symbol)),
+        }
+    }
+
+    fn path(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Path(path)) => Ok(path),
+            _ => Err("expected a signal".into()),
+        }
+    }
+
+    fn number(&mut self) -> Result<u128, String> {
+        match self.next() {
+            Some(Token::Number(number)) => parse_value(&number),
+            _ => Err("expected a number".into()),
+        }
+    }
+
+    fn delay(&mut self) -> Result<(u32, u32), String> {
+        let to_u32 = |value: u128| u32::try_from(value).map_err(|_| "delay too long".to_string());
+        if self.accept("[") {
+            let low = to_u32(self.number()?)?;
+            self.expect(":")?;
+            let high = to_u32(self.number()?)?;
+            self.expect("]")?;
+            match low <= high {
+                true => Ok((low, high)),
+                false => Err(format!("empty delay [{}:{}]", low, high)),
+            }
+        } else {
+            let delay = to_u32(self.number()?)?;
+            Ok((delay, delay))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr<String>, String> {
+        let mut left = self.and()?;
+        while self.accept("||") {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr<String>, String> {
+        let mut left = self.unary()?;
+        while self.accept("&&") {
+            left = Expr::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr<String>, String> {
+        if self.accept("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.accept("(") {
+            let expr = self.expr()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        if let Some(Token::Function(function)) = self.peek().cloned() {
+            self.position += 1;
+            self.expect("(")?;
+            let signal = self.path()?;
+            self.expect(")")?;
+            return match function.as_str() {
+                "$rose" => Ok(Expr::Rose(signal)),
+                "$fell" => Ok(Expr::Fell(signal)),
+                "$stable" => Ok(Expr::Stable(signal)),
+                "$isunknown" => Ok(Expr::IsUnknown(signal)),
+                _ => Err(format!("unknown function {}", function)),
+            };
+        }
+        let signal = self.path()?;
+        for (symbol, equal) in [("==", true), ("!=", false)] {
+            if self.accept(symbol) {
+                let value = self.number()?;
+                return Ok(Expr::Compare {
+                    signal,
+                    value,
+                    equal,
+                });
+            }
+        }
+        Ok(Expr::Signal(signal))
+    }
+}
+
+impl<T> Expr<T> {
+    fn map<U>(self, f: &mut impl FnMut(T) -> Result<U, String>) -> Result<Expr<U>, String> {
+        Ok(match self {
+            Expr::Signal(signal) => Expr::Signal(f(signal)?),
+            Expr::Compare {
+                signal,
+                value,
+                equal,
+            } => Expr::Compare {
+                signal: f(signal)?,
+                value,
+                equal,
+            },
+            Expr::Not(expr) => Expr::Not(Box::new(expr.map(f)?)),
+            Expr::And(a, b) => Expr::And(Box::new(a.map(f)?), Box::new(b.map(f)?)),
+            Expr::Or(a, b) => Expr::Or(Box::new(a.map(f)?), Box::new(b.map(f)?)),
+            Expr::Rose(signal) => Expr::Rose(f(signal)?),
+            Expr::Fell(signal) => Expr::Fell(f(signal)?),
+            Expr::Stable(signal) => Expr::Stable(f(signal)?),
+            Expr::IsUnknown(signal) => Expr::IsUnknown(f(signal)?),
+        })
+    }
+}
+
+impl Property {
+    pub fn from_file(path: &str) -> Result<Vec<Self>, String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        Self::from_config_string(&content).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    /// Parses one `<name>: <property>` per line; `#` starts a comment line.
+    pub fn from_config_string(content: &str) -> Result<Vec<Self>, String> {
+        content
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(index, line)| {
+                let property = line
+                    .split_once(':')
+                    .ok_or_else(|| "expected <name>: <property>".to_string())
+                    .and_then(|(name, property)| Self::parse(name.trim(), property));
+                property.map_err(|err| format!("Line {}: {}", index + 1, err))
+            })
+            .collect()
+    }
+
+    /// Parses `[@(posedge|negedge <clock>)] [disable iff (<expr>)] [<expr> |-> | |=>]
+    /// [##<n> | ##[<m>:<n>]] <expr>`. Expressions combine signals, `<signal> == <value>`,
+    /// `!=`, `!`, `&&`, `||`, `$rose`, `$fell`, `$stable` and `$isunknown`.
+    pub fn parse(name: &str, text: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let clock = match parser.accept("@") {
+            true => {
+                parser.expect("(")?;
+                let edge = match parser.path()?.as_str() {
+                    "posedge" => Edge::Posedge,
+                    "negedge" => Edge::Negedge,
+                    edge => return Err(format!("unknown edge {}", edge)),
+                };
+                let clock = parser.path()?;
+                parser.expect(")")?;
+                Some((edge, clock))
+            }
+            false => None,
+        };
+        let disable = match parser.accept_word("disable") {
+            true => {
+                if !parser.accept_word("iff") {
+                    return Err("expected iff".into());
+                }
+                parser.expect("(")?;
+                let disable = parser.expr()?;
+                parser.expect(")")?;
+                Some(disable)
+            }
+            false => None,
+        };
+        let mut antecedent = None;
+        let mut delay = (0, 0);
+        if !parser.accept("##") {
+            let expr = parser.expr()?;
+            if parser.accept("|->") {
+                antecedent = Some(expr);
+            } else if parser.accept("|=>") {
+                antecedent = Some(expr);
+                delay = (1, 1);
+            } else if parser.peek().is_none() {
+                return Ok(Property {
+                    name: name.into(),
+                    clock,
+                    disable,
+                    antecedent,
+                    delay,
+                    consequent: expr,
+                });
+            } else {
+                return Err("expected |-> or |=>".into());
+            }
+            if parser.accept("##") {
+                let (low, high) = parser.delay()?;
+                delay = (delay.0 + low, delay.1 + high);
+            }
+        } else {
+            delay = parser.delay()?;
+        }
+        let consequent = parser.expr()?;
+        if parser.peek().is_some() {
+            return Err("unexpected text at the end".into());
+        }
+        Ok(Property {
+            name: name.into(),
+            clock,
+            disable,
+            antecedent,
+            delay,
+            consequent,
+        })
+    }
+}
+
+fn and(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    match (a, b) {
+        (Some(false), _) | (_, Some(false)) => Some(false),
+        (Some(true), Some(true)) => Some(true),
+        _ => None,
+    }
+}
+
+fn or(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    match (a, b) {
+        (Some(true), _) | (_, Some(true)) => Some(true),
+        (Some(false), Some(false)) => Some(false),
+        _ => None,
+    }
+}
+
+impl PropertyChecker {
+    fn new(property: &Property, vcd: &VCD) -> Result<Self, String> {
+        let mut operands: Vec<Operand> = vec![];
+        let mut indices: HashMap<String, usize> = HashMap::new();
+        let mut resolve = |path: String| -> Result<usize, String> {
+            if let Some(index) = indices.get(&path) {
+                return Ok(*index);
+            }
+            let bits = vcd.find_bits(&path);
+            if bits.is_empty() {
+                return Err(format!(
+                    "Signal {} of property {} not found",
+                    path, property.name
+                ));
+            }
+            let offset = operands.last().map_or(0, |o| o.offset + o.bits.len());
+            operands.push(Operand {
+                path: path.clone(),
+                bits,
+                offset,
+            });
+            indices.insert(path, operands.len() - 1);
+            Ok(operands.len() - 1)
+        };
+        let clock = match &property.clock {
+            Some((edge, path)) => Some((*edge, resolve(path.clone())?)),
+            None => None,
+        };
+        let disable = property
+            .disable
+            .clone()
+            .map(|e| e.map(&mut resolve))
+            .transpose()?;
+        let antecedent = property
+            .antecedent
+            .clone()
+            .map(|e| e.map(&mut resolve))
+            .transpose()?;
+        let consequent = property.consequent.clone().map(&mut resolve)?;
+        let clock = match clock {
+            Some((_, operand)) if operands[operand].bits.len() != 1 => {
+                return Err(format!("Clock of property {} is not a bit", property.name))
+            }
+            Some((edge, operand)) => Some((edge, operands[operand].offset)),
+            None => None,
+        };
+        Ok(PropertyChecker {
+            property: property.clone(),
+            attempts: 0,
+            violations: vec![],
+            operands,
+            clock,
+            disable,
+            antecedent,
+            consequent,
+            committed: vec![],
+            last_sample: None,
+            obligations: vec![],
+        })
+    }
+
+    fn gather(&self, vcd: &VCD) -> Vec<SignalValue> {
+        self.operands
+            .iter()
+            .flat_map(|operand| operand.bits.iter())
+            .map(|bit| vcd.signals[*bit].current_state.value)
+            .collect()
+    }
+
+    fn bits<'a>(&self, operand: usize, sample: &'a [SignalValue]) -> &'a [SignalValue] {
+        let operand = &self.operands[operand];
+        &sample[operand.offset..operand.offset + operand.bits.len()]
+    }
+
+    fn eval(
+        &self,
+        expr: &Expr<usize>,
+        now: &[SignalValue],
+        before: &[SignalValue],
+    ) -> Option<bool> {
+        let value = |operand: usize| {
+            let digits: Vec<u8> = self
+                .bits(operand, now)
+                .iter()
+                .map(|v| char::from(*v) as u8)
+                .collect();
+            match BusValue::from_digits(&digits) {
+                BusValue::Known(value) => Some(value),
+                BusValue::Other(_) => None,
+            }
+        };
+        let lsb =
+            |operand: usize, sample: &[SignalValue]| *self.bits(operand, sample).last().unwrap();
+        match expr {
+            Expr::Signal(operand) => value(*operand).map(|value| value != 0),
+            Expr::Compare {
+                signal,
+                value: expected,
+                equal,
+            } => value(*signal).map(|value| (value == *expected) == *equal),
+            Expr::Not(expr) => self.eval(expr, now, before).map(|value| !value),
+            Expr::And(a, b) => and(self.eval(a, now, before), self.eval(b, now, before)),
+            Expr::Or(a, b) => or(self.eval(a, now, before), self.eval(b, now, before)),
+            Expr::Rose(operand) => Some(
+                lsb(*operand, now) == SignalValue::UP && lsb(*operand, before) != SignalValue::UP,
+            ),
+            Expr::Fell(operand) => Some(
+                lsb(*operand, now) == SignalValue::DOWN
+                    && lsb(*operand, before) != SignalValue::DOWN,
+            ),
+            Expr::Stable(operand) => Some(self.bits(*operand, now) == self.bits(*operand, before)),
+            Expr::IsUnknown(operand) => Some(
+                self.bits(*operand, now)
+                    .iter()
+                    .any(|value| matches!(value, SignalValue::X | SignalValue::Z)),
+            ),
+        }
+    }
+
+    fn violation(&self, time: i64, start: i64, sample: &[SignalValue]) -> Violation {
+        Violation {
+            time,
+            start,
+            values: (0..self.operands.len())
+                .map(|operand| {
+                    (
+                        self.operands[operand].path.clone(),
+                        self.bits(operand, sample)
+                            .iter()
+                            .map(|v| char::from(*v))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Evaluates the property on the values sampled at `time`; new attempts start only
+    /// when `open`, while the pending ones still complete.
+    fn evaluate(&mut self, now: Vec<SignalValue>, time: i64, open: bool) {
+        let before = self.last_sample.take().unwrap_or_else(|| now.clone());
+        let holds =
+            |checker: &Self, expr: &Expr<usize>| checker.eval(expr, &now, &before) == Some(true);
+        if self
+            .disable
+            .as_ref()
+            .is_some_and(|disable| holds(self, disable))
+        {
+            self.obligations.clear();
+            self.last_sample = Some(now);
+            return;
+        }
+        let (low, high) = self.property.delay;
+        let consequent = holds(self, &self.consequent);
+        let mut violations = vec![];
+        self.obligations.retain_mut(|obligation| {
+            obligation.elapsed += 1;
+            if obligation.elapsed >= low && consequent {
+                return false;
+            }
+            if obligation.elapsed >= high {
+                violations.push(obligation.start);
+                return false;
+            }
+            true
+        });
+        let matched = match &self.antecedent {
+            Some(antecedent) => holds(self, antecedent),
+            None => true,
+        };
+        if matched && open {
+            self.attempts += 1;
+            match (low, high) {
+                (0, _) if consequent => {}
+                (_, 0) => violations.push(time),
+                _ => self.obligations.push(Obligation {
+                    start: time,
+                    elapsed: 0,
+                }),
+            }
+        }
+        for start in violations {
+            let violation = self.violation(time, start, &now);
+            self.violations.push(violation);
+        }
+        self.last_sample = Some(now);
+    }
+
+    /// Takes the values at the end of the timestamp `time`.
+    fn commit(&mut self, vcd_values: Vec<SignalValue>, time: i64, open: bool) {
+        let committed = std::mem::replace(&mut self.committed, vcd_values);
+        match self.clock {
+            Some((edge, clock)) => {
+                let (before, after) = (committed[clock], self.committed[clock]);
+                let fired = match edge {
+                    Edge::Posedge => before != SignalValue::UP && after == SignalValue::UP,
+                    Edge::Negedge => before != SignalValue::DOWN && after == SignalValue::DOWN,
+                };
+                // Values sampled right before the edge
+                if fired {
+                    self.evaluate(committed, time, open);
+                }
+            }
+            None if committed != self.committed => {
+                self.evaluate(self.committed.clone(), time, open)
+            }
+            None => {}
+        }
+    }
+
+    /// Obligations still waiting for their consequent at the end of the dump.
+    pub fn pending(&self) -> usize {
+        self.obligations.len()
+    }
+
+    fn to_result_string(&self) -> String {
+        let mut lines = vec![format!(
+            "{} attempts {} violations {} pending {}",
+            self.property.name,
+            self.attempts,
+            self.violations.len(),
+            self.pending()
+        )];
+        lines.extend(self.violations.iter().map(|violation| {
+            let values: Vec<String> = violation
+                .values
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            format!(
+                "  violation {} started {} {}",
+                violation.time,
+                violation.start,
+                values.join(" ")
+            )
+        }));
+        lines.join("\n")
+    }
+
+    fn to_json_string(&self) -> String {
+        let violations: Vec<String> = self
+            .violations
+            .iter()
+            .map(|violation| {
+                let values: Vec<String> = violation
+                    .values
+                    .iter()
+                    .map(|(name, value)| format!("\"{}\":\"{}\"", json_escape(name), value))
+                    .collect();
+                format!(
+                    "{{\"time\":{},\"start\":{},\"values\":{{{}}}}}",
+                    violation.time,
+                    violation.start,
+                    values.join(",")
+                )
+            })
+            .collect();
+        format!(
+            "{{\"name\":\"{}\",\"attempts\":{},\"pending\":{},\"violations\":[{}]}}",
+            json_escape(&self.property.name),
+            self.attempts,
+            self.pending(),
+            violations.join(",")
+        )
+    }
+}
+
+impl VCD {
+    /// Resolves the signals of the properties. Must be called after the definitions.
+    pub(crate) fn set_properties(&mut self, properties: &[Property]) -> Result<(), String> {
+        self.properties = properties
+            .iter()
+            .map(|property| PropertyChecker::new(property, self))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Takes the values at the end of the timestamp `time` and evaluates the properties
+    /// sampled there. Only the attempts starting inside the window are checked.
+    pub(crate) fn check_properties(&mut self, time: i64) {
+        let open = self.window_state == WindowState::Open;
+        let mut properties = std::mem::take(&mut self.properties);
+        properties.iter_mut().for_each(|checker| {
+            let values = checker.gather(self);
+            match checker.committed.is_empty() {
+                // Initial values
+                true => checker.committed = values,
+                false => checker.commit(values, time, open),
+            }
+        });
+        self.properties = properties;
+    }
+
+    pub fn violations(&self) -> usize {
+        self.properties
+            .iter()
+            .map(|checker| checker.violations.len())
+            .sum()
+    }
+
+    pub fn to_property_string(&self) -> String {
+        let mut lines = vec![format!(
+            "# Property check: {} properties, {} violations",
+            self.properties.len(),
+            self.violations()
+        )];
+        lines.push("# Property, attempts, violations, pending at the end; then violation time, antecedent time, sampled values".into());
+        lines.extend(
+            self.properties
+                .iter()
+                .map(|checker| checker.to_result_string()),
+        );
+        lines.join("\n")
+    }
+
+    pub fn to_property_json_string(&self) -> String {
+        let properties: Vec<String> = self
+            .properties
+            .iter()
+            .map(|checker| checker.to_json_string())
+            .collect();
+        format!(
+            "{{\"violations\":{},\"properties\":[{}]}}",
+            self.violations(),
+            properties.join(",")
+        )
+    }
+}
@@ -0,0 +1,238 @@
+use vcd_reader::SignalValue;
+
+use crate::{json_escape, Signal, State, VCD};
+
+/// Rising edges needed before a bit can be a clock.
+pub const MIN_CLOCK_EDGES: u64 = 4;
+/// Largest period variation of a clock, as a fraction of its mean period.
+pub const MAX_CLOCK_JITTER: f64 = 0.1;
+/// A reset is asserted within this fraction of the dump.
+pub const RESET_ASSERT: f64 = 0.05;
+/// A reset is released within this fraction of the dump.
+pub const RESET_RELEASE: f64 = 0.25;
+
+/// Edges and periods of a single bit over the whole dump.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdgeStats {
+    pub rising_edges: u64,
+    pub min_period: Option<i64>,
+    pub max_period: Option<i64>,
+    pub high_time: i64,
+    pub low_time: i64,
+    /// Changes between 0 and 1, X and Z values in between are skipped
+    pub known_changes: u64,
+    pub first_known: Option<State>,
+    pub first_known_change: Option<i64>,
+    pub last_known_change: Option<i64>,
+    period_sum: i64,
+    last_rise: Option<i64>,
+    last_known: Option<SignalValue>,
+    value: SignalValue,
+    since: i64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SignalKind {
+    #[default]
+    Data,
+    Clock {
+        /// Mean period
+        period: f64,
+        /// Difference between the longest and the shortest period
+        jitter: i64,
+        duty_cycle: f64,
+    },
+    Reset {
+        /// Value while the reset is asserted
+        active: SignalValue,
+        released: i64,
+    },
+}
+
+fn is_known(value: SignalValue) -> bool {
+    matches!(value, SignalValue::UP | SignalValue::DOWN)
+}
+
+impl EdgeStats {
+    pub(crate) fn new(state: State) -> Self {
+        let known = is_known(state.value);
+        EdgeStats {
+            rising_edges: 0,
+            min_period: None,
+            max_period: None,
+            high_time: 0,
+            low_time: 0,
+            known_changes: 0,
+            first_known: known.then_some(state),
+            first_known_change: None,
+            last_known_change: None,
+            period_sum: 0,
+            last_rise: None,
+            last_known: known.then_some(state.value),
+            value: state.value,
+            since: state.time.max(0),
+        }
+    }
+
+    fn accumulate(&mut self, time: i64) {
+        match self.value {
+            SignalValue::UP => self.high_time += time - self.since,
+            SignalValue::DOWN => self.low_time += time - self.since,
+            _ => {}
+        }
+        self.since = time;
+    }
+
+    pub(crate) fn add_change(&mut self, state: State) {
+        if state.value == self.value {
+            return;
+        }
+        if self.value == SignalValue::DOWN && state.value == SignalValue::UP {
+            self.rising_edges += 1;
+            if let Some(last_rise) = self.last_rise {
+                let period = state.time - last_rise;
+                self.period_sum += period;
+                self.min_period = Some(self.min_period.map_or(period, |min| min.min(period)));
+                self.max_period = Some(self.max_period.map_or(period, |max| max.max(period)));
+            }
+            self.last_rise = Some(state.time);
+        }
+        if is_known(state.value) {
+            match self.last_known {
+                Some(last) if last != state.value => {
+                    self.known_changes += 1;
+                    self.first_known_change.get_or_insert(state.time);
+                    self.last_known_change = Some(state.time);
+                }
+                Some(_) => {}
+                None => self.first_known = Some(state),
+            }
+            self.last_known = Some(state.value);
+        }
+        self.accumulate(state.time);
+        self.value = state.value;
+    }
+
+    pub(crate) fn finish(&mut self, time: i64) {
+        if time > self.since {
+            self.accumulate(time);
+        }
+    }
+
+    /// Classifies the bit over a dump ending at `end`.
+    pub fn kind(&self, end: i64) -> SignalKind {
+        if let (true, Some(min), Some(max)) = (
+            self.rising_edges >= MIN_CLOCK_EDGES,
+            self.min_period,
+            self.max_period,
+        ) {
+            let period = self.period_sum as f64 / (self.rising_edges - 1) as f64;
+            if (max - min) as f64 <= period * MAX_CLOCK_JITTER {
+                return SignalKind::Clock {
+                    period,
+                    jitter: max - min,
+                    duty_cycle: self.high_time as f64 / (self.high_time + self.low_time) as f64,
+                };
+            }
+        }
+        // An assertion starting near time 0 followed by a release: the bit is either
+        // asserted from the start, or deasserted, asserted and released
+        let asserted = match self.known_changes {
+            1 => self.first_known.map(|state| state.time),
+            _ => self.first_known_change,
+        };
+        match (self.known_changes, asserted, self.last_known_change) {
+            (1..=2, Some(asserted), Some(released))
+                if asserted as f64 <= end as f64 * RESET_ASSERT
+                    && released as f64 <= end as f64 * RESET_RELEASE =>
+            {
+                SignalKind::Reset {
+                    active: match self.first_known.map(|state| state.value) {
+                        Some(value) if self.known_changes == 1 => value,
+                        Some(SignalValue::UP) => SignalValue::DOWN,
+                        _ => SignalValue::UP,
+                    },
+                    released,
+                }
+            }
+            _ => SignalKind::Data,
+        }
+    }
+}
+
+impl SignalKind {
+    pub(crate) fn to_result_string(self, name: &str) -> Option<String> {
+        match self {
+            SignalKind::Data => None,
+            SignalKind::Clock {
+                period,
+                jitter,
+                duty_cycle,
+            } => Some(format!(
+                "# Clock {} period {:.3} jitter {} duty cycle {:.3}",
+                name, period, jitter, duty_cycle
+            )),
+            SignalKind::Reset { active, released } => Some(format!(
+                "# Reset {} active {} released {}",
+                name,
+                char::from(active),
+                released
+            )),
+        }
+    }
+
+    pub(crate) fn to_json_string(self) -> String {
+        match self {
+            SignalKind::Data => "{\"type\":\"data\"}".into(),
+            SignalKind::Clock {
+                period,
+                jitter,
+                duty_cycle,
+            } => format!(
+                "{{\"type\":\"clock\",\"period\":{:.3},\"jitter\":{},\"duty_cycle\":{:.4}}}",
+                period, jitter, duty_cycle
+            ),
+            SignalKind::Reset { active, released } => format!(
+                "{{\"type\":\"reset\",\"active\":\"{}\",\"released\":{}}}",
+                json_escape(&char::from(active).to_string()),
+                released
+            ),
+        }
+    }
+}
+
+/// Comment line of the text report when clocks and resets are excluded from the totals.
+pub(crate) const EXCLUDED_NOTE: &str = "# Clocks and resets are excluded from the totals";
+
+impl VCD {
+    /// Classifies every bit once the dump, ending at `end`, is over.
+    pub(crate) fn classify_signals(&mut self, end: i64) {
+        self.signals.iter_mut().for_each(|signal| {
+            if let Some(edges) = signal.edges.as_mut() {
+                edges.finish(end);
+                signal.kind = edges.kind(end);
+            }
+        });
+    }
+
+    /// Bits counted in the coverage totals: all of them, unless clocks and resets are
+    /// excluded.
+    pub fn counted_signals(&self) -> impl Iterator<Item = &Signal> {
+        self.signals
+            .iter()
+            .filter(|signal| !self.exclude_clocks_resets || signal.kind == SignalKind::Data)
+    }
+
+    /// Comment lines listing the clocks and resets.
+    pub(crate) fn classification_string(&self) -> String {
+        let mut lines: Vec<String> = self
+            .signals
+            .iter()
+            .filter_map(|signal| signal.kind.to_result_string(&signal.name.join("/")))
+            .collect();
+        if self.exclude_clocks_resets && !lines.is_empty() {
+            lines.push(EXCLUDED_NOTE.into());
+        }
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+}
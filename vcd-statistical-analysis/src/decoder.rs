@@ -0,0 +1,719 @@
+use std::{collections::HashMap, fmt::Debug, fs};
+
+use crate::{json_escape, BusValue, VCD};
+
+/// A decoded protocol transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    pub start: i64,
+    pub end: i64,
+    /// e.g. `byte`, `address`, `transfer`
+    pub kind: String,
+    pub data: String,
+    /// Acknowledges, errors and other remarks
+    pub note: String,
+}
+
+/// Turns the values of its signals into transactions. The values are given once per
+/// timestamp in which one of them changed: `before` holds them at the end of the previous
+/// such timestamp, `now` at the end of `time`, both in the order of `roles`.
+pub trait Decoder: Debug {
+    /// Names of the signals the decoder needs, e.g. `["scl", "sda"]`
+    fn roles(&self) -> &'static [&'static str];
+    fn on_sample(&mut self, before: &[BusValue], now: &[BusValue], time: i64);
+    /// Closes the decoding at the end of the dump.
+    fn finish(&mut self, _time: i64) {}
+    fn transactions(&self) -> &[Transaction];
+    fn box_clone(&self) -> Box<dyn Decoder>;
+}
+
+impl Clone for Box<dyn Decoder> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+const HIGH: BusValue = BusValue::Known(1);
+const LOW: BusValue = BusValue::Known(0);
+
+/// Value of single-bit samples, most significant first. `None` if any is unknown.
+fn bits_value<'a>(bits: impl Iterator<Item = &'a BusValue>) -> Option<u128> {
+    bits.into_iter().try_fold(0, |value, bit| match bit {
+        BusValue::Known(bit) => Some(value << 1 | bit),
+        BusValue::Other(_) => None,
+    })
+}
+
+fn transaction(start: i64, end: i64, kind: &str, data: String, note: String) -> Transaction {
+    Transaction {
+        start,
+        end,
+        kind: kind.into(),
+        data,
+        note,
+    }
+}
+
+/// Serial line, idle high, least significant bit first.
+#[derive(Debug, Clone)]
+pub struct Uart {
+    /// Bit duration in time units of the dump
+    pub bit_time: f64,
+    pub data_bits: u32,
+    /// `Some(true)` for odd parity
+    pub parity: Option<bool>,
+    pub stop_bits: u32,
+    line: BusValue,
+    /// Start time of the frame being received and its bits so far
+    frame: Option<(f64, Vec<BusValue>)>,
+    transactions: Vec<Transaction>,
+}
+
+impl Uart {
+    pub fn new(bit_time: f64, data_bits: u32, parity: Option<bool>, stop_bits: u32) -> Self {
+        Uart {
+            bit_time,
+            data_bits,
+            parity,
+            stop_bits,
+            line: HIGH,
+            frame: None,
+            transactions: vec![],
+        }
+    }
+
+    fn frame_bits(&self) -> usize {
+        (1 + self.data_bits + self.parity.is_some() as u32 + self.stop_bits) as usize
+    }
+
+    /// Samples the line in the middle of the bits that end before `time`; `inclusive`
+    /// also takes a bit sampled exactly at `time`.
+    fn sample_until(&mut self, time: i64, inclusive: bool) {
+        let Some((start, mut bits)) = self.frame.take() else {
+            return;
+        };
+        while bits.len() < self.frame_bits() {
+            let at = start + (bits.len() as f64 + 0.5) * self.bit_time;
+            if at > time as f64 || (!inclusive && at == time as f64) {
+                self.frame = Some((start, bits));
+                return;
+            }
+            if bits.is_empty() && self.line != LOW {
+                // Glitch shorter than half a bit
+                return;
+            }
+            bits.push(self.line.clone());
+        }
+        let data_bits = &bits[1..1 + self.data_bits as usize];
+        let data = bits_value(data_bits.iter().rev());
+        let mut notes = vec![];
+        if let (Some(odd), Some(data)) = (self.parity, data) {
+            let ones = data.count_ones() + (bits[1 + self.data_bits as usize] == HIGH) as u32;
+            if (ones % 2 == 1) != odd {
+                notes.push("parity error");
+            }
+        }
+        if bits[bits.len() - self.stop_bits as usize..]
+            .iter()
+            .any(|bit| *bit != HIGH)
+        {
+            notes.push("framing error");
+        }
+        self.transactions.push(transaction(
+            start.round() as i64,
+            (start + self.frame_bits() as f64 * self.bit_time).round() as i64,
+            "byte",
+            data.map_or("x".into(), |data| BusValue::Known(data).to_string()),
+            notes.join(" "),
+        ));
+    }
+}
+
+impl Decoder for Uart {
+    fn roles(&self) -> &'static [&'static str] {
+        &["tx"]
+    }
+
+    fn on_sample(&mut self, _before: &[BusValue], now: &[BusValue], time: i64) {
+        self.sample_until(time, false);
+        if self.frame.is_none() && self.line == HIGH && now[0] == LOW {
+            self.frame = Some((time as f64, vec![]));
+        }
+        self.line = now[0].clone();
+    }
+
+    fn finish(&mut self, time: i64) {
+        self.sample_until(time, true);
+    }
+
+    fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    fn box_clone(&self) -> Box<dyn Decoder> {
+        Box::new(self.clone())
+    }
+}
+
+/// SPI with an active low chip select, most significant bit first.
+#[derive(Debug, Clone)]
+pub struct Spi {
+    /// Clock polarity and phase, 0 to 3
+    pub mode: u8,
+    pub word_bits: u32,
+    word: Option<(i64, Vec<(BusValue, BusValue)>)>,
+    transactions: Vec<Transaction>,
+}
+
+impl Spi {
+    pub fn new(mode: u8, word_bits: u32) -> Self {
+        Spi {
+            mode,
+            word_bits,
+            word: None,
+            transactions: vec![],
+        }
+    }
+
+    fn push_word(&mut self, end: i64, note: &str) {
+        let Some((start, bits)) = self.word.take() else {
+            return;
+        };
+        let value = |value: Option<u128>| {
+            value.map_or("x".into(), |value| BusValue::Known(value).to_string())
+        };
+        let mosi = value(bits_value(bits.iter().map(|(mosi, _)| mosi)));
+        let miso = value(bits_value(bits.iter().map(|(_, miso)| miso)));
+        self.transactions.push(transaction(
+            start,
+            end,
+            "word",
+            format!("mosi={} miso={}", mosi, miso),
+            note.into(),
+        ));
+    }
+}
+
+impl Decoder for Spi {
+    fn roles(&self) -> &'static [&'static str] {
+        &["sclk", "cs", "mosi", "miso"]
+    }
+
+    fn on_sample(&mut self, before: &[BusValue], now: &[BusValue], time: i64) {
+        if before[1] == LOW && now[1] != LOW {
+            if self.word.as_ref().is_some_and(|(_, bits)| !bits.is_empty()) {
+                self.push_word(time, "incomplete");
+            }
+            self.word = None;
+            return;
+        }
+        if now[1] != LOW {
+            return;
+        }
+        // Modes 0 and 3 sample on the rising edge, 1 and 2 on the falling edge
+        let sample_high = matches!(self.mode, 0 | 3);
+        let (from, to) = match sample_high {
+            true => (LOW, HIGH),
+            false => (HIGH, LOW),
+        };
+        if before[0] == from && now[0] == to {
+            let word = self.word.get_or_insert((time, vec![]));
+            word.1.push((before[2].clone(), before[3].clone()));
+            if word.1.len() == self.word_bits as usize {
+                self.push_word(time, "");
+            }
+        }
+    }
+
+    fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    fn box_clone(&self) -> Box<dyn Decoder> {
+        Box::new(self.clone())
+    }
+}
+
+/// I2C bytes with their acknowledge bit. The first byte after a start is the address.
+#[derive(Debug, Clone, Default)]
+pub struct I2c {
+    /// Start of the current byte, whether it is an address, and its bits
+    byte: Option<(i64, bool, Vec<BusValue>)>,
+    active: bool,
+    transactions: Vec<Transaction>,
+}
+
+impl Decoder for I2c {
+    fn roles(&self) -> &'static [&'static str] {
+        &["scl", "sda"]
+    }
+
+    fn on_sample(&mut self, before: &[BusValue], now: &[BusValue], time: i64) {
+        let scl_high = before[0] == HIGH && now[0] == HIGH;
+        if scl_high && before[1] == HIGH && now[1] == LOW {
+            self.transactions.push(transaction(
+                time,
+                time,
+                "start",
+                String::new(),
+                String::new(),
+            ));
+            self.active = true;
+            self.byte = Some((time, true, vec![]));
+            return;
+        }
+        if scl_high && before[1] == LOW && now[1] == HIGH {
+            self.transactions.push(transaction(
+                time,
+                time,
+                "stop",
+                String::new(),
+                String::new(),
+            ));
+            self.active = false;
+            self.byte = None;
+            return;
+        }
+        if !self.active || before[0] != LOW || now[0] != HIGH {
+            return;
+        }
+        let Some((start, address, bits)) = self.byte.as_mut() else {
+            return;
+        };
+        if bits.is_empty() {
+            *start = time;
+        }
+        bits.push(before[1].clone());
+        if bits.len() < 9 {
+            return;
+        }
+        let value = bits_value(bits[..8].iter());
+        let ack = match bits[8] == LOW {
+            true => "ack",
+            false => "nack",
+        };
+        let (kind, data, note) = match (*address, value) {
+            (true, Some(value)) => (
+                "address",
+                BusValue::Known(value >> 1).to_string(),
+                format!("{} {}", ["write", "read"][value as usize & 1], ack),
+            ),
+            (_, value) => (
+                if *address { "address" } else { "data" },
+                value.map_or("x".into(), |value| BusValue::Known(value).to_string()),
+                ack.to_string(),
+            ),
+        };
+        self.transactions
+            .push(transaction(*start, time, kind, data, note));
+        self.byte = Some((time, false, vec![]));
+    }
+
+    fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    fn box_clone(&self) -> Box<dyn Decoder> {
+        Box::new(self.clone())
+    }
+}
+
+/// Valid/ready transfers of an AXI or AXI-Stream channel, sampled on the rising clock edge.
+#[derive(Debug, Clone, Default)]
+pub struct Handshake {
+    /// Edge at which valid was first seen and the cycles waited for ready
+    waiting: Option<(i64, u64)>,
+    transactions: Vec<Transaction>,
+}
+
+impl Decoder for Handshake {
+    fn roles(&self) -> &'static [&'static str] {
+        &["clk", "valid", "ready", "data"]
+    }
+
+    fn on_sample(&mut self, before: &[BusValue], now: &[BusValue], time: i64) {
+        if before[0] == HIGH || now[0] != HIGH || before[1] != HIGH {
+            return;
+        }
+        let (start, waited) = self.waiting.get_or_insert((time, 0));
+        if before[2] != HIGH {
+            *waited += 1;
+            return;
+        }
+        let transaction = transaction(
+            *start,
+            time,
+            "transfer",
+            before[3].to_string(),
+            format!("{} wait cycles", waited),
+        );
+        self.transactions.push(transaction);
+        self.waiting = None;
+    }
+
+    fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    fn box_clone(&self) -> Box<dyn Decoder> {
+        Box::new(self.clone())
+    }
+}
+
+/// A decoder with its signals, e.g. the line
+/// `uart console baud=115200 tx=top/uart_tx`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecoderDescription {
+    /// `uart`, `spi`, `i2c`, `handshake` or a registered protocol
+    pub protocol: String,
+    pub name: String,
+    /// Roles and options with their values, e.g. `tx` -> `top/uart_tx`
+    pub settings: HashMap<String, String>,
+}
+
+/// Seconds of a time unit like `1ns` or `10 ps`.
+fn timescale_seconds(timescale: &str) -> Option<f64> {
+    let timescale: String = timescale.split_ascii_whitespace().collect();
+    let unit_start = timescale.find(|c: char| !c.is_ascii_digit())?;
+    let (value, unit) = timescale.split_at(unit_start);
+    let unit = match unit {
+        "s" => 1.0,
+        "ms" => 1e-3,
+        "us" => 1e-6,
+        "ns" => 1e-9,
+        "ps" => 1e-12,
+        "fs" => 1e-15,
+        _ => return None,
+    };
+    Some(value.parse::<f64>().ok()? * unit)
+}
+
+impl DecoderDescription {
+    pub fn from_file(path: &str) -> Result<Vec<Self>, String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        Self::from_config_string(&content).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    /// Parses one `<protocol> <name> <key>=<value>...` per line; `#` starts a comment line.
+    /// ```text
+    /// uart console baud=115200 tx=top/uart_tx data_bits=8 parity=none stop_bits=1
+    /// spi flash sclk=top/sclk cs=top/cs_n mosi=top/mosi miso=top/miso mode=0 bits=8
+    /// i2c eeprom scl=top/scl sda=top/sda
+    /// handshake aw clk=top/clk valid=top/awvalid ready=top/awready data=top/awaddr
+    /// ```
+    pub fn from_config_string(content: &str) -> Result<Vec<Self>, String> {
+        Self::from_config_string_with(content, &Protocols::default())
+    }
+
+    /// Parses the decoders of `content`, which may use the registered protocols.
+    pub fn from_config_string_with(
+        content: &str,
+        protocols: &Protocols,
+    ) -> Result<Vec<Self>, String> {
+        let mut decoders = vec![];
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("Line {}: {}", index + 1, message);
+            let mut words = line.split_ascii_whitespace();
+            let (Some(protocol), Some(name)) = (words.next(), words.next()) else {
+                return Err(error(format!("expected <protocol> <name>: {}", line)));
+            };
+            let settings = words
+                .map(|word| {
+                    word.split_once('=')
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                        .ok_or_else(|| error(format!("expected <key>=<value>: {}", word)))
+                })
+                .collect::<Result<_, _>>()?;
+            let description = DecoderDescription {
+                protocol: protocol.into(),
+                name: name.into(),
+                settings,
+            };
+            // Reports unknown protocols and bad options before reading the dump
+            description.build(protocols, Some("1ns")).map_err(error)?;
+            decoders.push(description);
+        }
+        Ok(decoders)
+    }
+
+    /// Value of the option `key`, or `default` when it is not set.
+    pub fn option<T: std::str::FromStr>(&self, key: &str, default: T) -> Result<T, String> {
+        match self.settings.get(key) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("Decoder {}: invalid {} {}", self.name, key, value)),
+            None => Ok(default),
+        }
+    }
+
+    /// Builds the decoder for a dump with the given time scale.
+    pub fn build(
+        &self,
+        protocols: &Protocols,
+        timescale: Option<&str>,
+    ) -> Result<Box<dyn Decoder>, String> {
+        let builder = protocols
+            .0
+            .get(&self.protocol)
+            .ok_or_else(|| format!("Unknown protocol {}", self.protocol))?;
+        let decoder = builder(self, timescale)?;
+        if let Some(role) = decoder
+            .roles()
+            .iter()
+            .find(|role| !self.settings.contains_key(**role))
+        {
+            return Err(format!("Decoder {}: missing signal {}", self.name, role));
+        }
+        Ok(decoder)
+    }
+}
+
+/// Builds the decoder of a description for a dump with the given time scale.
+pub type DecoderBuilder = fn(&DecoderDescription, Option<&str>) -> Result<Box<dyn Decoder>, String>;
+
+/// Protocols the decoders are built for, by name: `uart`, `spi`, `i2c`, `handshake` and
+/// the registered ones.
+#[derive(Debug, Clone)]
+pub struct Protocols(HashMap<String, DecoderBuilder>);
+
+impl Default for Protocols {
+    fn default() -> Self {
+        let builders: [(&str, DecoderBuilder); 4] = [
+            ("uart", build_uart),
+            ("spi", build_spi),
+            ("i2c", |_, _| Ok(Box::new(I2c::default()))),
+            ("handshake", |_, _| Ok(Box::new(Handshake::default()))),
+        ];
+        Protocols(
+            builders
+                .into_iter()
+                .map(|(protocol, builder)| (protocol.to_string(), builder))
+                .collect(),
+        )
+    }
+}
+
+impl Protocols {
+    /// Adds a protocol, replacing any other of the same name.
+    pub fn register(&mut self, protocol: &str, builder: DecoderBuilder) {
+        self.0.insert(protocol.into(), builder);
+    }
+}
+
+fn build_uart(
+    description: &DecoderDescription,
+    timescale: Option<&str>,
+) -> Result<Box<dyn Decoder>, String> {
+    let baud: f64 = description.option("baud", 0.0)?;
+    if baud <= 0.0 {
+        return Err(format!("Decoder {}: baud is required", description.name));
+    }
+    let unit = timescale.and_then(timescale_seconds).ok_or_else(|| {
+        format!(
+            "Decoder {}: the dump has no usable time scale",
+            description.name
+        )
+    })?;
+    let parity = match description.option("parity", "none".to_string())?.as_str() {
+        "none" => None,
+        "odd" => Some(true),
+        "even" => Some(false),
+        parity => {
+            return Err(format!(
+                "Decoder {}: invalid parity {}",
+                description.name, parity
+            ))
+        }
+    };
+    Ok(Box::new(Uart::new(
+        1.0 / baud / unit,
+        description.option("data_bits", 8)?,
+        parity,
+        description.option("stop_bits", 1)?,
+    )))
+}
+
+fn build_spi(
+    description: &DecoderDescription,
+    _timescale: Option<&str>,
+) -> Result<Box<dyn Decoder>, String> {
+    let mode = description.option("mode", 0)?;
+    if mode > 3 {
+        return Err(format!(
+            "Decoder {}: invalid mode {}",
+            description.name, mode
+        ));
+    }
+    let bits = description.option("bits", 8)?;
+    if bits == 0 {
+        return Err(format!("Decoder {}: invalid bits 0", description.name));
+    }
+    Ok(Box::new(Spi::new(mode, bits)))
+}
+
+/// A decoder attached to the bits of its signals.
+#[derive(Debug, Clone)]
+pub struct DecoderInstance {
+    pub name: String,
+    pub decoder: Box<dyn Decoder>,
+    /// Bits of every role, most significant first
+    bits: Vec<Vec<usize>>,
+    committed: Vec<BusValue>,
+    /// Transactions of the decoder starting inside the window
+    transactions: Vec<Transaction>,
+    /// Transactions of the decoder already looked at
+    seen: usize,
+}
+
+impl DecoderInstance {
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    /// Keeps the new transactions of the decoder that start inside the window of `vcd`.
+    fn collect(&mut self, vcd: &VCD) {
+        let transactions = &self.decoder.transactions()[self.seen..];
+        self.seen += transactions.len();
+        self.transactions.extend(
+            transactions
+                .iter()
+                .filter(|transaction| vcd.in_window(transaction.start))
+                .cloned(),
+        );
+    }
+}
+
+/// CSV fields are quoted when they contain separators or quotes.
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.into(),
+    }
+}
+
+impl VCD {
+    /// Attaches the decoders to their signals. Must be called after the definitions.
+    pub(crate) fn set_decoders(
+        &mut self,
+        decoders: &[DecoderDescription],
+        protocols: &Protocols,
+    ) -> Result<(), String> {
+        self.decoders = decoders
+            .iter()
+            .map(|description| {
+                let decoder = description.build(protocols, self.timescale.as_deref())?;
+                let bits = decoder
+                    .roles()
+                    .iter()
+                    .map(|role| {
+                        let path = &description.settings[*role];
+                        match self.find_bits(path) {
+                            bits if bits.is_empty() => Err(format!(
+                                "Signal {} of decoder {} not found",
+                                path, description.name
+                            )),
+                            bits => Ok(bits),
+                        }
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(DecoderInstance {
+                    name: description.name.clone(),
+                    decoder,
+                    bits,
+                    committed: vec![],
+                    transactions: vec![],
+                    seen: 0,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(())
+    }
+
+    /// Gives the decoders the values at the end of the timestamp `time`.
+    pub(crate) fn decode(&mut self, time: i64) {
+        let mut decoders = std::mem::take(&mut self.decoders);
+        for instance in decoders.iter_mut() {
+            let values: Vec<BusValue> = instance
+                .bits
+                .iter()
+                .map(|bits| {
+                    let digits: Vec<u8> = bits
+                        .iter()
+                        .map(|bit| char::from(self.signals[*bit].current_state.value) as u8)
+                        .collect();
+                    BusValue::from_digits(&digits)
+                })
+                .collect();
+            if instance.committed.is_empty() {
+                instance.committed = values;
+            } else if instance.committed != values {
+                let before = std::mem::replace(&mut instance.committed, values);
+                instance
+                    .decoder
+                    .on_sample(&before, &instance.committed, time);
+                instance.collect(self);
+            }
+        }
+        self.decoders = decoders;
+    }
+
+    pub(crate) fn finish_decoders(&mut self, time: i64) {
+        let mut decoders = std::mem::take(&mut self.decoders);
+        for instance in decoders.iter_mut() {
+            instance.decoder.finish(time);
+            instance.collect(self);
+        }
+        self.decoders = decoders;
+    }
+
+    pub fn to_transactions_csv_string(&self) -> String {
+        let mut lines = vec!["decoder,start,end,kind,data,note".to_string()];
+        for instance in self.decoders.iter() {
+            lines.extend(instance.transactions().iter().map(|t| {
+                [
+                    csv_field(&instance.name),
+                    t.start.to_string(),
+                    t.end.to_string(),
+                    csv_field(&t.kind),
+                    csv_field(&t.data),
+                    csv_field(&t.note),
+                ]
+                .join(",")
+            }));
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_transactions_json_string(&self) -> String {
+        let decoders: Vec<String> = self
+            .decoders
+            .iter()
+            .map(|instance| {
+                let transactions: Vec<String> = instance
+                    .transactions()
+                    .iter()
+                    .map(|t| {
+                        format!(
+                            "{{\"start\":{},\"end\":{},\"kind\":\"{}\",\"data\":\"{}\",\"note\":\"{}\"}}",
+                            t.start,
+                            t.end,
+                            json_escape(&t.kind),
+                            json_escape(&t.data),
+                            json_escape(&t.note)
+                        )
+                    })
+                    .collect();
+                format!(
+                    "{{\"name\":\"{}\",\"transactions\":[{}]}}",
+                    json_escape(&instance.name),
+                    transactions.join(",")
+                )
+            })
+            .collect();
+        format!("{{\"decoders\":[{}]}}", decoders.join(","))
+    }
+}
@@ -0,0 +1,505 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File},
+    io::{BufWriter, Write},
+};
+
+use crate::{clock::EXCLUDED_NOTE, json::Json, json_escape, VCD};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+pub struct DiffConfiguration {
+    pub baseline_file: String,
+    pub current_file: String,
+    pub out_file: String,
+    pub format: ReportFormat,
+}
+
+/// Coverage of a single bit, as written in a result file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BitCoverage {
+    pub name: String,
+    pub id: String,
+    pub sub_id: u16,
+    pub coverage: f32,
+    pub transitioned_up: bool,
+    pub transitioned_down: bool,
+    pub initial_value: char,
+}
+
+/// The per-bit coverage of a whole run.
+#[derive(Debug, Default, Clone)]
+pub struct CoverageReport {
+    pub bits: Vec<BitCoverage>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModuleCoverage {
+    pub coverage: f64,
+    pub bits: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BitDelta {
+    pub name: String,
+    pub old_coverage: f32,
+    pub new_coverage: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitialValueDelta {
+    pub name: String,
+    pub old_value: char,
+    pub new_value: char,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleDelta {
+    pub module: String,
+    pub old: Option<ModuleCoverage>,
+    pub new: Option<ModuleCoverage>,
+}
+
+/// Differences between a baseline and a current coverage report.
+/// Bits are matched by hierarchical name, since id codes are not stable between dumps.
+#[derive(Debug, Default, Clone)]
+pub struct CoverageDiff {
+    pub old_total: f64,
+    pub new_total: f64,
+    pub newly_covered: Vec<BitDelta>,
+    pub newly_uncovered: Vec<BitDelta>,
+    pub initial_value_changed: Vec<InitialValueDelta>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modules: Vec<ModuleDelta>,
+}
+
+pub fn perform_diff_and_save(c: DiffConfiguration) -> Result<(), String> {
+    let baseline = CoverageReport::from_file(&c.baseline_file)?;
+    let current = CoverageReport::from_file(&c.current_file)?;
+    CoverageDiff::new(&baseline, &current).save(&c.out_file, c.format)
+}
+
+/// Module path of a bit name: the name without the signal and its optional `[n]` index.
+fn module_of(name: &str) -> &str {
+    match name.rsplit_once('/') {
+        Some((parent, last)) if last.starts_with('[') => {
+            parent.rsplit_once('/').map_or("", |(module, _)| module)
+        }
+        Some((module, _)) => module,
+        None => "",
+    }
+}
+
+impl BitCoverage {
+    fn from_result_line(line: &str, lineno: usize) -> Result<Self, String> {
+        let malformed = || format!("Line {}: malformed result line", lineno);
+        let mut words = line.split_ascii_whitespace();
+        let name = words.next().ok_or_else(malformed)?;
+        let (id, sub_id) = words
+            .next()
+            .and_then(|id| id.rsplit_once('-'))
+            .ok_or_else(malformed)?;
+        let coverage = words.next().and_then(|c| c.parse().ok());
+        let up = words.next().and_then(|c| c.parse::<u8>().ok());
+        let down = words.next().and_then(|c| c.parse::<u8>().ok());
+        let initial_value = words.next().and_then(|c| c.chars().next());
+        match (sub_id.parse(), coverage, up, down, initial_value) {
+            (Ok(sub_id), Some(coverage), Some(up), Some(down), Some(initial_value)) => {
+                Ok(BitCoverage {
+                    name: name.into(),
+                    id: id.into(),
+                    sub_id,
+                    coverage,
+                    transitioned_up: up != 0,
+                    transitioned_down: down != 0,
+                    initial_value,
+                })
+            }
+            _ => Err(malformed()),
+        }
+    }
+
+    fn from_json(signal: &Json, index: usize) -> Result<Self, String> {
+        let str_field = |key| signal.get(key).and_then(Json::as_str);
+        let bool_field = |key| signal.get(key).and_then(Json::as_bool);
+        let sub_id = signal.get("sub_id").and_then(Json::as_f64);
+        let coverage = signal.get("coverage").and_then(Json::as_f64);
+        let initial_value = str_field("initial_value").and_then(|value| value.chars().next());
+        match (
+            str_field("name"),
+            str_field("id"),
+            sub_id,
+            coverage,
+            bool_field("transitioned_up"),
+            bool_field("transitioned_down"),
+            initial_value,
+        ) {
+            (
+                Some(name),
+                Some(id),
+                Some(sub_id),
+                Some(coverage),
+                Some(transitioned_up),
+                Some(transitioned_down),
+                Some(initial_value),
+            ) => Ok(BitCoverage {
+                name: name.into(),
+                id: id.into(),
+                sub_id: sub_id as _,
+                coverage: coverage as _,
+                transitioned_up,
+                transitioned_down,
+                initial_value,
+            }),
+            _ => Err(format!("Signal {}: malformed result", index + 1)),
+        }
+    }
+
+    pub fn module(&self) -> &str {
+        module_of(&self.name)
+    }
+}
+
+impl From<&VCD> for CoverageReport {
+    fn from(vcd: &VCD) -> Self {
+        CoverageReport {
+            bits: vcd
+                .counted_signals()
+                .map(|signal| BitCoverage {
+                    name: signal.name.join("/"),
+                    id: signal.id.to_string(),
+                    sub_id: signal.sub_id,
+                    coverage: signal.calculate_coverage(),
+                    transitioned_up: signal.has_transitioned_up(),
+                    transitioned_down: signal.has_transitioned_down(),
+                    initial_value: signal.initial_state.value.into(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl CoverageReport {
+    /// Reads a result file, text or JSON.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        match content.trim_start().starts_with('{') {
+            true => Self::from_json_string(&content),
+            false => Self::from_result_string(&content),
+        }
+        .map_err(|err| format!("{}: {}", path, err))
+    }
+
+    /// Parses the output of `VCD::to_json_string`, skipping the clocks and resets when the
+    /// report excludes them from the totals.
+    pub fn from_json_string(content: &str) -> Result<Self, String> {
+        let report = Json::parse(content)?;
+        let excluded = report
+            .get("clocks_resets_excluded")
+            .and_then(Json::as_bool)
+            .unwrap_or(false);
+        let signals = report
+            .get("signals")
+            .and_then(Json::as_array)
+            .ok_or("Missing signals")?;
+        let bits = signals
+            .iter()
+            .enumerate()
+            .filter(|(_, signal)| {
+                let kind = signal.get("kind").and_then(|kind| kind.get("type"));
+                !excluded
+                    || kind
+                        .and_then(Json::as_str)
+                        .is_none_or(|kind| kind == "data")
+            })
+            .map(|(index, signal)| BitCoverage::from_json(signal, index))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(CoverageReport { bits })
+    }
+
+    /// Parses the output of `VCD::to_result_string`. Comment lines are skipped, and so are
+    /// the clocks and resets when the report excludes them from the totals.
+    pub fn from_result_string(content: &str) -> Result<Self, String> {
+        let excluded: HashSet<&str> = match content.lines().any(|line| line == EXCLUDED_NOTE) {
+            true => content
+                .lines()
+                .filter_map(|line| {
+                    line.strip_prefix("# Clock ")
+                        .or_else(|| line.strip_prefix("# Reset "))
+                })
+                .filter_map(|line| line.split(' ').next())
+                .collect(),
+            false => HashSet::new(),
+        };
+        let bits = content
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(lineno, line)| BitCoverage::from_result_line(line, lineno))
+            .filter(|bit| {
+                bit.as_ref()
+                    .map_or(true, |bit| !excluded.contains(bit.name.as_str()))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(CoverageReport { bits })
+    }
+
+    pub fn total_coverage(&self) -> f64 {
+        if self.bits.is_empty() {
+            return 0.0;
+        }
+        self.bits.iter().map(|bit| bit.coverage as f64).sum::<f64>() / self.bits.len() as f64
+    }
+
+    /// Coverage of every module, including the bits of its submodules.
+    pub fn module_coverage(&self) -> BTreeMap<String, ModuleCoverage> {
+        let mut modules: BTreeMap<String, (f64, usize)> = BTreeMap::new();
+        for bit in self.bits.iter() {
+            let module = bit.module();
+            let mut end = 0;
+            while end < module.len() {
+                end = module[end..]
+                    .find('/')
+                    .map_or(module.len(), |pos| end + pos);
+                let entry = modules.entry(module[..end].into()).or_default();
+                entry.0 += bit.coverage as f64;
+                entry.1 += 1;
+                end += 1;
+            }
+        }
+        modules
+            .into_iter()
+            .map(|(module, (sum, bits))| {
+                (
+                    module,
+                    ModuleCoverage {
+                        coverage: sum / bits as f64,
+                        bits,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+impl CoverageDiff {
+    pub fn new(baseline: &CoverageReport, current: &CoverageReport) -> Self {
+        let mut diff = CoverageDiff {
+            old_total: baseline.total_coverage(),
+            new_total: current.total_coverage(),
+            ..Default::default()
+        };
+        let old_bits: HashMap<&str, &BitCoverage> = baseline
+            .bits
+            .iter()
+            .map(|bit| (bit.name.as_str(), bit))
+            .collect();
+        let mut matched = 0;
+        for new in current.bits.iter() {
+            let Some(old) = old_bits.get(new.name.as_str()) else {
+                diff.added.push(new.name.clone());
+                continue;
+            };
+            matched += 1;
+            let delta = BitDelta {
+                name: new.name.clone(),
+                old_coverage: old.coverage,
+                new_coverage: new.coverage,
+            };
+            let gained = (new.transitioned_up && !old.transitioned_up)
+                || (new.transitioned_down && !old.transitioned_down);
+            let lost = (old.transitioned_up && !new.transitioned_up)
+                || (old.transitioned_down && !new.transitioned_down);
+            if lost {
+                diff.newly_uncovered.push(delta.clone());
+            }
+            if gained {
+                diff.newly_covered.push(delta);
+            }
+            if old.initial_value != new.initial_value {
+                diff.initial_value_changed.push(InitialValueDelta {
+                    name: new.name.clone(),
+                    old_value: old.initial_value,
+                    new_value: new.initial_value,
+                });
+            }
+        }
+        if matched != baseline.bits.len() {
+            let new_names: HashSet<&str> =
+                current.bits.iter().map(|bit| bit.name.as_str()).collect();
+            diff.removed = baseline
+                .bits
+                .iter()
+                .filter(|bit| !new_names.contains(bit.name.as_str()))
+                .map(|bit| bit.name.clone())
+                .collect();
+        }
+
+        let mut old_modules = baseline.module_coverage();
+        for (module, new) in current.module_coverage() {
+            let old = old_modules.remove(&module);
+            if old != Some(new) {
+                diff.modules.push(ModuleDelta {
+                    module,
+                    old,
+                    new: Some(new),
+                });
+            }
+        }
+        diff.modules
+            .extend(old_modules.into_iter().map(|(module, old)| ModuleDelta {
+                module,
+                old: Some(old),
+                new: None,
+            }));
+        diff.modules.sort_by(|a, b| a.module.cmp(&b.module));
+        diff
+    }
+
+    pub fn to_result_string(&self) -> String {
+        let mut lines = vec![format!(
+            "# VCD coverage diff. Total coverage: {:.2} % -> {:.2} % ({:+.2} %)",
+            self.old_total * 100.0,
+            self.new_total * 100.0,
+            (self.new_total - self.old_total) * 100.0
+        )];
+        lines.push(format!(
+            "# Newly covered bits: {}",
+            self.newly_covered.len()
+        ));
+        lines.extend(self.newly_covered.iter().map(|bit| {
+            format!(
+                "+ {} {:.1} -> {:.1}",
+                bit.name, bit.old_coverage, bit.new_coverage
+            )
+        }));
+        lines.push(format!(
+            "# Newly uncovered bits: {}",
+            self.newly_uncovered.len()
+        ));
+        lines.extend(self.newly_uncovered.iter().map(|bit| {
+            format!(
+                "- {} {:.1} -> {:.1}",
+                bit.name, bit.old_coverage, bit.new_coverage
+            )
+        }));
+        lines.push(format!(
+            "# Initial value changed: {}",
+            self.initial_value_changed.len()
+        ));
+        lines.extend(
+            self.initial_value_changed
+                .iter()
+                .map(|bit| format!("~ {} {} -> {}", bit.name, bit.old_value, bit.new_value)),
+        );
+        lines.push(format!("# Bits only in current: {}", self.added.len()));
+        lines.extend(self.added.iter().map(|name| format!("> {}", name)));
+        lines.push(format!("# Bits only in baseline: {}", self.removed.len()));
+        lines.extend(self.removed.iter().map(|name| format!("< {}", name)));
+        lines.push(format!("# Module deltas: {}", self.modules.len()));
+        lines.extend(self.modules.iter().map(|module| {
+            let old = module.old.map_or(0.0, |m| m.coverage);
+            let new = module.new.map_or(0.0, |m| m.coverage);
+            format!(
+                "{} {} -> {} ({:+.2} %)",
+                module.module,
+                format_module_coverage(module.old),
+                format_module_coverage(module.new),
+                (new - old) * 100.0
+            )
+        }));
+        lines.join("\n")
+    }
+
+    pub fn to_format_string(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.to_result_string(),
+            ReportFormat::Json => self.to_json_string(),
+        }
+    }
+
+    pub fn save(&self, path: &str, format: ReportFormat) -> Result<(), String> {
+        let mut writer = BufWriter::new(File::create(path).map_err(|err| err.to_string())?);
+        writer
+            .write_fmt(format_args!("{}", self.to_format_string(format)))
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn to_json_string(&self) -> String {
+        let bit_deltas = |bits: &[BitDelta]| {
+            bits.iter()
+                .map(|bit| {
+                    format!(
+                        "{{\"name\":\"{}\",\"old_coverage\":{:.1},\"new_coverage\":{:.1}}}",
+                        json_escape(&bit.name),
+                        bit.old_coverage,
+                        bit.new_coverage
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let names = |names: &[String]| {
+            names
+                .iter()
+                .map(|name| format!("\"{}\"", json_escape(name)))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let initial_values = self
+            .initial_value_changed
+            .iter()
+            .map(|bit| {
+                format!(
+                    "{{\"name\":\"{}\",\"old_value\":\"{}\",\"new_value\":\"{}\"}}",
+                    json_escape(&bit.name),
+                    bit.old_value,
+                    bit.new_value
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let module_coverage = |coverage: Option<ModuleCoverage>| match coverage {
+            Some(m) => format!("{{\"coverage\":{:.4},\"bits\":{}}}", m.coverage, m.bits),
+            None => "null".into(),
+        };
+        let modules = self
+            .modules
+            .iter()
+            .map(|module| {
+                format!(
+                    "{{\"module\":\"{}\",\"old\":{},\"new\":{}}}",
+                    json_escape(&module.module),
+                    module_coverage(module.old),
+                    module_coverage(module.new)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            "{{\"old_total\":{:.4},\"new_total\":{:.4},\"newly_covered\":[{}],\"newly_uncovered\":[{}],\"initial_value_changed\":[{}],\"added\":[{}],\"removed\":[{}],\"modules\":[{}]}}",
+            self.old_total,
+            self.new_total,
+            bit_deltas(&self.newly_covered),
+            bit_deltas(&self.newly_uncovered),
+            initial_values,
+            names(&self.added),
+            names(&self.removed),
+            modules
+        )
+    }
+}
+
+fn format_module_coverage(coverage: Option<ModuleCoverage>) -> String {
+    match coverage {
+        Some(m) => format!("{:.2} %", m.coverage * 100.0),
+        None => "-".into(),
+    }
+}
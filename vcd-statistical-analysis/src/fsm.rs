@@ -0,0 +1,364 @@
+use std::fs;
+
+use crate::{json_escape, values::parse_value, window::WindowState, BusValue, VCD};
+
+/// States and legal transitions of the state register at `signal` (e.g. `top/ctrl/state`).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FsmDescription {
+    pub signal: String,
+    /// State names and their encodings
+    pub states: Vec<(String, u128)>,
+    /// Legal transitions between state names
+    pub arcs: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FsmState {
+    pub name: String,
+    pub encoding: BusValue,
+    pub visits: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FsmArc {
+    pub from: String,
+    pub to: String,
+    pub taken: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FsmTransition {
+    pub from: String,
+    pub to: String,
+    pub time: i64,
+}
+
+/// Coverage of a state register. The register is sampled once per timestamp, so values
+/// it only holds in the middle of a timestamp are neither visits nor transitions.
+#[derive(Debug, Clone)]
+pub struct Fsm {
+    pub signal: String,
+    /// Found by naming convention instead of described: every value is a legal state
+    /// and every transition a legal arc
+    pub detected: bool,
+    pub states: Vec<FsmState>,
+    pub arcs: Vec<FsmArc>,
+    pub illegal: Vec<FsmTransition>,
+    pub(crate) committed: Option<BusValue>,
+    pending: Option<(BusValue, i64)>,
+}
+
+impl FsmDescription {
+    pub fn from_file(path: &str) -> Result<Vec<Self>, String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        Self::from_config_string(&content).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    /// Parses blocks like
+    /// ```text
+    /// fsm top/ctrl/state
+    /// state IDLE 0
+    /// state RUN 0b01
+    /// arc IDLE RUN
+    /// ```
+    /// Encodings are decimal, `0x` hexadecimal or `0b` binary; `#` starts a comment line.
+    pub fn from_config_string(content: &str) -> Result<Vec<Self>, String> {
+        let mut fsms: Vec<FsmDescription> = vec![];
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("Line {}: {}", index + 1, message);
+            let words: Vec<&str> = line.split_ascii_whitespace().collect();
+            if let ["fsm", signal] = words.as_slice() {
+                fsms.push(FsmDescription {
+                    signal: signal.to_string(),
+                    ..Default::default()
+                });
+                continue;
+            }
+            let fsm = fsms
+                .last_mut()
+                .ok_or_else(|| error(format!("{} outside of an fsm", line)))?;
+            match words.as_slice() {
+                ["state", name, encoding] => {
+                    let encoding = parse_value(encoding).map_err(error)?;
+                    if let Some((other, _)) =
+                        fsm.states.iter().find(|(n, e)| n == name || *e == encoding)
+                    {
+                        return Err(error(format!("state {} redefines state {}", name, other)));
+                    }
+                    fsm.states.push((name.to_string(), encoding));
+                }
+                ["arc", from, to] => {
+                    for state in [from, to] {
+                        if !fsm.states.iter().any(|(name, _)| name == state) {
+                            return Err(error(format!("unknown state {}", state)));
+                        }
+                    }
+                    fsm.arcs.push((from.to_string(), to.to_string()));
+                }
+                _ => return Err(error(format!("unrecognized line {}", line))),
+            }
+        }
+        Ok(fsms)
+    }
+}
+
+/// Register names marking a state machine.
+pub(crate) fn is_state_register(name: &str) -> bool {
+    name.ends_with("_state") || name.ends_with("_fsm")
+}
+
+impl Fsm {
+    pub(crate) fn new(description: &FsmDescription) -> Self {
+        Fsm {
+            signal: description.signal.clone(),
+            detected: false,
+            states: description
+                .states
+                .iter()
+                .map(|(name, encoding)| FsmState {
+                    name: name.clone(),
+                    encoding: BusValue::Known(*encoding),
+                    visits: 0,
+                })
+                .collect(),
+            arcs: description
+                .arcs
+                .iter()
+                .map(|(from, to)| FsmArc {
+                    from: from.clone(),
+                    to: to.clone(),
+                    taken: 0,
+                })
+                .collect(),
+            illegal: vec![],
+            committed: None,
+            pending: None,
+        }
+    }
+
+    pub(crate) fn detected(signal: String) -> Self {
+        Fsm {
+            detected: true,
+            ..Fsm::new(&FsmDescription {
+                signal,
+                ..Default::default()
+            })
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.states.iter_mut().for_each(|state| state.visits = 0);
+        self.arcs.iter_mut().for_each(|arc| arc.taken = 0);
+        self.illegal.clear();
+    }
+
+    /// Index of the state encoded by `value`, adding it if the FSM was detected.
+    fn state(&mut self, value: &BusValue) -> Option<usize> {
+        match self
+            .states
+            .iter()
+            .position(|state| state.encoding == *value)
+        {
+            None if self.detected && matches!(value, BusValue::Known(_)) => {
+                self.states.push(FsmState {
+                    name: value.to_string(),
+                    encoding: value.clone(),
+                    visits: 0,
+                });
+                Some(self.states.len() - 1)
+            }
+            index => index,
+        }
+    }
+
+    fn name(&self, value: &BusValue) -> String {
+        match self.states.iter().find(|state| state.encoding == *value) {
+            Some(state) => state.name.clone(),
+            None => value.to_string(),
+        }
+    }
+
+    /// Counts the current state as visited, used when the window opens.
+    pub(crate) fn visit_current(&mut self) {
+        if let Some(state) = self.committed.clone().and_then(|value| self.state(&value)) {
+            self.states[state].visits += 1;
+        }
+    }
+
+    pub(crate) fn add_change(&mut self, value: BusValue, time: i64) {
+        self.pending = Some((value, time));
+    }
+
+    /// Takes the last value of the timestamp that just ended.
+    pub(crate) fn commit(&mut self, count: bool) {
+        let Some((value, time)) = self.pending.take() else {
+            return;
+        };
+        let previous = self.committed.replace(value.clone());
+        if previous.as_ref() == Some(&value) || !count {
+            return;
+        }
+        if let Some(to) = self.state(&value) {
+            self.states[to].visits += 1;
+        }
+        // Leaving X/Z or undescribed values, as after a reset, is not a transition
+        let Some(from) = previous.and_then(|previous| self.state(&previous)) else {
+            return;
+        };
+        let (from, to) = (self.states[from].name.clone(), self.name(&value));
+        match self
+            .arcs
+            .iter_mut()
+            .find(|arc| arc.from == from && arc.to == to)
+        {
+            Some(arc) => arc.taken += 1,
+            None if self.detected => self.arcs.push(FsmArc { from, to, taken: 1 }),
+            None => self.illegal.push(FsmTransition { from, to, time }),
+        }
+    }
+
+    /// Fractions of the described states visited and arcs taken, `None` when detected.
+    pub fn coverage(&self) -> Option<(f64, f64)> {
+        let fraction = |hit: usize, total: usize| match total {
+            0 => 1.0,
+            total => hit as f64 / total as f64,
+        };
+        (!self.detected).then(|| {
+            (
+                fraction(
+                    self.states.iter().filter(|state| state.visits > 0).count(),
+                    self.states.len(),
+                ),
+                fraction(
+                    self.arcs.iter().filter(|arc| arc.taken > 0).count(),
+                    self.arcs.len(),
+                ),
+            )
+        })
+    }
+
+    fn to_result_string(&self) -> String {
+        let coverage = match self.coverage() {
+            Some((states, arcs)) => format!(
+                ", states {:.2} %, arcs {:.2} %",
+                states * 100.0,
+                arcs * 100.0
+            ),
+            None => ", detected".into(),
+        };
+        let mut lines = vec![format!(
+            "{}{}, {} illegal transitions",
+            self.signal,
+            coverage,
+            self.illegal.len()
+        )];
+        lines.extend(
+            self.states
+                .iter()
+                .map(|state| format!("  state {} {} {}", state.name, state.encoding, state.visits)),
+        );
+        lines.extend(
+            self.arcs
+                .iter()
+                .map(|arc| format!("  arc {} {} {}", arc.from, arc.to, arc.taken)),
+        );
+        lines.extend(
+            self.illegal
+                .iter()
+                .map(|t| format!("  illegal {} {} {}", t.from, t.to, t.time)),
+        );
+        lines.join("\n")
+    }
+
+    fn to_json_string(&self) -> String {
+        let states: Vec<String> = self
+            .states
+            .iter()
+            .map(|state| {
+                format!(
+                    "{{\"name\":\"{}\",\"encoding\":\"{}\",\"visits\":{}}}",
+                    json_escape(&state.name),
+                    state.encoding,
+                    state.visits
+                )
+            })
+            .collect();
+        let arcs: Vec<String> = self
+            .arcs
+            .iter()
+            .map(|arc| {
+                format!(
+                    "{{\"from\":\"{}\",\"to\":\"{}\",\"taken\":{}}}",
+                    json_escape(&arc.from),
+                    json_escape(&arc.to),
+                    arc.taken
+                )
+            })
+            .collect();
+        let illegal: Vec<String> = self
+            .illegal
+            .iter()
+            .map(|t| {
+                format!(
+                    "{{\"from\":\"{}\",\"to\":\"{}\",\"time\":{}}}",
+                    json_escape(&t.from),
+                    json_escape(&t.to),
+                    t.time
+                )
+            })
+            .collect();
+        let (state_coverage, arc_coverage) = match self.coverage() {
+            Some((states, arcs)) => (format!("{:.4}", states), format!("{:.4}", arcs)),
+            None => ("null".into(), "null".into()),
+        };
+        format!(
+            "{{\"signal\":\"{}\",\"detected\":{},\"state_coverage\":{},\"arc_coverage\":{},\"states\":[{}],\"arcs\":[{}],\"illegal\":[{}]}}",
+            json_escape(&self.signal),
+            self.detected,
+            state_coverage,
+            arc_coverage,
+            states.join(","),
+            arcs.join(","),
+            illegal.join(",")
+        )
+    }
+}
+
+impl VCD {
+    /// Checks that every described state register was declared. Must be called after the
+    /// definitions.
+    pub(crate) fn check_fsms(&self) -> Result<(), String> {
+        match self
+            .fsm_descriptions
+            .iter()
+            .find(|description| !self.fsms.iter().any(|fsm| fsm.signal == description.signal))
+        {
+            Some(description) => Err(format!("FSM signal {} not found", description.signal)),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn commit_fsms(&mut self) {
+        let count = self.window_state == WindowState::Open;
+        self.fsms.iter_mut().for_each(|fsm| fsm.commit(count));
+    }
+
+    pub fn to_fsm_string(&self) -> String {
+        let mut lines = vec![format!(
+            "# FSM coverage over {} state registers",
+            self.fsms.len()
+        )];
+        lines.push("# Register, coverage; then state name/encoding/visits, arc from/to/taken, illegal from/to/time".into());
+        lines.extend(self.fsms.iter().map(|fsm| fsm.to_result_string()));
+        lines.join("\n")
+    }
+
+    pub fn to_fsm_json_string(&self) -> String {
+        let fsms: Vec<String> = self.fsms.iter().map(|fsm| fsm.to_json_string()).collect();
+        format!("{{\"fsms\":[{}]}}", fsms.join(","))
+    }
+}
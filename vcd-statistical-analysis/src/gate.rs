@@ -0,0 +1,163 @@
+use std::fs;
+
+use crate::{CoverageDiff, CoverageReport};
+
+/// Minimum coverage percentages a run must reach.
+#[derive(Debug, Default, Clone)]
+pub struct Thresholds {
+    pub min_total: Option<f64>,
+    pub modules: Vec<(String, f64)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GateFailure {
+    TotalBelowMinimum {
+        actual: f64,
+        minimum: f64,
+    },
+    ModuleBelowMinimum {
+        module: String,
+        actual: f64,
+        minimum: f64,
+    },
+    MissingModule {
+        module: String,
+    },
+    NewUncoveredBits {
+        bits: Vec<String>,
+    },
+}
+
+/// Percentage between 0 and 100, with or without a trailing `%`.
+pub fn parse_percent(value: &str) -> Result<f64, String> {
+    match value.trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent),
+        _ => Err(format!("invalid percentage {}", value)),
+    }
+}
+
+impl Thresholds {
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        Self::from_config_string(&content).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    /// Parses a threshold file. Every line is either `total <percent>` or
+    /// `module <path> <percent>`; empty lines and lines starting with `#` are skipped.
+    pub fn from_config_string(content: &str) -> Result<Self, String> {
+        let mut thresholds = Thresholds::default();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words: Vec<&str> = line.split_ascii_whitespace().collect();
+            let parse_percent = |value: &str| {
+                parse_percent(value).map_err(|err| format!("Line {}: {}", index + 1, err))
+            };
+            match words.as_slice() {
+                ["total", percent] => thresholds.min_total = Some(parse_percent(percent)?),
+                ["module", module, percent] => thresholds
+                    .modules
+                    .push((module.to_string(), parse_percent(percent)?)),
+                _ => {
+                    return Err(format!(
+                        "Line {}: unrecognized threshold {}",
+                        index + 1,
+                        line
+                    ))
+                }
+            }
+        }
+        Ok(thresholds)
+    }
+}
+
+impl GateFailure {
+    /// Process exit code for the failure. 1 is left for analysis errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            GateFailure::TotalBelowMinimum { .. } => 2,
+            GateFailure::ModuleBelowMinimum { .. } | GateFailure::MissingModule { .. } => 3,
+            GateFailure::NewUncoveredBits { .. } => 4,
+        }
+    }
+
+    pub fn to_result_string(&self) -> String {
+        match self {
+            GateFailure::TotalBelowMinimum { actual, minimum } => {
+                format!("Total coverage {:.2} % is below {:.2} %", actual, minimum)
+            }
+            GateFailure::ModuleBelowMinimum {
+                module,
+                actual,
+                minimum,
+            } => format!(
+                "Module {} coverage {:.2} % is below {:.2} %",
+                module, actual, minimum
+            ),
+            GateFailure::MissingModule { module } => {
+                format!("Module {} has a threshold but no signals", module)
+            }
+            GateFailure::NewUncoveredBits { bits } => {
+                const SHOWN: usize = 5;
+                let mut names = bits.iter().take(SHOWN).cloned().collect::<Vec<_>>();
+                if bits.len() > SHOWN {
+                    names.push(format!("... {} more", bits.len() - SHOWN));
+                }
+                format!(
+                    "{} bits lost coverage against the baseline: {}",
+                    bits.len(),
+                    names.join(", ")
+                )
+            }
+        }
+    }
+}
+
+/// Checks a run against the thresholds and, if given, against a baseline run.
+/// Failures are returned in check order: total, modules, baseline.
+pub fn check_gates(
+    report: &CoverageReport,
+    thresholds: &Thresholds,
+    baseline: Option<&CoverageReport>,
+) -> Vec<GateFailure> {
+    let mut failures = vec![];
+    if let Some(minimum) = thresholds.min_total {
+        let actual = report.total_coverage() * 100.0;
+        if actual < minimum {
+            failures.push(GateFailure::TotalBelowMinimum { actual, minimum });
+        }
+    }
+    if !thresholds.modules.is_empty() {
+        let modules = report.module_coverage();
+        for (module, minimum) in thresholds.modules.iter() {
+            match modules.get(module.trim_end_matches('/')) {
+                Some(coverage) if coverage.coverage * 100.0 < *minimum => {
+                    failures.push(GateFailure::ModuleBelowMinimum {
+                        module: module.clone(),
+                        actual: coverage.coverage * 100.0,
+                        minimum: *minimum,
+                    })
+                }
+                Some(_) => {}
+                None => failures.push(GateFailure::MissingModule {
+                    module: module.clone(),
+                }),
+            }
+        }
+    }
+    if let Some(baseline) = baseline {
+        let diff = CoverageDiff::new(baseline, report);
+        if !diff.newly_uncovered.is_empty() {
+            failures.push(GateFailure::NewUncoveredBits {
+                bits: diff
+                    .newly_uncovered
+                    .into_iter()
+                    .map(|bit| bit.name)
+                    .collect(),
+            });
+        }
+    }
+    failures
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+
+/// JSON value, as read back from the reports.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+impl Json {
+    pub(crate) fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            position: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_spaces();
+        match parser.position < parser.text.len() {
+            true => parser.error("Unexpected text"),
+            false => Ok(value),
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.get(key),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        Err(format!("{} at byte {}", message, self.position))
+    }
+
+    fn skip_spaces(&mut self) {
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(|c| c.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        self.skip_spaces();
+        let found = self.text.get(self.position) == Some(&c);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Json) -> Result<Json, String> {
+        match self.text[self.position..].starts_with(keyword.as_bytes()) {
+            true => {
+                self.position += keyword.len();
+                Ok(value)
+            }
+            false => self.error("Unexpected text"),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_spaces();
+        match self.text.get(self.position) {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(c) if *c == b'-' || c.is_ascii_digit() => self.number(),
+            Some(_) => self.error("Unexpected text"),
+            None => self.error("Unexpected end"),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.position += 1;
+        let mut fields = BTreeMap::new();
+        if self.eat(b'}') {
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_spaces();
+            if self.text.get(self.position) != Some(&b'"') {
+                return self.error("Expected a key");
+            }
+            let key = self.string()?;
+            if !self.eat(b':') {
+                return self.error("Expected :");
+            }
+            fields.insert(key, self.value()?);
+            if self.eat(b'}') {
+                return Ok(Json::Object(fields));
+            }
+            if !self.eat(b',') {
+                return self.error("Expected , or }");
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.position += 1;
+        let mut values = vec![];
+        if self.eat(b']') {
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            if self.eat(b']') {
+                return Ok(Json::Array(values));
+            }
+            if !self.eat(b',') {
+                return self.error("Expected , or ]");
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.position += 1;
+        let mut value = vec![];
+        loop {
+            match self.text.get(self.position) {
+                None => return self.error("Unterminated string"),
+                Some(b'"') => {
+                    self.position += 1;
+                    return String::from_utf8(value).or_else(|_| self.error("Invalid UTF-8"));
+                }
+                Some(b'\\') => {
+                    let escaped = match self.text.get(self.position + 1) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'n') => '\n',
+                        Some(b't') => '\t',
+                        Some(b'r') => '\r',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'u') => {
+                            let digits = self
+                                .text
+                                .get(self.position + 2..self.position + 6)
+                                .and_then(|digits| std::str::from_utf8(digits).ok())
+                                .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                                .and_then(char::from_u32);
+                            match digits {
+                                Some(c) => {
+                                    self.position += 4;
+                                    c
+                                }
+                                None => return self.error("Invalid escape"),
+                            }
+                        }
+                        _ => return self.error("Invalid escape"),
+                    };
+                    self.position += 2;
+                    value.extend_from_slice(escaped.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                Some(c) => {
+                    value.push(*c);
+                    self.position += 1;
+                }
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.position;
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(|c| c.is_ascii_digit() || b"+-.eE".contains(c))
+        {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.text[start..self.position])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .map(Json::Number)
+            .map_or_else(|| self.error("Invalid number"), Ok)
+    }
+}
@@ -1,66 +1,148 @@
-use logger::{Log, Priority};
-use spinners::{Spinner, Spinners};
+//! Toggle coverage and related analyses of VCD dumps.
+//!
+//! [`perform_analysis`] reads a dump and returns a [`VCD`] that can be saved as a report
+//! or queried directly:
+//!
+//! ```no_run
+//! use vcd_statistical_analysis::{perform_analysis, Configuration};
+//!
+//! let vcd = perform_analysis(Configuration {
+//!     in_file: "dump.vcd".into(),
+//!     separator: ' ',
+//!     ..Default::default()
+//! })
+//! .unwrap();
+//! println!("total {:.2} %", vcd.total_coverage() * 100.0);
+//! for bit in vcd.counted_signals().filter(|bit| bit.calculate_coverage() < 1.0) {
+//!     println!("{} first toggled at {:?}", bit.path(), bit.first_toggle());
+//! }
+//! if let Some(module) = vcd.module_summary().get("top/cpu") {
+//!     println!("top/cpu {:.2} % over {} bits", module.coverage * 100.0, module.bits);
+//! }
+//! ```
+
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs::File,
     io::{BufWriter, Write},
     rc::Rc,
-    sync::mpsc::{self, Receiver},
-    thread,
-    time::Instant,
+    sync::Arc,
+};
+use vcd_reader::{SignalValue, VCDFile};
+
+mod activity;
+mod cancel;
+mod checker;
+mod clock;
+mod decoder;
+mod diff;
+mod fsm;
+mod gate;
+mod json;
+mod progress;
+mod saif;
+mod translator;
+mod values;
+mod window;
+mod xz;
+
+pub use activity::Activity;
+pub use cancel::CancelToken;
+pub use checker::{Edge, Expr, Property, PropertyChecker, Violation};
+pub use clock::{
+    EdgeStats, SignalKind, MAX_CLOCK_JITTER, MIN_CLOCK_EDGES, RESET_ASSERT, RESET_RELEASE,
+};
+pub use decoder::{
+    Decoder, DecoderBuilder, DecoderDescription, DecoderInstance, Handshake, I2c, Protocols, Spi,
+    Transaction, Uart,
 };
-use vcd_reader::{Change, SignalValue};
-use vcd_reader::{LineInfo, LineValue, VCDFile};
+pub use diff::{
+    perform_diff_and_save, BitCoverage, BitDelta, CoverageDiff, CoverageReport, DiffConfiguration,
+    InitialValueDelta, ModuleCoverage, ModuleDelta, ReportFormat,
+};
+pub use fsm::{Fsm, FsmArc, FsmDescription, FsmState, FsmTransition};
+pub use gate::{check_gates, parse_percent, GateFailure, Thresholds};
+pub use progress::{Phase, ProgressSink};
+use translator::Translator;
+pub use values::{BinHits, Bus, BusValue, ValueBin, MAX_DISTINCT_VALUES};
+use window::WindowState;
+pub use window::{TimeWindow, WindowBound};
+pub use xz::XZActivity;
 
+#[derive(Default, Clone)]
 pub struct Configuration {
     pub in_file: String,
     pub out_file: String,
     pub separator: char,
-    pub use_spinner: bool,
+    /// Receives the phases and the bytes read
+    pub progress: Option<Arc<dyn ProgressSink>>,
+    /// Stops the analysis, keeping the changes read until then
+    pub cancel: CancelToken,
+    /// Each window gets its own report. No windows means the whole dump.
+    pub windows: Vec<TimeWindow>,
+    /// Computes the switching activity of every bit
+    pub statistics: bool,
+    pub format: ReportFormat,
+    /// Tracks X and Z values of every bit
+    pub xz_analysis: bool,
+    /// End of the reset for the X/Z analysis. `None` means the end of the initializations.
+    pub reset_end: Option<WindowBound>,
+    /// Records the values taken by every bus
+    pub value_coverage: bool,
+    pub value_bins: Vec<ValueBin>,
+    /// Described state registers
+    pub fsms: Vec<FsmDescription>,
+    /// Also tracks the registers named `*_state` or `*_fsm`
+    pub detect_fsms: bool,
+    /// Classifies the bits as clocks, resets or data
+    pub detect_clocks_resets: bool,
+    /// Leaves clocks and resets out of the coverage totals. Implies `detect_clocks_resets`.
+    pub exclude_clocks_resets: bool,
+    /// Properties checked over the dump
+    pub properties: Vec<Property>,
+    /// Protocol decoders run over the dump
+    pub decoders: Vec<DecoderDescription>,
+    /// Protocols of the decoders: the built-in ones, unless others are registered
+    pub protocols: Protocols,
+}
+
+/// Result file of the window at `index`: `out_file` itself when there is only one window,
+/// `out_file.<index>` otherwise.
+pub fn window_out_file(out_file: &str, index: usize, windows: usize) -> String {
+    match windows {
+        0 | 1 => out_file.into(),
+        _ => format!("{}.{}", out_file, index),
+    }
 }
 
 pub fn perform_analysis_and_save(c: Configuration) -> Result<(), String> {
     let out_file = c.out_file.clone();
-    let vcd = perform_analysis(c)?;
-    let mut writer = BufWriter::new(File::create(out_file).map_err(|err| err.to_string())?);
-    writer
-        .write_fmt(format_args!("{}", vcd.to_result_string()))
-        .map_err(|err| err.to_string())
+    let format = c.format;
+    let vcds = perform_windowed_analysis(c)?;
+    for (index, vcd) in vcds.iter().enumerate() {
+        vcd.save(&window_out_file(&out_file, index, vcds.len()), format)?;
+    }
+    Ok(())
 }
 
+/// Analysis of the whole dump, or of the first window if any is configured.
 pub fn perform_analysis(c: Configuration) -> Result<VCD, String> {
-    let (tx, rx) = mpsc::sync_channel(1000000);
-    let th = thread::spawn(move || translate_infos(rx, c.use_spinner));
-    let reader_config = vcd_reader::Configuration {
-        in_file: &c.in_file,
-        separator: c.separator,
-    };
-
-    let result: Vec<String> = VCDFile::new(reader_config)?
-        .map(|info| {
-            if let Ok(in_info) = info {
-                let _ = tx.send(in_info);
-                Ok(())
-            } else {
-                Err(info.err().unwrap())
-            }
-        })
-        .filter(|res| res.is_err())
-        .map(|res| res.err().unwrap())
-        .collect();
-    drop(tx);
-    if !result.is_empty() {
-        return Err(result.join("\n"));
-    }
-    th.join().unwrap()
+    Ok(perform_windowed_analysis(c)?.swap_remove(0))
 }
 
-struct InfoTranslator {
-    modules: Vec<Rc<str>>,
+/// One analysis per configured window, in the same order.
+pub fn perform_windowed_analysis(c: Configuration) -> Result<Vec<VCD>, String> {
+    let mut reader = VCDFile::new(vcd_reader::Configuration {
+        in_file: &c.in_file,
+        separator: c.separator,
+    })?;
+    let mut translator = Translator::new(&c);
+    reader.visit(&mut translator)?;
+    translator.finish()
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct State {
     pub value: SignalValue,
     pub time: i64,
@@ -75,16 +157,38 @@ impl Default for State {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Signal {
     pub id: Rc<str>,
     pub sub_id: u16,
     pub name: Vec<Rc<str>>,
     pub states: [State; 3], // Initial state, opposite state, back to initial state
     pub initial_state: State,
+    pub current_state: State,
+    pub activity: Option<Activity>,
+    pub xz: Option<XZActivity>,
+    pub edges: Option<EdgeStats>,
+    /// Data unless the clock and reset detection is enabled
+    pub kind: SignalKind,
+    /// Time of the first 0->1 transition in the window
+    pub first_rise: Option<i64>,
+    /// Time of the first 1->0 transition in the window
+    pub first_fall: Option<i64>,
 }
 
 impl Signal {
+    fn initialize(&mut self, state: State) {
+        self.states[0] = state;
+        self.states[1] = state;
+        self.initial_state = state;
+        self.current_state = state;
+        self.first_rise = None;
+        self.first_fall = None;
+        if self.activity.is_some() {
+            self.activity = Some(Activity::new(state));
+        }
+    }
+
     fn add_change(&mut self, state: State) {
         if state.value != SignalValue::X {
             match self.states[0].value {
@@ -122,13 +226,40 @@ impl Signal {
         }
     }
 
-    fn calculate_coverage(&self) -> f32 {
+    /// Hierarchical name, e.g. `top/cnt/count/[0]`.
+    pub fn path(&self) -> String {
+        self.name.join("/")
+    }
+
+    /// Time of the first transition between 0 and 1 in the window.
+    pub fn first_toggle(&self) -> Option<i64> {
+        match (self.first_rise, self.first_fall) {
+            (Some(rise), Some(fall)) => Some(rise.min(fall)),
+            (rise, fall) => rise.or(fall),
+        }
+    }
+
+    fn record_toggle(&mut self, previous: SignalValue, state: State) {
+        match (previous, state.value) {
+            (SignalValue::DOWN, SignalValue::UP) => {
+                self.first_rise.get_or_insert(state.time);
+            }
+            (SignalValue::UP, SignalValue::DOWN) => {
+                self.first_fall.get_or_insert(state.time);
+            }
+            _ => {}
+        }
+    }
+
+    /// Toggle coverage of the bit: 0.5 for each direction it transitioned in. The
+    /// direction leaving the initial value counts from the start.
+    pub fn calculate_coverage(&self) -> f32 {
         let up_transition = 0.5 * (self.has_transitioned_up() as u32 as f32);
         let down_transition = 0.5 * (self.has_transitioned_down() as u32 as f32);
         up_transition + down_transition
     }
 
-    fn has_transitioned_up(&self) -> bool {
+    pub fn has_transitioned_up(&self) -> bool {
         match self.states[0].value {
             SignalValue::UP => self.states[2].value != SignalValue::X,
             SignalValue::DOWN => self.states[1].value != SignalValue::X,
@@ -136,7 +267,7 @@ impl Signal {
         }
     }
 
-    fn has_transitioned_down(&self) -> bool {
+    pub fn has_transitioned_down(&self) -> bool {
         match self.states[0].value {
             SignalValue::UP => self.states[1].value != SignalValue::X,
             SignalValue::DOWN => self.states[2].value != SignalValue::X,
@@ -146,34 +277,110 @@ impl Signal {
 
     fn to_result_string(&self) -> String {
         let initial_value: char = self.initial_state.value.into();
+        let activity = match &self.activity {
+            Some(activity) => format!(" {}", activity.to_result_string()),
+            None => String::new(),
+        };
         format!(
-            "{} {}-{} {:.1} {} {} {}",
+            "{} {}-{} {:.1} {} {} {}{}",
             self.name.join("/"),
             self.id,
             self.sub_id,
             self.calculate_coverage(),
             self.has_transitioned_up() as u8,
             self.has_transitioned_down() as u8,
-            initial_value
+            initial_value,
+            activity
+        )
+    }
+
+    fn to_json_string(&self) -> String {
+        let initial_value: char = self.initial_state.value.into();
+        let activity = match &self.activity {
+            Some(activity) => format!(",\"statistics\":{}", activity.to_json_string()),
+            None => String::new(),
+        };
+        let kind = match &self.edges {
+            Some(_) => format!(",\"kind\":{}", self.kind.to_json_string()),
+            None => String::new(),
+        };
+        format!(
+            "{{\"name\":\"{}\",\"id\":\"{}\",\"sub_id\":{},\"coverage\":{:.1},\"transitioned_up\":{},\"transitioned_down\":{},\"initial_value\":\"{}\"{}{}}}",
+            json_escape(&self.name.join("/")),
+            json_escape(&self.id),
+            self.sub_id,
+            self.calculate_coverage(),
+            self.has_transitioned_up(),
+            self.has_transitioned_down(),
+            initial_value,
+            activity,
+            kind
         )
     }
 
-    fn result_explanation() -> &'static str {
+    /// Header describing the columns of the text report.
+    pub fn result_explanation() -> &'static str {
         "# Signal name, id-sub_id, coverage [%], has transitioned up, has transitioned down, initial value"
     }
 }
 
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct VCD {
     pub signals: Vec<Signal>,
     pub signals_by_id: HashMap<Rc<str>, usize>,
+    /// First bit with each path, filled at the end of the definitions
+    signals_by_path: HashMap<String, usize>,
+    /// Bits of each bus, most significant first
+    bits_by_bus: HashMap<String, Vec<usize>>,
+    pub window: Option<TimeWindow>,
+    window_state: WindowState,
+    window_markers: [Option<usize>; 2],
+    window_open_time: i64,
+    statistics: bool,
+    xz_analysis: bool,
+    pub reset_end: Option<WindowBound>,
+    reset_marker: Option<usize>,
+    reset_done: bool,
+    value_coverage: bool,
+    pub buses: Vec<Bus>,
+    buses_by_id: HashMap<Rc<str>, usize>,
+    fsm_descriptions: Vec<FsmDescription>,
+    detect_fsms: bool,
+    pub fsms: Vec<Fsm>,
+    fsms_by_id: HashMap<Rc<str>, usize>,
+    detect_clocks_resets: bool,
+    exclude_clocks_resets: bool,
+    pub properties: Vec<PropertyChecker>,
+    pub decoders: Vec<DecoderInstance>,
+    pub timescale: Option<String>,
+    /// Length of the analyzed time span, known once the changes are read
+    pub duration: i64,
+    /// Time the analysis was cancelled at, if it was
+    pub cancelled_at: Option<i64>,
 }
 
 impl VCD {
-    fn push(&mut self, signal: vcd_reader::Signal, translator: &InfoTranslator) {
-        let mut modules = translator.modules.clone();
+    fn push(&mut self, signal: vcd_reader::Signal, modules: &[Rc<str>]) {
+        let mut modules = modules.to_vec();
         modules.push(signal.name);
+        if self.value_coverage && signal.num_values > 1 {
+            self.buses_by_id.insert(signal.id.clone(), self.buses.len());
+            self.buses
+                .push(Bus::new(modules.join("/"), signal.num_values));
+        }
+        let path = modules.join("/");
+        let fsm = match self.fsm_descriptions.iter().find(|fsm| fsm.signal == path) {
+            Some(description) => Some(Fsm::new(description)),
+            None if self.detect_fsms && fsm::is_state_register(modules.last().unwrap()) => {
+                Some(Fsm::detected(path))
+            }
+            None => None,
+        };
+        if let Some(fsm) = fsm {
+            self.fsms_by_id.insert(signal.id.clone(), self.fsms.len());
+            self.fsms.push(fsm);
+        }
         for sub_id in 0..signal.num_values {
             let mut name = modules.clone();
             if signal.num_values > 1 {
@@ -185,6 +392,15 @@ impl VCD {
                 name,
                 states: Default::default(),
                 initial_state: Default::default(),
+                current_state: Default::default(),
+                activity: self.statistics.then(|| Activity::new(State::default())),
+                xz: self.xz_analysis.then(|| XZActivity::new(State::default())),
+                edges: self
+                    .detect_clocks_resets
+                    .then(|| EdgeStats::new(State::default())),
+                kind: SignalKind::Data,
+                first_rise: None,
+                first_fall: None,
             };
             let index = self.signals.len();
             self.signals.push(s);
@@ -195,217 +411,282 @@ impl VCD {
         }
     }
 
-    fn get_signal(&mut self, id: &str, sub_id: usize) -> &mut Signal {
-        &mut self.signals[self.signals_by_id.get(id).unwrap() + sub_id]
+    /// The bit at `path`, e.g. `top/clk` or `top/cnt/count/[0]`.
+    pub fn bit(&self, path: &str) -> Option<&Signal> {
+        self.find_signal(path).map(|index| &self.signals[index])
     }
 
-    fn add_change(&mut self, change: Change, time: i64) {
-        change
-            .values
+    /// The bits of the bus or bit at `path`, most significant first.
+    pub fn bits_of(&self, path: &str) -> Vec<&Signal> {
+        self.find_bits(path)
             .into_iter()
-            .enumerate()
-            .for_each(|(sub_id, state)| {
-                self.get_signal(&change.signal_id, sub_id)
-                    .add_change(State {
-                        value: SignalValue::from(state),
-                        time,
-                    })
-            })
+            .map(|index| &self.signals[index])
+            .collect()
     }
 
-    fn translate_changes(
-        &mut self,
-        infos: Receiver<LineInfo>,
-        use_spinner: bool,
-    ) -> Result<(), String> {
-        let mut current_timestamp: i64 = -1;
-        let start = Instant::now();
-        Log::write(Priority::Info, "Reading signal changes");
-        let sp = match use_spinner {
-            true => Some(Spinner::new(Spinners::Aesthetic, "".into())),
-            false => None,
-        };
-        for info in infos.into_iter() {
-            match info.value {
-                LineValue::Signal(_) => unreachable!("Error: Signal declaration in initialization"),
-                LineValue::DateInfo(_) => unreachable!("Error: Date info not expected here"),
-                LineValue::VersionInfo(_) => unreachable!("Error: Version info not expected here"),
-                LineValue::Dumpports => unreachable!("Error: Dumpports not expected here"),
-                LineValue::TimeScaleInfo(_) => {
-                    unreachable!("Error: Time scale info not expected here")
-                }
-                LineValue::InScope(_) => unreachable!("Error: Scope definitions not expected here"),
-                LineValue::UpScope => unreachable!("Error: Upscope not expected here"),
-                LineValue::EndDefinitions => {
-                    unreachable!("Error: Definitions should have already ended")
-                }
-                LineValue::EndInitializations => {
-                    unreachable!("Error: Initializations should have already ended")
-                }
-                LineValue::Useless => {}
-                LineValue::ParsingError(s) => {
-                    return Err(format!("Line {}: {}", info.line_number, s));
+    pub fn total_coverage(&self) -> f64 {
+        CoverageReport::from(self).total_coverage()
+    }
+
+    /// Coverage of every module, including the bits of its submodules.
+    pub fn module_summary(&self) -> BTreeMap<String, ModuleCoverage> {
+        CoverageReport::from(self).module_coverage()
+    }
+
+    /// Indexes the paths of the signals, once they are all declared.
+    pub(crate) fn index_paths(&mut self) {
+        for (index, signal) in self.signals.iter().enumerate() {
+            self.signals_by_path
+                .entry(signal.name.join("/"))
+                .or_insert(index);
+            if let Some((bit, bus)) = signal.name.split_last() {
+                if bit.starts_with('[') {
+                    self.bits_by_bus
+                        .entry(bus.join("/"))
+                        .or_default()
+                        .push(index);
                 }
+            }
+        }
+    }
+
+    fn find_signal(&self, path: &str) -> Option<usize> {
+        self.signals_by_path.get(path).copied()
+    }
 
-                LineValue::Timestamp(t) => current_timestamp = t as i64,
-                LineValue::Change(c) => self.add_change(c, current_timestamp),
+    /// Bits of `path`: a single bit, or every bit of a bus most significant first.
+    pub(crate) fn find_bits(&self, path: &str) -> Vec<usize> {
+        if let Some(index) = self.find_signal(path) {
+            return vec![index];
+        }
+        self.bits_by_bus.get(path).cloned().unwrap_or_default()
+    }
+
+    /// Restricts the analysis to `window`. Must be called after the initializations.
+    fn set_window(&mut self, window: TimeWindow) -> Result<(), String> {
+        let mut markers = [None, None];
+        for (marker, bound) in markers.iter_mut().zip([&window.start, &window.end]) {
+            if let Some(WindowBound::Signal { path, .. }) = bound {
+                *marker = Some(
+                    self.find_signal(path)
+                        .ok_or_else(|| format!("Window signal {} not found", path))?,
+                );
             }
         }
-        if let Some(mut s) = sp {
-            s.stop();
+        if window.start.is_some() {
+            // Nothing is covered until the window opens
+            self.window_state = WindowState::Pending;
+            self.signals.iter_mut().for_each(|signal| {
+                signal.states = Default::default();
+                signal.initial_state = Default::default();
+            });
+            self.buses.iter_mut().for_each(|bus| bus.reset());
+            self.fsms.iter_mut().for_each(|fsm| fsm.reset());
         }
-        Log::write(Priority::Info, "Changes read correctly");
-        let end = Instant::now();
-        Log::write(
-            Priority::Info,
-            &format!("Duration: {} s", (end - start).as_millis() as f64 / 1000.0),
-        );
+        self.window = Some(window);
+        self.window_markers = markers;
         Ok(())
     }
 
-    fn translate_initializations(
-        &mut self,
-        infos: Receiver<LineInfo>,
-        use_spinner: bool,
-    ) -> Result<Receiver<LineInfo>, String> {
-        let mut current_timestamp: i64 = 0;
-        let start = Instant::now();
-        Log::write(Priority::Info, "Reading signal initializations");
-        let sp = match use_spinner {
-            true => Some(Spinner::new(Spinners::Aesthetic, "".into())),
-            false => None,
+    fn open_window(&mut self, time: i64) {
+        self.window_state = WindowState::Open;
+        self.window_open_time = time;
+        self.signals.iter_mut().for_each(|signal| {
+            signal.initialize(State {
+                value: signal.current_state.value,
+                time,
+            })
+        });
+        self.buses.iter_mut().for_each(|bus| {
+            if let Some(value) = bus.current.clone() {
+                bus.sample(value);
+            }
+        });
+        self.fsms.iter_mut().for_each(|fsm| fsm.visit_current());
+    }
+
+    /// Whether `time` is inside the window, once it opened and until it closed.
+    pub(crate) fn in_window(&self, time: i64) -> bool {
+        match self.window_state {
+            _ if self.window.is_none() => true,
+            WindowState::Pending => false,
+            WindowState::Open => time >= self.window_open_time,
+            WindowState::Closed => {
+                (self.window_open_time..self.window_open_time + self.duration).contains(&time)
+            }
+        }
+    }
+
+    /// Stops counting at `time`, the end of the window or of the dump.
+    fn close_window(&mut self, time: i64) {
+        if self.window_state != WindowState::Open {
+            return;
+        }
+        self.window_state = WindowState::Closed;
+        self.duration = time - self.window_open_time;
+        self.signals
+            .iter_mut()
+            .filter_map(|signal| signal.activity.as_mut())
+            .for_each(|activity| activity.finish(time));
+    }
+
+    /// Closes every open time span at the end of the dump.
+    fn finish(&mut self, time: i64) {
+        self.check_properties(time);
+        self.decode(time);
+        self.finish_decoders(time);
+        self.commit_fsms();
+        self.close_window(time);
+        self.signals
+            .iter_mut()
+            .filter_map(|signal| signal.xz.as_mut())
+            .for_each(|xz| xz.finish(time));
+        self.classify_signals(time);
+    }
+
+    /// Opens or closes the window at its time bounds, reached between the `previous`
+    /// timestamp and `time`.
+    fn update_window_on_timestamp(&mut self, previous: i64, time: i64) {
+        let Some(window) = &self.window else {
+            return;
         };
-        for info in infos.iter() {
-            match info.value {
-                LineValue::Signal(_) => unreachable!("Error: Signal declaration in initialization"),
-                LineValue::DateInfo(_) => unreachable!("Error: Date info not expected here"),
-                LineValue::VersionInfo(_) => unreachable!("Error: Version info not expected here"),
-                LineValue::TimeScaleInfo(_) => {
-                    unreachable!("Error: Time scale info not expected here")
-                }
-                LineValue::InScope(_) => unreachable!("Error: Scope definitions not expected here"),
-                LineValue::UpScope => unreachable!("Error: Upscope not expected here"),
-                LineValue::EndDefinitions => {
-                    unreachable!("Error: Definitions should have already ended")
-                }
-                LineValue::Useless => {}
-                LineValue::ParsingError(s) => {
-                    return Err(format!("Line: {}: {}", info.line_number, s));
-                }
-                LineValue::EndInitializations => {
-                    if let Some(mut s) = sp {
-                        s.stop();
-                    }
-                    Log::write(Priority::Info, "Signals initialized correctly");
-                    break;
-                }
-                LineValue::Dumpports => Log::write(Priority::Info, "Dumpports found: VCD ok!"),
-                LineValue::Timestamp(t) => current_timestamp = t as i64,
-                LineValue::Change(c) => {
-                    c.values.into_iter().enumerate().for_each(|(index, value)| {
-                        let signal = self.get_signal(&c.signal_id, index);
-                        signal.states[0] = State {
-                            value: SignalValue::from(value),
-                            time: current_timestamp,
-                        };
-                        signal.states[1] = State {
-                            value: SignalValue::from(value),
-                            time: current_timestamp,
-                        };
-                        signal.initial_state = State {
-                            value: SignalValue::from(value),
-                            time: current_timestamp,
-                        };
-                    })
-                }
+        let bound_time = |bound: &Option<WindowBound>| match bound {
+            Some(WindowBound::Time(t)) => Some(*t),
+            _ => None,
+        };
+        let (start, end) = (bound_time(&window.start), bound_time(&window.end));
+        if let Some(start) = start.filter(|start| time >= *start) {
+            if self.window_state == WindowState::Pending {
+                self.open_window(start.max(previous));
             }
         }
-        let end = Instant::now();
-        Log::write(
-            Priority::Info,
-            &format!("Duration: {} s", (end - start).as_millis() as f64 / 1000.0),
-        );
-        Ok(infos)
+        if let Some(end) = end.filter(|end| time >= *end) {
+            self.close_window(end);
+        }
     }
 
-    fn translate_definitions(
-        &mut self,
-        infos: Receiver<LineInfo>,
-        use_spinner: bool,
-    ) -> Result<Receiver<LineInfo>, String> {
-        Log::write(Priority::Info, "Reading signal declarations");
-        let sp = match use_spinner {
-            true => Some(Spinner::new(Spinners::Aesthetic, "".into())),
-            false => None,
+    fn update_window_on_change(&mut self, index: usize, time: i64) {
+        let Some(window) = &self.window else {
+            return;
         };
-        let start = Instant::now();
-        let mut translator = InfoTranslator { modules: vec![] };
-        for info in infos.iter() {
-            match info.value {
-                LineValue::Signal(s) => self.push(s, &translator),
-                LineValue::DateInfo(s) => Log::write(
-                    Priority::Info,
-                    &format!("Date: {}", s.trim().replace("$end", "").trim()),
-                ),
-                LineValue::VersionInfo(s) => Log::write(
-                    Priority::Info,
-                    &format!("Tool: {}", s.trim().replace("$end", "").trim()),
-                ),
-                LineValue::TimeScaleInfo(s) => Log::write(
-                    Priority::Info,
-                    &format!("Time scale: {}", s.trim().replace("$end", "").trim()),
-                ),
-                LineValue::InScope(module) => {
-                    translator.modules.push(module.into_boxed_str().into())
-                }
-                LineValue::UpScope => {
-                    translator.modules.pop().unwrap();
-                }
-                LineValue::ParsingError(s) => {
-                    return Err(format!("Line: {}: {}", info.line_number, s))
-                }
-                LineValue::EndDefinitions => {
-                    if let Some(mut s) = sp {
-                        s.stop();
-                    }
-                    Log::write(
-                        Priority::Info,
-                        &format!(
-                            "Signals read correctly. Number of signals: {}",
-                            self.signals.len()
-                        ),
-                    );
-                    break;
-                }
-                LineValue::Useless => {}
-                LineValue::Dumpports => unreachable!("Error: Dumpports not expected here"),
-                LineValue::Timestamp(t) => panic!("Unexpected timestamp: {:?}", t),
-                LineValue::Change(c) => panic!("Unexpected change: {:?}", c),
-                LineValue::EndInitializations => {
-                    panic!("End initializations found before the beginning!")
+        let value = self.signals[index].current_state.value;
+        match (self.window_state, &window.start, &window.end) {
+            (WindowState::Pending, Some(WindowBound::Signal { value: start, .. }), _)
+                if self.window_markers[0] == Some(index) && value == *start =>
+            {
+                self.open_window(time)
+            }
+            (WindowState::Open, _, Some(WindowBound::Signal { value: end, .. }))
+                if self.window_markers[1] == Some(index) && value == *end =>
+            {
+                self.close_window(time)
+            }
+            _ => {}
+        }
+    }
+
+    fn add_change(&mut self, signal_id: &str, values: &[u8], time: i64) -> Result<(), String> {
+        let first_index = *self
+            .signals_by_id
+            .get(signal_id)
+            .ok_or_else(|| format!("Unknown signal id {}", signal_id))?;
+        if let Some(&bus) = self.buses_by_id.get(signal_id) {
+            let value = BusValue::from_digits(values);
+            if self.window_state == WindowState::Open {
+                self.buses[bus].sample(value.clone());
+            }
+            self.buses[bus].current = Some(value);
+        }
+        if let Some(&fsm) = self.fsms_by_id.get(signal_id) {
+            self.fsms[fsm].add_change(BusValue::from_digits(values), time);
+        }
+        values.iter().enumerate().for_each(|(sub_id, state)| {
+            let state = State {
+                value: SignalValue::from(*state),
+                time,
+            };
+            let signal = &mut self.signals[first_index + sub_id];
+            let previous = signal.current_state.value;
+            signal.current_state = state;
+            if self.window_state == WindowState::Open {
+                signal.add_change(state);
+                signal.record_toggle(previous, state);
+                if let Some(activity) = signal.activity.as_mut() {
+                    activity.add_change(state);
                 }
             }
+            if let Some(xz) = signal.xz.as_mut() {
+                xz.add_change(state);
+            }
+            if let Some(edges) = signal.edges.as_mut() {
+                edges.add_change(state);
+            }
+            self.update_window_on_change(first_index + sub_id, time);
+            self.update_reset_on_change(first_index + sub_id);
+        });
+        Ok(())
+    }
+
+    /// Value of a signal in the `$dumpvars` section, the starting point of its coverage.
+    fn initialize_change(
+        &mut self,
+        signal_id: &str,
+        values: &[u8],
+        time: i64,
+    ) -> Result<(), String> {
+        let first_index = *self
+            .signals_by_id
+            .get(signal_id)
+            .ok_or_else(|| format!("Unknown signal id {}", signal_id))?;
+        if let Some(&bus) = self.buses_by_id.get(signal_id) {
+            let value = BusValue::from_digits(values);
+            self.buses[bus].sample(value.clone());
+            self.buses[bus].current = Some(value);
         }
-        let end = Instant::now();
-        Log::write(
-            Priority::Info,
-            &format!("Duration: {} s", (end - start).as_millis() as f64 / 1000.0),
-        );
-        Ok(infos)
+        if let Some(&fsm) = self.fsms_by_id.get(signal_id) {
+            let fsm = &mut self.fsms[fsm];
+            fsm.add_change(BusValue::from_digits(values), time);
+            fsm.commit(true);
+        }
+        for (sub_id, value) in values.iter().enumerate() {
+            let state = State {
+                value: SignalValue::from(*value),
+                time,
+            };
+            let signal = &mut self.signals[first_index + sub_id];
+            signal.initialize(state);
+            if let Some(xz) = signal.xz.as_mut() {
+                *xz = XZActivity::new(state);
+            }
+            if let Some(edges) = signal.edges.as_mut() {
+                *edges = EdgeStats::new(state);
+            }
+        }
+        Ok(())
     }
 
     pub fn to_result_string(&self) -> String {
-        let mut total_coverage: f64 = self
-            .signals
-            .iter()
-            .map(|signal| signal.calculate_coverage() as f64)
-            .sum();
-        total_coverage /= self.signals.len() as f64;
+        let total_coverage = self.total_coverage();
+        let window = match &self.window {
+            Some(window) => format!("# Window: {}\n", window),
+            None => String::new(),
+        };
+        let incomplete = match self.cancelled_at {
+            Some(time) => format!("# Incomplete: analysis cancelled at time {}\n", time),
+            None => String::new(),
+        };
         let explanation = format!(
-            "# VCD Statistical analysis. Total coverage: {:.2} % over {} signals\n{}\n",
+            "# VCD Statistical analysis. Total coverage: {:.2} % over {} signals\n{}{}{}{}{}\n",
             total_coverage * 100.0,
-            self.signals.len(),
-            Signal::result_explanation()
+            self.counted_signals().count(),
+            incomplete,
+            window,
+            self.classification_string(),
+            Signal::result_explanation(),
+            match self.statistics {
+                true => Activity::result_explanation(),
+                false => "",
+            }
         );
         let result_values: Vec<String> = self
             .signals
@@ -414,14 +695,110 @@ impl VCD {
             .collect();
         format!("{}{}", explanation, result_values.join("\n"))
     }
+
+    pub fn to_json_string(&self) -> String {
+        let total_coverage = self.total_coverage();
+        let window = match &self.window {
+            Some(window) => format!("\"{}\"", json_escape(&window.to_string())),
+            None => "null".into(),
+        };
+        let signals: Vec<String> = self
+            .signals
+            .iter()
+            .map(|signal| signal.to_json_string())
+            .collect();
+        format!(
+            "{{\"total_coverage\":{:.4},\"window\":{},\"cancelled_at\":{},\"clocks_resets_excluded\":{},\"signals\":[{}]}}",
+            total_coverage,
+            window,
+            self.cancelled_at
+                .map_or("null".into(), |time| time.to_string()),
+            self.exclude_clocks_resets,
+            signals.join(",")
+        )
+    }
+
+    pub fn to_format_string(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.to_result_string(),
+            ReportFormat::Json => self.to_json_string(),
+        }
+    }
+
+    pub fn save(&self, path: &str, format: ReportFormat) -> Result<(), String> {
+        let report = self.to_format_string(format);
+        write_report(path, |out| out.write_all(report.as_bytes()))
+    }
+
+    pub fn save_xz(&self, path: &str, format: ReportFormat) -> Result<(), String> {
+        let report = match format {
+            ReportFormat::Text => self.to_xz_string()?,
+            ReportFormat::Json => self.to_xz_json_string()?,
+        };
+        write_report(path, |out| out.write_all(report.as_bytes()))
+    }
+
+    pub fn save_value_coverage(&self, path: &str, format: ReportFormat) -> Result<(), String> {
+        let report = match format {
+            ReportFormat::Text => self.to_value_coverage_string(),
+            ReportFormat::Json => self.to_value_coverage_json_string(),
+        };
+        write_report(path, |out| out.write_all(report.as_bytes()))
+    }
+
+    pub fn save_fsm(&self, path: &str, format: ReportFormat) -> Result<(), String> {
+        let report = match format {
+            ReportFormat::Text => self.to_fsm_string(),
+            ReportFormat::Json => self.to_fsm_json_string(),
+        };
+        write_report(path, |out| out.write_all(report.as_bytes()))
+    }
+
+    pub fn save_properties(&self, path: &str, format: ReportFormat) -> Result<(), String> {
+        let report = match format {
+            ReportFormat::Text => self.to_property_string(),
+            ReportFormat::Json => self.to_property_json_string(),
+        };
+        write_report(path, |out| out.write_all(report.as_bytes()))
+    }
+
+    /// Transactions of the decoders; the text format is CSV.
+    pub fn save_transactions(&self, path: &str, format: ReportFormat) -> Result<(), String> {
+        let report = match format {
+            ReportFormat::Text => self.to_transactions_csv_string(),
+            ReportFormat::Json => self.to_transactions_json_string(),
+        };
+        write_report(path, |out| out.write_all(report.as_bytes()))
+    }
+
+    pub fn save_saif(&self, path: &str) -> Result<(), String> {
+        let report = self.to_saif_string()?;
+        write_report(path, |out| out.write_all(report.as_bytes()))
+    }
 }
 
-fn translate_infos(mut infos: Receiver<LineInfo>, use_spinner: bool) -> Result<VCD, String> {
-    let mut vcd = VCD::default();
-    infos = vcd.translate_definitions(infos, use_spinner)?;
-    infos = vcd.translate_initializations(infos, use_spinner)?;
-    vcd.translate_changes(infos, use_spinner)?;
-    Ok(vcd)
+/// Creates the file at `path` and writes a report to it with `write`.
+fn write_report(
+    path: &str,
+    write: impl FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+) -> Result<(), String> {
+    let mut writer = BufWriter::new(File::create(path).map_err(|err| err.to_string())?);
+    write(&mut writer)
+        .and_then(|_| writer.flush())
+        .map_err(|err| err.to_string())
+}
+
+fn json_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
 }
 
 unsafe impl Send for Signal {}
@@ -0,0 +1,35 @@
+use std::fmt::Display;
+
+/// Steps of an analysis, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Definitions,
+    Initializations,
+    Changes,
+    Finished,
+}
+
+/// Receives the progress of an analysis. The calls come from the analysis threads, so
+/// implementations forward them to wherever they are shown.
+pub trait ProgressSink: Send + Sync {
+    fn phase(&self, phase: Phase);
+    /// `read` of the `total` bytes of the input were read. Calls are spaced out to about
+    /// a thousand per file.
+    fn bytes(&self, read: u64, total: u64);
+}
+
+/// Smallest advance in bytes between two calls of `ProgressSink::bytes`.
+pub(crate) fn progress_step(total: u64) -> u64 {
+    (total / 1000).max(1 << 16)
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Phase::Definitions => write!(f, "Reading signal declarations"),
+            Phase::Initializations => write!(f, "Reading signal initializations"),
+            Phase::Changes => write!(f, "Reading signal changes"),
+            Phase::Finished => write!(f, "Analysis finished"),
+        }
+    }
+}
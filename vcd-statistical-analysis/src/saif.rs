@@ -0,0 +1,126 @@
+use std::rc::Rc;
+
+use crate::{Activity, VCD};
+
+/// Scope of the SAIF hierarchy, holding the nets declared directly inside it.
+#[derive(Default)]
+struct Instance<'vcd> {
+    name: &'vcd str,
+    nets: Vec<(String, &'vcd Activity)>,
+    children: Vec<Instance<'vcd>>,
+}
+
+impl<'vcd> Instance<'vcd> {
+    fn insert(&mut self, modules: &'vcd [Rc<str>], net: String, activity: &'vcd Activity) {
+        let Some((module, rest)) = modules.split_first() else {
+            self.nets.push((net, activity));
+            return;
+        };
+        let index = match self
+            .children
+            .iter()
+            .position(|child| child.name == &**module)
+        {
+            Some(index) => index,
+            None => {
+                self.children.push(Instance {
+                    name: module,
+                    ..Default::default()
+                });
+                self.children.len() - 1
+            }
+        };
+        self.children[index].insert(rest, net, activity);
+    }
+
+    fn write(&self, lines: &mut Vec<String>, depth: usize) {
+        let indent = "  ".repeat(depth);
+        lines.push(format!("{}(INSTANCE {}", indent, escape(self.name)));
+        if !self.nets.is_empty() {
+            lines.push(format!("{}  (NET", indent));
+            for (net, activity) in self.nets.iter() {
+                lines.push(format!("{}    ({}", indent, net));
+                lines.push(format!(
+                    "{}      (T0 {}) (T1 {}) (TX {}) (TZ {})",
+                    indent, activity.low_time, activity.high_time, activity.x_time, activity.z_time
+                ));
+                lines.push(format!(
+                    "{}      (TC {}) (IG {})",
+                    indent, activity.toggles, activity.glitches
+                ));
+                lines.push(format!("{}    )", indent));
+            }
+            lines.push(format!("{}  )", indent));
+        }
+        self.children
+            .iter()
+            .for_each(|child| child.write(lines, depth + 1));
+        lines.push(format!("{})", indent));
+    }
+}
+
+/// SAIF identifiers escape every character that is not alphanumeric or `_`.
+fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if !c.is_ascii_alphanumeric() && c != '_' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Splits a VCD time scale such as `1ns` or `10 ps` into SAIF's `1 ns` form.
+fn saif_timescale(timescale: &str) -> Option<String> {
+    let timescale: String = timescale.split_ascii_whitespace().collect();
+    let unit_start = timescale.find(|c: char| !c.is_ascii_digit())?;
+    let (value, unit) = timescale.split_at(unit_start);
+    match (value.is_empty(), unit) {
+        (false, "s" | "ms" | "us" | "ns" | "ps" | "fs") => Some(format!("{} {}", value, unit)),
+        _ => None,
+    }
+}
+
+impl VCD {
+    /// Backward SAIF with the switching activity of every bit. IG counts the zero-width
+    /// glitches. Requires the statistics to be computed.
+    pub fn to_saif_string(&self) -> Result<String, String> {
+        let mut root = Instance::default();
+        for signal in self.signals.iter() {
+            let Some(activity) = &signal.activity else {
+                return Err("SAIF export needs the statistics to be computed".into());
+            };
+            let (modules, net) = match signal.name.split_last() {
+                Some((index, rest)) if index.starts_with('[') && !rest.is_empty() => {
+                    let (signal_name, modules) = rest.split_last().unwrap();
+                    (modules, escape(signal_name) + &escape(index))
+                }
+                Some((signal_name, modules)) => (modules, escape(signal_name)),
+                None => continue,
+            };
+            root.insert(modules, net, activity);
+        }
+
+        let mut lines = vec![
+            "(SAIFILE".to_string(),
+            "(SAIFVERSION \"2.0\")".into(),
+            "(DIRECTION \"backward\")".into(),
+            "(DESIGN )".into(),
+            "(VENDOR \"oxyvcd\")".into(),
+            "(PROGRAM_NAME \"vcd-statistical-analysis\")".into(),
+            format!("(VERSION \"{}\")", env!("CARGO_PKG_VERSION")),
+            "(DIVIDER / )".into(),
+        ];
+        if let Some(timescale) = self.timescale.as_deref().and_then(saif_timescale) {
+            lines.push(format!("(TIMESCALE {})", timescale));
+        }
+        lines.push(format!("(DURATION {})", self.duration));
+        // Nets outside of any scope cannot be represented, SAIF nets live in instances
+        root.children
+            .iter()
+            .for_each(|instance| instance.write(&mut lines, 0));
+        lines.push(")".into());
+        Ok(lines.join("\n"))
+    }
+}
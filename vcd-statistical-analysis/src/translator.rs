@@ -0,0 +1,254 @@
+use std::{rc::Rc, time::Instant};
+
+use logger::{Log, Priority};
+use vcd_reader::VcdVisitor;
+
+use crate::{progress::progress_step, window::WindowState, Configuration, Phase, WindowBound, VCD};
+
+/// Builds the analyses from the lines of the dump as the reader goes through them. The
+/// declarations and initializations fill a single analysis, cloned once per window when
+/// the changes start.
+pub(crate) struct Translator<'c> {
+    c: &'c Configuration,
+    phase: Phase,
+    phase_start: Instant,
+    modules: Vec<Rc<str>>,
+    vcds: Vec<VCD>,
+    current_timestamp: i64,
+    bytes: (u64, u64),
+    reported: u64,
+    stopped: bool,
+}
+
+impl<'c> Translator<'c> {
+    pub(crate) fn new(c: &'c Configuration) -> Self {
+        let vcd = VCD {
+            statistics: c.statistics,
+            xz_analysis: c.xz_analysis,
+            value_coverage: c.value_coverage,
+            fsm_descriptions: c.fsms.clone(),
+            detect_fsms: c.detect_fsms,
+            detect_clocks_resets: c.detect_clocks_resets || c.exclude_clocks_resets,
+            exclude_clocks_resets: c.exclude_clocks_resets,
+            ..Default::default()
+        };
+        let mut translator = Translator {
+            c,
+            phase: Phase::Definitions,
+            phase_start: Instant::now(),
+            modules: vec![],
+            vcds: vec![vcd],
+            current_timestamp: 0,
+            bytes: (0, 0),
+            reported: 0,
+            stopped: false,
+        };
+        translator.enter(Phase::Definitions);
+        translator
+    }
+
+    fn enter(&mut self, phase: Phase) {
+        if self.phase != phase {
+            Log::write(
+                Priority::Info,
+                &format!(
+                    "Duration: {} s",
+                    self.phase_start.elapsed().as_millis() as f64 / 1000.0
+                ),
+            );
+        }
+        self.phase = phase;
+        self.phase_start = Instant::now();
+        if phase != Phase::Finished {
+            Log::write(Priority::Info, &phase.to_string());
+        }
+        if let Some(progress) = &self.c.progress {
+            progress.phase(phase);
+        }
+    }
+
+    /// The analysis being built before the windows are split.
+    fn vcd(&mut self) -> &mut VCD {
+        &mut self.vcds[0]
+    }
+
+    /// Closes the analyses at the last timestamp read, marking the windows still open or
+    /// pending as incomplete if the analysis was cancelled.
+    pub(crate) fn finish(mut self) -> Result<Vec<VCD>, String> {
+        if let Some(progress) = &self.c.progress {
+            progress.bytes(self.bytes.0, self.bytes.1);
+        }
+        let cancelled = self.c.cancel.is_cancelled();
+        if cancelled && self.phase != Phase::Changes {
+            return Err("Analysis cancelled before the changes were read".into());
+        }
+        if self.phase == Phase::Definitions {
+            self.on_end_definitions()?;
+        }
+        if self.phase == Phase::Initializations {
+            self.on_end_initializations()?;
+        }
+        let time = self.current_timestamp;
+        if cancelled {
+            Log::write(
+                Priority::Warn,
+                &format!("Analysis cancelled at time {}", time),
+            );
+        }
+        for vcd in self.vcds.iter_mut() {
+            // Windows closed before the cancellation are complete
+            let closed = vcd.window_state == WindowState::Closed;
+            vcd.finish(time);
+            vcd.cancelled_at = (cancelled && !closed).then_some(time);
+        }
+        if !cancelled {
+            Log::write(Priority::Info, "Changes read correctly");
+        }
+        self.enter(Phase::Finished);
+        Ok(self.vcds)
+    }
+}
+
+impl VcdVisitor for Translator<'_> {
+    fn on_date(&mut self, date: &str) -> Result<(), String> {
+        Log::write(Priority::Info, &format!("Date: {}", date));
+        Ok(())
+    }
+
+    fn on_version(&mut self, version: &str) -> Result<(), String> {
+        Log::write(Priority::Info, &format!("Tool: {}", version));
+        Ok(())
+    }
+
+    fn on_timescale(&mut self, timescale: &str) -> Result<(), String> {
+        Log::write(Priority::Info, &format!("Time scale: {}", timescale));
+        self.vcd().timescale = Some(timescale.into());
+        Ok(())
+    }
+
+    fn on_scope(&mut self, name: &str) -> Result<(), String> {
+        self.modules.push(name.into());
+        Ok(())
+    }
+
+    fn on_upscope(&mut self) -> Result<(), String> {
+        self.modules
+            .pop()
+            .map(|_| ())
+            .ok_or_else(|| "$upscope without a matching $scope".into())
+    }
+
+    fn on_signal(&mut self, signal: vcd_reader::Signal) -> Result<(), String> {
+        let modules = std::mem::take(&mut self.modules);
+        self.vcd().push(signal, &modules);
+        self.modules = modules;
+        Ok(())
+    }
+
+    fn on_end_definitions(&mut self) -> Result<(), String> {
+        let c = self.c;
+        let vcd = self.vcd();
+        Log::write(
+            Priority::Info,
+            &format!(
+                "Signals read correctly. Number of signals: {}",
+                vcd.signals.len()
+            ),
+        );
+        vcd.index_paths();
+        vcd.set_value_bins(&c.value_bins)?;
+        vcd.check_fsms()?;
+        vcd.set_properties(&c.properties)?;
+        vcd.set_decoders(&c.decoders, &c.protocols)?;
+        self.current_timestamp = 0;
+        self.enter(Phase::Initializations);
+        Ok(())
+    }
+
+    fn on_end_initializations(&mut self) -> Result<(), String> {
+        Log::write(Priority::Info, "Signals initialized correctly");
+        let c = self.c;
+        let mut vcd = self.vcds.pop().unwrap();
+        vcd.check_properties(0);
+        vcd.decode(0);
+        match &c.reset_end {
+            Some(WindowBound::Signal { path, .. }) => {
+                vcd.reset_marker = Some(
+                    vcd.find_signal(path)
+                        .ok_or_else(|| format!("Reset signal {} not found", path))?,
+                )
+            }
+            Some(WindowBound::Time(_)) => {}
+            None => vcd.end_reset(),
+        }
+        vcd.reset_end = c.reset_end.clone();
+        self.vcds = match c.windows.is_empty() {
+            true => vec![vcd],
+            false => c
+                .windows
+                .iter()
+                .map(|window| {
+                    let mut windowed = vcd.clone();
+                    windowed.set_window(window.clone())?;
+                    Ok(windowed)
+                })
+                .collect::<Result<Vec<_>, String>>()?,
+        };
+        self.current_timestamp = -1;
+        self.enter(Phase::Changes);
+        Ok(())
+    }
+
+    fn on_timestamp(&mut self, time: usize) -> Result<(), String> {
+        if self.phase != Phase::Changes {
+            self.current_timestamp = time as i64;
+            return Ok(());
+        }
+        if self.c.cancel.is_cancelled() {
+            // Stop at a timestamp, so that every analysis saw whole timestamps
+            self.current_timestamp = self.current_timestamp.max(0);
+            self.stopped = true;
+            return Ok(());
+        }
+        let previous_timestamp = self.current_timestamp;
+        self.current_timestamp = time as i64;
+        for vcd in self.vcds.iter_mut() {
+            vcd.check_properties(previous_timestamp);
+            vcd.decode(previous_timestamp);
+            vcd.commit_fsms();
+            vcd.update_window_on_timestamp(previous_timestamp, self.current_timestamp);
+            vcd.update_reset_on_timestamp(self.current_timestamp);
+        }
+        Ok(())
+    }
+
+    fn on_change(&mut self, signal_id: &str, values: &[u8]) -> Result<(), String> {
+        match self.phase {
+            Phase::Changes => {
+                for vcd in self.vcds.iter_mut() {
+                    vcd.add_change(signal_id, values, self.current_timestamp)?;
+                }
+                Ok(())
+            }
+            Phase::Initializations => {
+                let time = self.current_timestamp;
+                self.vcd().initialize_change(signal_id, values, time)
+            }
+            _ => Err(format!("Value change of {} in the declarations", signal_id)),
+        }
+    }
+
+    fn on_progress(&mut self, read: u64, total: u64) -> bool {
+        self.bytes = (read, total);
+        if let Some(progress) = &self.c.progress {
+            if read - self.reported >= progress_step(total) {
+                progress.bytes(read, total);
+                self.reported = read;
+            }
+        }
+        match self.phase {
+            Phase::Changes => !self.stopped,
+            _ => !self.c.cancel.is_cancelled(),
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::{collections::HashMap, fmt::Display, fs};
+
+use crate::{json_escape, VCD};
+
+/// Buses taking more distinct values than this only count the excess values.
+pub const MAX_DISTINCT_VALUES: usize = 4096;
+
+/// Value of a whole bus. Buses wider than 128 bits or containing X/Z keep their digits.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BusValue {
+    Known(u128),
+    Other(String),
+}
+
+/// A named range of values of a bus, both ends included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueBin {
+    pub bus: String,
+    pub name: String,
+    pub low: u128,
+    pub high: u128,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinHits {
+    pub name: String,
+    pub low: u128,
+    pub high: u128,
+    pub hits: u64,
+}
+
+/// Values taken by a multi-bit signal.
+#[derive(Debug, Clone)]
+pub struct Bus {
+    pub name: String,
+    pub width: usize,
+    pub histogram: HashMap<BusValue, u64>,
+    /// Samples of values beyond the first `MAX_DISTINCT_VALUES`
+    pub overflow: u64,
+    pub bins: Vec<BinHits>,
+    pub(crate) current: Option<BusValue>,
+}
+
+impl BusValue {
+    pub fn from_digits(digits: &[u8]) -> Self {
+        if digits.len() <= 128 && digits.iter().all(|d| matches!(d, b'0' | b'1')) {
+            BusValue::Known(
+                digits
+                    .iter()
+                    .fold(0, |value, digit| (value << 1) | (digit - b'0') as u128),
+            )
+        } else {
+            BusValue::Other(String::from_utf8_lossy(digits).to_lowercase())
+        }
+    }
+}
+
+impl Display for BusValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BusValue::Known(value) => write!(f, "0x{:x}", value),
+            BusValue::Other(digits) => write!(f, "b{}", digits),
+        }
+    }
+}
+
+pub(crate) fn parse_value(value: &str) -> Result<u128, String> {
+    let parsed = if let Some(hex) = value.strip_prefix("0x") {
+        u128::from_str_radix(hex, 16)
+    } else if let Some(binary) = value.strip_prefix("0b") {
+        u128::from_str_radix(binary, 2)
+    } else {
+        value.parse()
+    };
+    parsed.map_err(|_| format!("Invalid value {}", value))
+}
+
+impl ValueBin {
+    pub fn from_file(path: &str) -> Result<Vec<Self>, String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        Self::from_config_string(&content).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    /// Parses lines like `bin top/cpu/opcode alu 0x10..0x1f` or `bin top/cpu/opcode nop 0`.
+    /// Values are decimal, `0x` hexadecimal or `0b` binary; `#` starts a comment line.
+    pub fn from_config_string(content: &str) -> Result<Vec<Self>, String> {
+        let mut bins = vec![];
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words: Vec<&str> = line.split_ascii_whitespace().collect();
+            let ["bin", bus, name, range] = words.as_slice() else {
+                return Err(format!("Line {}: unrecognized bin {}", index + 1, line));
+            };
+            let (low, high) = match range.split_once("..") {
+                Some((low, high)) => (parse_value(low), parse_value(high)),
+                None => (parse_value(range), parse_value(range)),
+            };
+            let (low, high) = (
+                low.map_err(|err| format!("Line {}: {}", index + 1, err))?,
+                high.map_err(|err| format!("Line {}: {}", index + 1, err))?,
+            );
+            if low > high {
+                return Err(format!("Line {}: empty range {}", index + 1, range));
+            }
+            bins.push(ValueBin {
+                bus: bus.to_string(),
+                name: name.to_string(),
+                low,
+                high,
+            });
+        }
+        Ok(bins)
+    }
+}
+
+impl Bus {
+    pub(crate) fn new(name: String, width: usize) -> Self {
+        Bus {
+            name,
+            width,
+            histogram: HashMap::new(),
+            overflow: 0,
+            bins: vec![],
+            current: None,
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.histogram.clear();
+        self.overflow = 0;
+        self.bins.iter_mut().for_each(|bin| bin.hits = 0);
+    }
+
+    pub(crate) fn sample(&mut self, value: BusValue) {
+        if let BusValue::Known(known) = value {
+            self.bins
+                .iter_mut()
+                .filter(|bin| (bin.low..=bin.high).contains(&known))
+                .for_each(|bin| bin.hits += 1);
+        }
+        let full = self.histogram.len() >= MAX_DISTINCT_VALUES;
+        match self.histogram.get_mut(&value) {
+            Some(count) => *count += 1,
+            None if full => self.overflow += 1,
+            None => {
+                self.histogram.insert(value, 1);
+            }
+        }
+    }
+
+    pub fn distinct_values(&self) -> usize {
+        self.histogram.len()
+    }
+
+    /// Fraction of the bins that were hit, `None` without bins.
+    pub fn bin_coverage(&self) -> Option<f64> {
+        match self.bins.len() {
+            0 => None,
+            bins => Some(self.bins.iter().filter(|bin| bin.hits > 0).count() as f64 / bins as f64),
+        }
+    }
+
+    /// Histogram sorted by value, numeric values first.
+    pub fn sorted_histogram(&self) -> Vec<(&BusValue, u64)> {
+        let mut histogram: Vec<_> = self
+            .histogram
+            .iter()
+            .map(|(value, count)| (value, *count))
+            .collect();
+        histogram.sort_by(|(a, _), (b, _)| match (a, b) {
+            (BusValue::Known(a), BusValue::Known(b)) => a.cmp(b),
+            (BusValue::Known(_), BusValue::Other(_)) => std::cmp::Ordering::Less,
+            (BusValue::Other(_), BusValue::Known(_)) => std::cmp::Ordering::Greater,
+            (BusValue::Other(a), BusValue::Other(b)) => a.cmp(b),
+        });
+        histogram
+    }
+
+    fn to_result_string(&self) -> String {
+        let bins = match self.bin_coverage() {
+            Some(coverage) => format!(
+                ", bins {}/{} ({:.2} %)",
+                self.bins.iter().filter(|bin| bin.hits > 0).count(),
+                self.bins.len(),
+                coverage * 100.0
+            ),
+            None => String::new(),
+        };
+        let overflow = match self.overflow {
+            0 => String::new(),
+            overflow => format!(
+                ", {} samples beyond the first {}",
+                overflow, MAX_DISTINCT_VALUES
+            ),
+        };
+        let mut lines = vec![format!(
+            "{} width {}, {} distinct values{}{}",
+            self.name,
+            self.width,
+            self.distinct_values(),
+            bins,
+            overflow
+        )];
+        lines.extend(
+            self.sorted_histogram()
+                .into_iter()
+                .map(|(value, count)| format!("  value {} {}", value, count)),
+        );
+        lines.extend(self.bins.iter().map(|bin| {
+            format!(
+                "  bin {} 0x{:x}..0x{:x} {}",
+                bin.name, bin.low, bin.high, bin.hits
+            )
+        }));
+        lines.join("\n")
+    }
+
+    fn to_json_string(&self) -> String {
+        let histogram: Vec<String> = self
+            .sorted_histogram()
+            .into_iter()
+            .map(|(value, count)| format!("{{\"value\":\"{}\",\"count\":{}}}", value, count))
+            .collect();
+        let bins: Vec<String> = self
+            .bins
+            .iter()
+            .map(|bin| {
+                format!(
+                    "{{\"name\":\"{}\",\"low\":\"0x{:x}\",\"high\":\"0x{:x}\",\"hits\":{}}}",
+                    json_escape(&bin.name),
+                    bin.low,
+                    bin.high,
+                    bin.hits
+                )
+            })
+            .collect();
+        format!(
+            "{{\"name\":\"{}\",\"width\":{},\"distinct_values\":{},\"overflow\":{},\"bin_coverage\":{},\"histogram\":[{}],\"bins\":[{}]}}",
+            json_escape(&self.name),
+            self.width,
+            self.distinct_values(),
+            self.overflow,
+            self.bin_coverage()
+                .map_or("null".into(), |coverage| format!("{:.4}", coverage)),
+            histogram.join(","),
+            bins.join(",")
+        )
+    }
+}
+
+impl VCD {
+    /// Attaches the bins to their buses. Must be called after the definitions.
+    pub(crate) fn set_value_bins(&mut self, bins: &[ValueBin]) -> Result<(), String> {
+        for bin in bins.iter() {
+            let bus = self
+                .buses
+                .iter_mut()
+                .find(|bus| bus.name == bin.bus)
+                .ok_or_else(|| format!("Bus {} of bin {} not found", bin.bus, bin.name))?;
+            bus.bins.push(BinHits {
+                name: bin.name.clone(),
+                low: bin.low,
+                high: bin.high,
+                hits: 0,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn to_value_coverage_string(&self) -> String {
+        let mut lines = vec![format!(
+            "# Bus value coverage over {} buses",
+            self.buses.len()
+        )];
+        lines
+            .push("# Bus name, width, distinct values, bin coverage; then value/bin, count".into());
+        lines.extend(self.buses.iter().map(|bus| bus.to_result_string()));
+        lines.join("\n")
+    }
+
+    pub fn to_value_coverage_json_string(&self) -> String {
+        let buses: Vec<String> = self.buses.iter().map(|bus| bus.to_json_string()).collect();
+        format!("{{\"buses\":[{}]}}", buses.join(","))
+    }
+}
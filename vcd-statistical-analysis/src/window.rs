@@ -0,0 +1,92 @@
+use std::{fmt::Display, str::FromStr};
+
+use vcd_reader::SignalValue;
+
+/// One end of a time window.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowBound {
+    Time(i64),
+    /// The first change of the bit at `path` (e.g. `top/rst_n`) to `value`.
+    Signal {
+        path: String,
+        value: SignalValue,
+    },
+}
+
+/// Only transitions between `start` and `end` are counted. Missing bounds extend the
+/// window to the beginning or to the end of the dump.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TimeWindow {
+    pub start: Option<WindowBound>,
+    pub end: Option<WindowBound>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WindowState {
+    Pending,
+    #[default]
+    Open,
+    Closed,
+}
+
+impl FromStr for WindowBound {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((path, value)) if value.len() == 1 && !path.is_empty() => {
+                Ok(WindowBound::Signal {
+                    path: path.into(),
+                    value: SignalValue::from(value.as_bytes()[0]),
+                })
+            }
+            Some(_) => Err(format!("Invalid signal condition {}", s)),
+            None => s
+                .parse()
+                .map(WindowBound::Time)
+                .map_err(|_| format!("Invalid time {}", s)),
+        }
+    }
+}
+
+/// Parses `<start>..<end>`, where each bound is either a time or a `<path>=<value>`
+/// condition and can be left empty, e.g. `top/rst_n=1..` or `100..2000`.
+impl FromStr for TimeWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once("..")
+            .ok_or_else(|| format!("Invalid window {}: expected <start>..<end>", s))?;
+        let parse_bound = |bound: &str| match bound.trim() {
+            "" => Ok(None),
+            bound => bound.parse().map(Some),
+        };
+        Ok(TimeWindow {
+            start: parse_bound(start)?,
+            end: parse_bound(end)?,
+        })
+    }
+}
+
+impl Display for WindowBound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WindowBound::Time(t) => write!(f, "{}", t),
+            WindowBound::Signal { path, value } => write!(f, "{}={}", path, char::from(*value)),
+        }
+    }
+}
+
+impl Display for TimeWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(start) = &self.start {
+            write!(f, "{}", start)?;
+        }
+        write!(f, "..")?;
+        if let Some(end) = &self.end {
+            write!(f, "{}", end)?;
+        }
+        Ok(())
+    }
+}
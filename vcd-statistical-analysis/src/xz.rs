@@ -0,0 +1,197 @@
+use vcd_reader::SignalValue;
+
+use crate::{json_escape, State, WindowBound, VCD};
+
+/// Unknown and high-impedance values of a single bit over the whole dump.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XZActivity {
+    /// Value once the reset is over, `None` while it is still in progress
+    pub value_at_reset_end: Option<SignalValue>,
+    pub x_time: i64,
+    pub z_time: i64,
+    /// First X or Z value after the bit had a known value
+    pub first_unknown: Option<State>,
+    value: SignalValue,
+    since: i64,
+    known: bool,
+}
+
+fn is_unknown(value: SignalValue) -> bool {
+    matches!(value, SignalValue::X | SignalValue::Z)
+}
+
+impl XZActivity {
+    pub(crate) fn new(state: State) -> Self {
+        XZActivity {
+            value_at_reset_end: None,
+            x_time: 0,
+            z_time: 0,
+            first_unknown: None,
+            value: state.value,
+            since: state.time.max(0),
+            known: !is_unknown(state.value),
+        }
+    }
+
+    fn accumulate(&mut self, time: i64) {
+        match self.value {
+            SignalValue::X => self.x_time += time - self.since,
+            SignalValue::Z => self.z_time += time - self.since,
+            _ => {}
+        }
+        self.since = time;
+    }
+
+    pub(crate) fn add_change(&mut self, state: State) {
+        if state.value == self.value {
+            return;
+        }
+        self.accumulate(state.time);
+        self.value = state.value;
+        if !is_unknown(state.value) {
+            self.known = true;
+        } else if self.known && self.first_unknown.is_none() {
+            self.first_unknown = Some(state);
+        }
+    }
+
+    pub(crate) fn end_reset(&mut self) {
+        self.value_at_reset_end = Some(self.value);
+    }
+
+    pub(crate) fn finish(&mut self, time: i64) {
+        if time > self.since {
+            self.accumulate(time);
+        }
+    }
+
+    pub fn unknown_at_reset_end(&self) -> bool {
+        self.value_at_reset_end.is_some_and(is_unknown)
+    }
+}
+
+impl VCD {
+    /// Takes the values of the bits at the end of the reset.
+    pub(crate) fn end_reset(&mut self) {
+        self.reset_done = true;
+        self.signals
+            .iter_mut()
+            .filter_map(|signal| signal.xz.as_mut())
+            .for_each(|xz| xz.end_reset());
+    }
+
+    pub(crate) fn update_reset_on_timestamp(&mut self, time: i64) {
+        if let (false, Some(WindowBound::Time(end))) = (self.reset_done, &self.reset_end) {
+            if time >= *end {
+                self.end_reset();
+            }
+        }
+    }
+
+    pub(crate) fn update_reset_on_change(&mut self, index: usize) {
+        if let (false, Some(WindowBound::Signal { value, .. })) = (self.reset_done, &self.reset_end)
+        {
+            if self.reset_marker == Some(index) && self.signals[index].current_state.value == *value
+            {
+                self.end_reset();
+            }
+        }
+    }
+
+    fn reset_description(&self) -> String {
+        match &self.reset_end {
+            Some(bound) => bound.to_string(),
+            None => "end of initializations".into(),
+        }
+    }
+
+    /// Report of the bits unknown at the end of the reset, of the bits that became unknown
+    /// after being known and of the time spent in X and Z.
+    pub fn to_xz_string(&self) -> Result<String, String> {
+        let xz = self.xz_signals()?;
+        let mut lines = vec![format!(
+            "# X/Z analysis. End of reset: {}",
+            self.reset_description()
+        )];
+        let at_reset: Vec<_> = xz
+            .iter()
+            .filter(|(_, xz)| xz.unknown_at_reset_end())
+            .collect();
+        lines.push(format!(
+            "# Bits unknown at the end of reset: {}",
+            at_reset.len()
+        ));
+        lines.extend(
+            at_reset.iter().map(|(name, xz)| {
+                format!("{} {}", name, char::from(xz.value_at_reset_end.unwrap()))
+            }),
+        );
+        let went_unknown: Vec<_> = xz
+            .iter()
+            .filter_map(|(name, xz)| xz.first_unknown.map(|state| (name, state)))
+            .collect();
+        lines.push(format!(
+            "# Bits unknown after being known (name, value, time): {}",
+            went_unknown.len()
+        ));
+        lines.extend(
+            went_unknown.iter().map(|(name, state)| {
+                format!("{} {} {}", name, char::from(state.value), state.time)
+            }),
+        );
+        let unknown_time: Vec<_> = xz
+            .iter()
+            .filter(|(_, xz)| xz.x_time + xz.z_time > 0)
+            .collect();
+        lines.push(format!(
+            "# Time spent in X/Z (name, X time, Z time): {}",
+            unknown_time.len()
+        ));
+        lines.extend(
+            unknown_time
+                .iter()
+                .map(|(name, xz)| format!("{} {} {}", name, xz.x_time, xz.z_time)),
+        );
+        Ok(lines.join("\n"))
+    }
+
+    pub fn to_xz_json_string(&self) -> Result<String, String> {
+        let xz = self.xz_signals()?;
+        let optional = |value: Option<String>| value.unwrap_or("null".into());
+        let signals: Vec<String> = xz
+            .iter()
+            .filter(|(_, xz)| {
+                xz.unknown_at_reset_end() || xz.first_unknown.is_some() || xz.x_time + xz.z_time > 0
+            })
+            .map(|(name, xz)| {
+                format!(
+                    "{{\"name\":\"{}\",\"value_at_reset_end\":{},\"x_time\":{},\"z_time\":{},\"first_unknown\":{}}}",
+                    json_escape(name),
+                    optional(xz.value_at_reset_end.map(|value| format!("\"{}\"", char::from(value)))),
+                    xz.x_time,
+                    xz.z_time,
+                    optional(xz.first_unknown.map(|state| format!(
+                        "{{\"value\":\"{}\",\"time\":{}}}",
+                        char::from(state.value),
+                        state.time
+                    )))
+                )
+            })
+            .collect();
+        Ok(format!(
+            "{{\"reset_end\":\"{}\",\"signals\":[{}]}}",
+            json_escape(&self.reset_description()),
+            signals.join(",")
+        ))
+    }
+
+    fn xz_signals(&self) -> Result<Vec<(String, &XZActivity)>, String> {
+        self.signals
+            .iter()
+            .map(|signal| match &signal.xz {
+                Some(xz) => Ok((signal.name.join("/"), xz)),
+                None => Err("X/Z report needs the X/Z analysis to be enabled".to_string()),
+            })
+            .collect()
+    }
+}
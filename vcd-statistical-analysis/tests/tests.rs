@@ -0,0 +1,824 @@
+use std::{fs, path::PathBuf};
+
+use vcd_statistical_analysis::*;
+
+/// Dump written for a test, removed when dropped. Named after the process, so that test
+/// runs of several checkouts do not share it.
+struct TempDump(PathBuf);
+
+impl TempDump {
+    fn new(name: &str, content: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("oxyvcd_{}_{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        TempDump(path)
+    }
+
+    fn path(&self) -> String {
+        self.0.to_str().unwrap().into()
+    }
+}
+
+impl Drop for TempDump {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn analyze(file: &str) -> VCD {
+    perform_analysis(Configuration {
+        in_file: format!("tests/files/{}", file),
+        out_file: String::new(),
+        separator: ' ',
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+#[test]
+fn test_report_roundtrip() {
+    let vcd = analyze("counter.vcd");
+    let report = CoverageReport::from_result_string(&vcd.to_result_string()).unwrap();
+    assert_eq!(report.bits, CoverageReport::from(&vcd).bits);
+    assert_eq!(report.bits.len(), 5);
+    assert_eq!(report.bits[2].module(), "top/cnt");
+    assert_eq!(report.bits[0].module(), "top");
+}
+
+#[test]
+fn test_json_baseline() {
+    let vcd = analyze("counter.vcd");
+    let report = CoverageReport::from_json_string(&vcd.to_json_string()).unwrap();
+    assert_eq!(report.bits, CoverageReport::from(&vcd).bits);
+    let baseline = TempDump::new("baseline.json", &vcd.to_json_string());
+    let baseline = CoverageReport::from_file(&baseline.path()).unwrap();
+    assert!(CoverageDiff::new(&baseline, &report)
+        .newly_uncovered
+        .is_empty());
+    assert!(CoverageReport::from_json_string("{\"signals\":[{\"name\":1}]}").is_err());
+    assert!(CoverageReport::from_json_string("{\"signals\":[").is_err());
+}
+
+#[test]
+fn test_diff_against_itself_is_empty() {
+    let report = CoverageReport::from(&analyze("counter.vcd"));
+    let diff = CoverageDiff::new(&report, &report);
+    assert!(diff.newly_covered.is_empty());
+    assert!(diff.newly_uncovered.is_empty());
+    assert!(diff.initial_value_changed.is_empty());
+    assert!(diff.modules.is_empty());
+}
+
+#[test]
+fn test_diff_detects_lost_coverage() {
+    let current = CoverageReport::from(&analyze("counter.vcd"));
+    let mut baseline = current.clone();
+    baseline.bits.iter_mut().for_each(|bit| {
+        bit.transitioned_up = true;
+        bit.transitioned_down = true;
+        bit.coverage = 1.0;
+    });
+    baseline.bits[0].initial_value = '1';
+    let diff = CoverageDiff::new(&baseline, &current);
+    let lost = current.bits.iter().filter(|bit| bit.coverage < 1.0).count();
+    assert_eq!(diff.newly_uncovered.len(), lost);
+    assert!(diff.newly_covered.is_empty());
+    assert_eq!(diff.initial_value_changed.len(), 1);
+    assert!(diff.modules.iter().any(|module| module.module == "top"));
+    assert!(diff.to_json_string().starts_with("{\"old_total\":1.0000"));
+}
+
+#[test]
+fn test_gates() {
+    let report = CoverageReport::from(&analyze("counter.vcd"));
+    let thresholds = Thresholds::from_config_string(
+        "# CI gates\ntotal 95\nmodule top/cnt 100\nmodule top/dma 50\n",
+    )
+    .unwrap();
+    let failures = check_gates(&report, &thresholds, None);
+    assert_eq!(failures.len(), 2);
+    assert_eq!(failures[0].exit_code(), 2);
+    assert_eq!(
+        failures[1],
+        GateFailure::MissingModule {
+            module: "top/dma".into()
+        }
+    );
+    assert!(Thresholds::from_config_string("total 120").is_err());
+    assert_eq!(parse_percent("80%"), Ok(80.0));
+    assert!(parse_percent("150").is_err());
+
+    let mut baseline = report.clone();
+    baseline.bits[1].transitioned_down = true;
+    let failures = check_gates(&report, &Thresholds::default(), Some(&baseline));
+    assert_eq!(
+        failures,
+        vec![GateFailure::NewUncoveredBits {
+            bits: vec!["top/rst_n".into()]
+        }]
+    );
+}
+
+#[test]
+fn test_windows() {
+    let vcds = perform_windowed_analysis(Configuration {
+        in_file: "tests/files/counter.vcd".into(),
+        separator: ' ',
+        windows: vec![
+            "top/rst_n=1..40".parse().unwrap(),
+            "1000..".parse().unwrap(),
+        ],
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(vcds.len(), 2);
+    let whole = CoverageReport::from(&analyze("counter.vcd"));
+    let after_reset = CoverageReport::from(&vcds[0]);
+    assert_eq!(after_reset.bits[4].name, "top/cnt/overflow");
+    assert_eq!(whole.bits[4].coverage, 1.0);
+    assert!(after_reset.bits[4].coverage < 1.0);
+    assert_eq!(after_reset.bits[0].coverage, 1.0);
+    assert_eq!(CoverageReport::from(&vcds[1]).total_coverage(), 0.0);
+    assert!(vcds[0]
+        .to_result_string()
+        .contains("# Window: top/rst_n=1..40"));
+
+    // Bounds between two timestamps count from the bound, with the value held across it
+    let between = perform_analysis(Configuration {
+        in_file: "tests/files/counter.vcd".into(),
+        separator: ' ',
+        statistics: true,
+        windows: vec!["12..40".parse().unwrap()],
+        ..Default::default()
+    })
+    .unwrap();
+    let clk = between.signals[0].activity.as_ref().unwrap();
+    assert_eq!((clk.low_time, clk.high_time), (13, 15));
+    assert_eq!(between.duration, 28);
+
+    let missing = perform_analysis(Configuration {
+        in_file: "tests/files/counter.vcd".into(),
+        separator: ' ',
+        windows: vec!["top/start=1..".parse().unwrap()],
+        ..Default::default()
+    });
+    assert!(missing.is_err());
+}
+
+#[test]
+fn test_statistics() {
+    let vcd = perform_analysis(Configuration {
+        in_file: "tests/files/counter.vcd".into(),
+        separator: ' ',
+        statistics: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let clk = vcd.signals[0].activity.as_ref().unwrap();
+    assert_eq!(clk.toggles, 10);
+    assert_eq!((clk.high_time, clk.low_time), (25, 25));
+    assert_eq!(clk.duty_cycle(), Some(0.5));
+    assert_eq!((clk.min_pulse, clk.max_pulse), (Some(5), Some(5)));
+    assert_eq!(clk.glitches, 0);
+
+    let overflow = vcd.signals[4].activity.as_ref().unwrap();
+    assert_eq!(overflow.toggles, 4);
+    assert_eq!((overflow.high_time, overflow.low_time), (5, 45));
+    assert_eq!((overflow.min_pulse, overflow.max_pulse), (Some(2), Some(3)));
+    assert_eq!(overflow.glitches, 1);
+
+    let report = vcd.to_result_string();
+    assert!(report.contains("top/clk !-0 1.0 1 1 0 10 25 25 0.500 5 5 0"));
+    assert_eq!(
+        CoverageReport::from_result_string(&report).unwrap().bits,
+        CoverageReport::from(&vcd).bits
+    );
+    assert!(vcd
+        .to_json_string()
+        .contains("\"statistics\":{\"toggles\":10,\"high_time\":25,"));
+}
+
+#[test]
+fn test_saif() {
+    assert!(analyze("counter.vcd").to_saif_string().is_err());
+    let vcd = perform_analysis(Configuration {
+        in_file: "tests/files/counter.vcd".into(),
+        separator: ' ',
+        statistics: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let saif = vcd.to_saif_string().unwrap();
+    assert!(saif.contains("(TIMESCALE 1 ns)\n(DURATION 50)\n(INSTANCE top\n"));
+    assert!(saif.contains(
+        "  (INSTANCE cnt\n    (NET\n      (count\\[0\\]\n        (T0 20) (T1 20) (TX 10) (TZ 0)\n        (TC 2) (IG 0)"
+    ));
+}
+
+#[test]
+fn test_xz() {
+    let analyze_xz = |reset_end: Option<&str>| {
+        perform_analysis(Configuration {
+            in_file: "tests/files/counter.vcd".into(),
+            separator: ' ',
+            xz_analysis: true,
+            reset_end: reset_end.map(|bound| bound.parse().unwrap()),
+            ..Default::default()
+        })
+        .unwrap()
+    };
+    let vcd = analyze_xz(Some("top/rst_n=1"));
+    let count = vcd.signals[2].xz.as_ref().unwrap();
+    assert!(count.unknown_at_reset_end());
+    assert_eq!(count.x_time, 10);
+    assert_eq!(count.first_unknown, None);
+    let rst_n = vcd.signals[1].xz.as_ref().unwrap();
+    assert!(!rst_n.unknown_at_reset_end());
+    assert_eq!(rst_n.x_time, 2);
+    assert_eq!(rst_n.first_unknown.map(|state| state.time), Some(42));
+
+    let report = vcd.to_xz_string().unwrap();
+    assert!(report.contains("# Bits unknown at the end of reset: 2\ntop/cnt/count/[0] x\n"));
+    assert!(report
+        .contains("# Bits unknown after being known (name, value, time): 1\ntop/rst_n x 42\n"));
+    assert!(vcd
+        .to_xz_json_string()
+        .unwrap()
+        .starts_with("{\"reset_end\":\"top/rst_n=1\",\"signals\":[{\"name\":\"top/rst_n\""));
+
+    let after_reset = analyze_xz(Some("20"));
+    assert!(!after_reset.signals[2]
+        .xz
+        .as_ref()
+        .unwrap()
+        .unknown_at_reset_end());
+    assert!(analyze_xz(None).signals[2]
+        .xz
+        .as_ref()
+        .unwrap()
+        .unknown_at_reset_end());
+    assert!(analyze("counter.vcd").to_xz_string().is_err());
+}
+
+#[test]
+fn test_value_coverage() {
+    let bins = ValueBin::from_config_string(
+        "# count bins\nbin top/cnt/count low 0..0b1\nbin top/cnt/count max 0x3\nbin top/cnt/count wide 4..7\n",
+    )
+    .unwrap();
+    let vcd = perform_analysis(Configuration {
+        in_file: "tests/files/counter.vcd".into(),
+        separator: ' ',
+        value_coverage: true,
+        value_bins: bins,
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(vcd.buses.len(), 1);
+    let count = &vcd.buses[0];
+    assert_eq!(count.name, "top/cnt/count");
+    assert_eq!(
+        count.sorted_histogram(),
+        vec![
+            (&BusValue::Known(0), 2),
+            (&BusValue::Known(1), 1),
+            (&BusValue::Known(2), 1),
+            (&BusValue::Known(3), 1),
+            (&BusValue::Other("xx".into()), 1),
+        ]
+    );
+    assert_eq!(
+        count.bins.iter().map(|bin| bin.hits).collect::<Vec<_>>(),
+        vec![3, 1, 0]
+    );
+    assert!((count.bin_coverage().unwrap() - 2.0 / 3.0).abs() < 1e-9);
+    assert!(vcd
+        .to_value_coverage_string()
+        .contains("top/cnt/count width 2, 5 distinct values, bins 2/3 (66.67 %)\n  value 0x0 2\n"));
+
+    assert!(ValueBin::from_config_string("bin top/cnt/count bad 3..1").is_err());
+    let missing = perform_analysis(Configuration {
+        in_file: "tests/files/counter.vcd".into(),
+        separator: ' ',
+        value_coverage: true,
+        value_bins: ValueBin::from_config_string("bin top/nothing x 0").unwrap(),
+        ..Default::default()
+    });
+    assert!(missing.is_err());
+}
+
+#[test]
+fn test_fsm() {
+    let fsms = FsmDescription::from_config_string(
+        "# controller\nfsm top/ctrl/state\nstate IDLE 0\nstate RUN 0b01\nstate DONE 0x2\nstate ERR 3\n\
+         arc IDLE RUN\narc RUN DONE\narc DONE IDLE\narc RUN ERR\narc ERR IDLE\n",
+    )
+    .unwrap();
+    let analyze_fsm = |windows: Vec<TimeWindow>| {
+        perform_windowed_analysis(Configuration {
+            in_file: "tests/files/fsm.vcd".into(),
+            separator: ' ',
+            windows,
+            fsms: fsms.clone(),
+            detect_fsms: true,
+            ..Default::default()
+        })
+        .unwrap()
+        .swap_remove(0)
+    };
+    let vcd = analyze_fsm(vec![]);
+    assert_eq!(vcd.fsms.len(), 2);
+    let state = &vcd.fsms[0];
+    assert!(!state.detected);
+    // The 0b11 glitch at #25 is not a visit of ERR
+    assert_eq!(
+        state.states.iter().map(|s| s.visits).collect::<Vec<_>>(),
+        vec![2, 1, 2, 0]
+    );
+    assert_eq!(
+        state.arcs.iter().map(|a| a.taken).collect::<Vec<_>>(),
+        vec![1, 1, 1, 0, 0]
+    );
+    assert_eq!(
+        state.illegal,
+        vec![FsmTransition {
+            from: "IDLE".into(),
+            to: "DONE".into(),
+            time: 45
+        }]
+    );
+    assert_eq!(state.coverage(), Some((0.75, 0.6)));
+
+    let busy = &vcd.fsms[1];
+    assert!(busy.detected);
+    assert_eq!(busy.signal, "top/ctrl/busy_fsm");
+    assert_eq!(busy.arcs.len(), 2);
+    assert!(vcd
+        .to_fsm_string()
+        .contains("top/ctrl/state, states 75.00 %, arcs 60.00 %, 1 illegal transitions\n"));
+
+    let late = analyze_fsm(vec!["40..".parse().unwrap()]);
+    assert_eq!(
+        late.fsms[0]
+            .states
+            .iter()
+            .map(|s| s.visits)
+            .collect::<Vec<_>>(),
+        vec![1, 0, 1, 0]
+    );
+    assert_eq!(late.fsms[0].illegal.len(), 1);
+
+    assert!(FsmDescription::from_config_string("state IDLE 0").is_err());
+    assert!(FsmDescription::from_config_string("fsm a\nstate A 0\narc A B").is_err());
+}
+
+#[test]
+fn test_clocks_and_resets() {
+    let analyze_kinds = |exclude_clocks_resets: bool| {
+        perform_analysis(Configuration {
+            in_file: "tests/files/counter.vcd".into(),
+            separator: ' ',
+            detect_clocks_resets: true,
+            exclude_clocks_resets,
+            ..Default::default()
+        })
+        .unwrap()
+    };
+    let vcd = analyze_kinds(false);
+    assert_eq!(
+        vcd.signals[0].kind,
+        SignalKind::Clock {
+            period: 10.0,
+            jitter: 0,
+            duty_cycle: 0.5
+        }
+    );
+    assert_eq!(
+        vcd.signals[1].kind,
+        SignalKind::Reset {
+            active: vcd_reader::SignalValue::DOWN,
+            released: 10
+        }
+    );
+    assert!(vcd.signals[2..]
+        .iter()
+        .all(|signal| signal.kind == SignalKind::Data));
+    let report = vcd.to_result_string();
+    assert!(report.contains("# Clock top/clk period 10.000 jitter 0 duty cycle 0.500\n"));
+    assert!(report.contains("# Reset top/rst_n active 0 released 10\n"));
+    assert_eq!(CoverageReport::from(&vcd).bits.len(), 5);
+
+    let excluded = analyze_kinds(true);
+    let report = CoverageReport::from(&excluded);
+    assert_eq!(report.bits.len(), 3);
+    let rows = excluded.to_result_string();
+    assert_eq!(
+        rows.lines().filter(|line| !line.starts_with('#')).count(),
+        5
+    );
+    assert!(excluded.to_json_string().contains("{\"type\":\"clock\""));
+    assert!(report.bits.iter().all(|bit| bit.module() == "top/cnt"));
+    assert_eq!(
+        CoverageReport::from_result_string(&excluded.to_result_string())
+            .unwrap()
+            .bits,
+        report.bits
+    );
+    assert_eq!(
+        CoverageReport::from_json_string(&excluded.to_json_string())
+            .unwrap()
+            .bits,
+        report.bits
+    );
+
+    // A pulse that starts late is not a reset, however short
+    let dump = TempDump::new(
+        "one_shot.vcd",
+        "$scope module top $end\n$var wire 1 ! rst $end\n$var wire 1 \" go $end\n\
+         $upscope $end\n$enddefinitions $end\n\
+         #0\n$dumpvars\n0!\n0\"\n$end\n#2\n1!\n#10\n1\"\n#12\n0\"\n#20\n0!\n#100\n0!\n",
+    );
+    let vcd = perform_analysis(Configuration {
+        in_file: dump.path(),
+        separator: ' ',
+        detect_clocks_resets: true,
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(
+        vcd.signals[0].kind,
+        SignalKind::Reset {
+            active: vcd_reader::SignalValue::UP,
+            released: 20
+        }
+    );
+
+    assert_eq!(vcd.signals[1].kind, SignalKind::Data);
+}
+
+#[test]
+fn test_properties() {
+    let properties = Property::from_config_string(
+        "# handshake\n\
+         req_ack: @(posedge top/clk) $rose(top/req) |-> ##[1:3] top/ack\n\
+         valid_known: @(posedge top/clk) disable iff (top/rst_n == 0) !$isunknown(top/valid)\n\
+         data_max: top/data != 3\n",
+    )
+    .unwrap();
+    let vcd = perform_analysis(Configuration {
+        in_file: "tests/files/handshake.vcd".into(),
+        separator: ' ',
+        properties,
+        ..Default::default()
+    })
+    .unwrap();
+    let req_ack = &vcd.properties[0];
+    assert_eq!(req_ack.attempts, 2);
+    assert_eq!(
+        req_ack.violations,
+        vec![Violation {
+            time: 95,
+            start: 65,
+            values: vec![
+                ("top/clk".into(), "0".into()),
+                ("top/req".into(), "1".into()),
+                ("top/ack".into(), "0".into())
+            ]
+        }]
+    );
+    let valid_known = &vcd.properties[1];
+    assert_eq!(
+        valid_known
+            .violations
+            .iter()
+            .map(|violation| violation.time)
+            .collect::<Vec<_>>(),
+        vec![55]
+    );
+    let data_max = &vcd.properties[2];
+    assert_eq!(data_max.violations.len(), 1);
+    assert_eq!(data_max.violations[0].time, 70);
+    assert_eq!(vcd.violations(), 3);
+    assert!(vcd
+        .to_property_string()
+        .contains("req_ack attempts 2 violations 1 pending 0\n  violation 95 started 65 top/clk=0 top/req=1 top/ack=0\n"));
+
+    // Each window checks only the attempts starting inside it
+    let vcds = perform_windowed_analysis(Configuration {
+        in_file: "tests/files/handshake.vcd".into(),
+        separator: ' ',
+        properties: vcd
+            .properties
+            .iter()
+            .map(|checker| checker.property.clone())
+            .collect(),
+        windows: vec!["0..50".parse().unwrap(), "50..".parse().unwrap()],
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(vcds[0].properties[0].attempts, 1);
+    assert_eq!(vcds[0].violations(), 0);
+    assert_eq!(vcds[1].properties[0].attempts, 1);
+    assert_eq!(vcds[1].violations(), 3);
+
+    let next = Property::parse("next", "@(negedge a) b |=> ##[0:2] (c || d/[1])").unwrap();
+    assert_eq!(next.delay, (1, 3));
+    assert_eq!(next.clock, Some((Edge::Negedge, "a".into())));
+    assert!(Property::parse("bad", "a |-> ##[3:1] b").is_err());
+    assert!(Property::parse("bad", "$past(a)").is_err());
+}
+
+#[test]
+fn test_decoders() {
+    let decoders = DecoderDescription::from_config_string(
+        "# protocols\n\
+         uart console baud=10000000 tx=top/uart_tx\n\
+         spi flash sclk=top/spi/sclk cs=top/spi/cs_n mosi=top/spi/mosi miso=top/spi/miso\n\
+         i2c eeprom scl=top/i2c/scl sda=top/i2c/sda\n\
+         handshake aw clk=top/axi/clk valid=top/axi/valid ready=top/axi/ready data=top/axi/data\n",
+    )
+    .unwrap();
+    let vcd = perform_analysis(Configuration {
+        in_file: "tests/files/protocols.vcd".into(),
+        separator: ' ',
+        decoders,
+        ..Default::default()
+    })
+    .unwrap();
+    let summary = |index: usize| {
+        vcd.decoders[index]
+            .transactions()
+            .iter()
+            .map(|t| format!("{} {} {} {} {}", t.start, t.end, t.kind, t.data, t.note))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        summary(0),
+        vec!["1000 2000 byte 0x41 ", "2500 3500 byte 0xf framing error"]
+    );
+    assert_eq!(
+        summary(1),
+        vec![
+            "110 250 word mosi=0x3c miso=0xc3 ",
+            "410 470 word mosi=0x7 miso=0x0 incomplete"
+        ]
+    );
+    assert_eq!(
+        summary(2),
+        vec![
+            "5000 5000 start  ",
+            "5020 5180 address 0x50 write ack",
+            "5200 5360 data 0x5a nack",
+            "5385 5385 stop  "
+        ]
+    );
+    assert_eq!(
+        summary(3),
+        vec![
+            "6020 6040 transfer 0x11 2 wait cycles",
+            "6050 6050 transfer 0x22 0 wait cycles"
+        ]
+    );
+    let csv = vcd.to_transactions_csv_string();
+    assert!(csv.starts_with("decoder,start,end,kind,data,note\nconsole,1000,2000,byte,0x41,\n"));
+    assert!(vcd
+        .to_transactions_json_string()
+        .contains("{\"name\":\"eeprom\",\"transactions\":[{\"start\":5000,"));
+
+    assert!(DecoderDescription::from_config_string("uart console tx=top/uart_tx").is_err());
+    assert!(DecoderDescription::from_config_string("can bus rx=top/rx").is_err());
+    assert!(
+        DecoderDescription::from_config_string("spi flash sclk=a cs=b mosi=c miso=d bits=0")
+            .is_err()
+    );
+
+    // Each window keeps the transactions starting inside it
+    let vcds = perform_windowed_analysis(Configuration {
+        in_file: "tests/files/protocols.vcd".into(),
+        separator: ' ',
+        decoders: DecoderDescription::from_config_string(
+            "uart console baud=10000000 tx=top/uart_tx\n",
+        )
+        .unwrap(),
+        windows: vec!["0..2200".parse().unwrap(), "2200..".parse().unwrap()],
+        ..Default::default()
+    })
+    .unwrap();
+    let starts = |vcd: &VCD| {
+        vcd.decoders[0]
+            .transactions()
+            .iter()
+            .map(|t| t.start)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(starts(&vcds[0]), vec![1000]);
+    assert_eq!(starts(&vcds[1]), vec![2500]);
+}
+
+/// Rising edges of a line, to check the registration of protocols.
+#[derive(Debug, Clone, Default)]
+struct RisingEdges {
+    transactions: Vec<Transaction>,
+}
+
+impl Decoder for RisingEdges {
+    fn roles(&self) -> &'static [&'static str] {
+        &["line"]
+    }
+
+    fn on_sample(&mut self, before: &[BusValue], now: &[BusValue], time: i64) {
+        if before[0] == BusValue::Known(0) && now[0] == BusValue::Known(1) {
+            self.transactions.push(Transaction {
+                start: time,
+                end: time,
+                kind: "rise".into(),
+                data: String::new(),
+                note: String::new(),
+            });
+        }
+    }
+
+    fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    fn box_clone(&self) -> Box<dyn Decoder> {
+        Box::new(self.clone())
+    }
+}
+
+#[test]
+fn test_registered_protocol() {
+    let mut protocols = Protocols::default();
+    protocols.register("edges", |_, _| Ok(Box::new(RisingEdges::default())));
+    let config = "edges ready line=top/axi/ready\n";
+    assert!(DecoderDescription::from_config_string(config).is_err());
+    let vcd = perform_analysis(Configuration {
+        in_file: "tests/files/protocols.vcd".into(),
+        separator: ' ',
+        decoders: DecoderDescription::from_config_string_with(config, &protocols).unwrap(),
+        protocols,
+        ..Default::default()
+    })
+    .unwrap();
+    assert!(!vcd.decoders[0].transactions().is_empty());
+    assert!(vcd.decoders[0]
+        .transactions()
+        .iter()
+        .all(|t| t.kind == "rise"));
+}
+
+#[test]
+fn test_query_api() {
+    let vcd = analyze("counter.vcd");
+    let clk = vcd.bit("top/clk").unwrap();
+    assert_eq!(clk.calculate_coverage(), 1.0);
+    assert_eq!((clk.first_rise, clk.first_fall), (Some(5), Some(10)));
+    assert_eq!(clk.first_toggle(), Some(5));
+    let rst_n = vcd.bit("top/rst_n").unwrap();
+    assert!(rst_n.has_transitioned_up());
+    assert_eq!((rst_n.first_rise, rst_n.first_fall), (Some(10), None));
+    assert!(vcd.bit("top/cnt").is_none());
+
+    let count = vcd.bits_of("top/cnt/count");
+    assert_eq!(count.len(), 2);
+    assert_eq!(count[1].path(), "top/cnt/count/[1]");
+    assert_eq!(count[1].first_toggle(), Some(15));
+    assert_eq!(vcd.bits_of("top/clk").len(), 1);
+
+    let modules = vcd.module_summary();
+    assert_eq!(modules["top/cnt"].bits, 3);
+    assert_eq!(modules["top"].bits, 5);
+    assert_eq!(
+        vcd.total_coverage(),
+        CoverageReport::from(&vcd).total_coverage()
+    );
+    assert_eq!(vcd.counted_signals().count(), 5);
+
+    let windowed = perform_analysis(Configuration {
+        in_file: "tests/files/counter.vcd".into(),
+        separator: ' ',
+        windows: vec!["20..".parse().unwrap()],
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(windowed.bit("top/clk").unwrap().first_rise, Some(25));
+}
+
+#[derive(Default)]
+struct RecordingProgress {
+    phases: std::sync::Mutex<Vec<Phase>>,
+    bytes: std::sync::Mutex<Vec<(u64, u64)>>,
+}
+
+impl ProgressSink for RecordingProgress {
+    fn phase(&self, phase: Phase) {
+        self.phases.lock().unwrap().push(phase);
+    }
+
+    fn bytes(&self, read: u64, total: u64) {
+        self.bytes.lock().unwrap().push((read, total));
+    }
+}
+
+#[test]
+fn test_progress() {
+    let progress = std::sync::Arc::new(RecordingProgress::default());
+    perform_analysis(Configuration {
+        in_file: "tests/files/counter.vcd".into(),
+        separator: ' ',
+        progress: Some(progress.clone()),
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(
+        *progress.phases.lock().unwrap(),
+        vec![
+            Phase::Definitions,
+            Phase::Initializations,
+            Phase::Changes,
+            Phase::Finished
+        ]
+    );
+    let size = std::fs::metadata("tests/files/counter.vcd").unwrap().len();
+    let bytes = progress.bytes.lock().unwrap();
+    assert_eq!(bytes.last(), Some(&(size, size)));
+    assert!(bytes.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+}
+
+struct CancelOnChanges(CancelToken);
+
+impl ProgressSink for CancelOnChanges {
+    fn phase(&self, phase: Phase) {
+        if phase == Phase::Changes {
+            self.0.cancel();
+        }
+    }
+
+    fn bytes(&self, _: u64, _: u64) {}
+}
+
+#[test]
+fn test_cancel() {
+    let cancel = CancelToken::new();
+    let vcd = perform_analysis(Configuration {
+        in_file: "tests/files/counter.vcd".into(),
+        separator: ' ',
+        progress: Some(std::sync::Arc::new(CancelOnChanges(cancel.clone()))),
+        cancel,
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(vcd.cancelled_at, Some(0));
+    assert!(vcd
+        .to_result_string()
+        .contains("# Incomplete: analysis cancelled at time 0"));
+    assert!(vcd.to_json_string().contains("\"cancelled_at\":0"));
+    assert!(vcd.total_coverage() < analyze("counter.vcd").total_coverage());
+}
+
+/// Cancels at the first progress report on the changes.
+struct CancelOnBytes(CancelToken);
+
+impl ProgressSink for CancelOnBytes {
+    fn phase(&self, _: Phase) {}
+
+    fn bytes(&self, read: u64, _: u64) {
+        if read > 0 {
+            self.0.cancel();
+        }
+    }
+}
+
+#[test]
+fn test_cancel_keeps_closed_windows() {
+    let mut content = String::from(
+        "$scope module top $end\n$var wire 1 ! clk $end\n$upscope $end\n$enddefinitions $end\n\
+         $dumpvars\nb0 !\n$end\n",
+    );
+    (1..20000).for_each(|time| content += &format!("#{}\nb{} !\n", time * 5, time % 2));
+    let dump = TempDump::new("cancel_windows.vcd", &content);
+    let cancel = CancelToken::new();
+    let vcds = perform_windowed_analysis(Configuration {
+        in_file: dump.path(),
+        separator: ' ',
+        progress: Some(std::sync::Arc::new(CancelOnBytes(cancel.clone()))),
+        cancel,
+        windows: vec!["..20".parse().unwrap(), "20..".parse().unwrap()],
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(vcds[0].cancelled_at, None);
+    assert!(!vcds[0].to_result_string().contains("# Incomplete"));
+    let time = vcds[1].cancelled_at.unwrap();
+    assert!(time > 20 && time < 99995);
+}
+
+#[test]
+fn test_first_error_stops_analysis() {
+    let result = perform_analysis(Configuration {
+        in_file: "tests/files/malformed.vcd".into(),
+        separator: ' ',
+        ..Default::default()
+    });
+    assert_eq!(result.err(), Some("Line 10: Unknown signal id ?".into()));
+}
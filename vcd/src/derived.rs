@@ -0,0 +1,99 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use vcd_indexer::{index_lazy, Configuration, State, PATH_SEPARATOR};
+
+/// Evaluates the `<name>=<expression>` derivations over the dump and writes the derived
+/// signals as a VCD file, in a `derived` scope. Paths are written with `/` like in the other
+/// options, or with the `.` of the index.
+pub fn save_derived(
+    in_file: &str,
+    separator: char,
+    derivations: &[String],
+    out_file: &str,
+) -> Result<(), String> {
+    let mut vcd = index_lazy(Configuration {
+        in_file: in_file.to_string(),
+        separator,
+    })?;
+    let mut signals = vec![];
+    for derivation in derivations {
+        let (name, expression) = derivation
+            .split_once('=')
+            .ok_or_else(|| format!("Expected <name>=<expression>, found {}", derivation))?;
+        let signal = vcd
+            .add_derived(
+                name.trim(),
+                &expression.replace('/', &PATH_SEPARATOR.to_string()),
+            )
+            .map_err(|err| format!("Derived signal {}: {}", name.trim(), err))?;
+        signals.push(signal);
+    }
+
+    let mut changes: Vec<(usize, State)> = signals
+        .iter()
+        .enumerate()
+        .flat_map(|(code, signal)| {
+            vcd.changes_in(*signal, i64::MIN..i64::MAX)
+                .map(move |state| (code, state))
+        })
+        .collect();
+    changes.sort_by_key(|(_, state)| state.time);
+
+    let file = File::create(out_file).map_err(|err| format!("{}: {}", out_file, err))?;
+    let mut out = BufWriter::new(file);
+    let write = |err: std::io::Error| format!("{}: {}", out_file, err);
+    if let Some(timescale) = &vcd.timescale {
+        writeln!(out, "$timescale {} $end", timescale).map_err(write)?;
+    }
+    writeln!(out, "$scope module derived $end").map_err(write)?;
+    for (code, signal) in signals.iter().enumerate() {
+        let signal = &vcd.signals[*signal];
+        writeln!(
+            out,
+            "$var wire {} {} {} $end",
+            signal.width,
+            id_code(code),
+            signal.name
+        )
+        .map_err(write)?;
+    }
+    writeln!(out, "$upscope $end\n$enddefinitions $end").map_err(write)?;
+
+    let mut current = None;
+    for (code, state) in changes.iter() {
+        if current != Some(state.time) {
+            match (current, state.time) {
+                (_, -1) => writeln!(out, "$dumpvars"),
+                (Some(-1), time) => writeln!(out, "$end\n#{}", time),
+                (_, time) => writeln!(out, "#{}", time),
+            }
+            .map_err(write)?;
+            current = Some(state.time);
+        }
+        match state.value.width() {
+            1 => writeln!(out, "{}{}", state.value, id_code(*code)),
+            _ => writeln!(out, "b{} {}", state.value, id_code(*code)),
+        }
+        .map_err(write)?;
+    }
+    if current == Some(-1) {
+        writeln!(out, "$end").map_err(write)?;
+    }
+    out.flush().map_err(write)
+}
+
+/// Short identifier of the `code`th signal, made of the printable characters.
+fn id_code(mut code: usize) -> String {
+    let mut id = String::new();
+    loop {
+        id.push((b'!' + (code % 94) as u8) as char);
+        code /= 94;
+        if code == 0 {
+            return id;
+        }
+        code -= 1;
+    }
+}
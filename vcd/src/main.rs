@@ -1,10 +1,35 @@
-use clap::Parser;
-use logger::Log;
-use std::io::stdout;
-use vcd_statistical_analysis::{self, perform_analysis_and_save, Configuration};
+mod derived;
+mod progress;
+
+use clap::{Parser, ValueEnum};
+use logger::{Log, Priority};
+use progress::TerminalProgress;
+use std::{io::stdout, process::exit, sync::Arc};
+use vcd_statistical_analysis::{
+    self, check_gates, parse_percent, perform_windowed_analysis, window_out_file, CancelToken,
+    Configuration, CoverageDiff, CoverageReport, DecoderDescription, FsmDescription, Property,
+    ReportFormat, Thresholds, TimeWindow, ValueBin, WindowBound,
+};
+
+const EXIT_ERROR: i32 = 1;
+const EXIT_VIOLATIONS: i32 = 5;
+const EXIT_CANCELLED: i32 = 130;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    after_help = "Exit codes: 0 success, 1 analysis error, 2 total coverage below minimum, \
+                  3 module coverage below minimum, 4 bits lost coverage against the baseline, \
+                  5 property violations, 130 cancelled with Ctrl-C (the reports are partial)"
+)]
 struct Args {
     /// Input file path
     #[arg(short, long)]
@@ -15,18 +40,274 @@ struct Args {
     /// Separator for changes.
     #[arg(short, long, default_value_t = '<')]
     separator: char,
+    /// Format of the result file
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Add toggle count, high/low time, duty cycle, pulse widths and glitches of every bit
+    #[arg(long)]
+    statistics: bool,
+    /// Also write the switching activity as a backward SAIF file
+    #[arg(long)]
+    saif: Option<String>,
+    /// Also write the bits that are X/Z at the end of reset or go X/Z later
+    #[arg(long)]
+    xz_report: Option<String>,
+    /// End of the reset for the X/Z report: a time or a <signal path>=<value> condition
+    /// (default: end of the initializations)
+    #[arg(long, requires = "xz_report")]
+    reset_end: Option<WindowBound>,
+    /// Also write the histogram of the values taken by every bus
+    #[arg(long)]
+    value_coverage: Option<String>,
+    /// File with value bins of the buses, one `bin <bus path> <name> <value|low..high>` per line
+    #[arg(long, requires = "value_coverage")]
+    value_bins: Option<String>,
+    /// Also write the visited states, taken arcs and illegal transitions of the state registers
+    #[arg(long)]
+    fsm_report: Option<String>,
+    /// File describing the state registers: `fsm <path>`, then `state <name> <encoding>`
+    /// and `arc <from> <to>` lines
+    #[arg(long, requires = "fsm_report")]
+    fsm: Option<String>,
+    /// Also report the registers named `*_state` or `*_fsm` without a description
+    #[arg(long, requires = "fsm_report")]
+    detect_fsms: bool,
+    /// File with one `<name>: <property>` per line to check over the dump, e.g.
+    /// `req_ack: @(posedge top/clk) $rose(top/req) |-> ##[1:10] top/ack`
+    #[arg(long, requires = "property_report")]
+    properties: Option<String>,
+    /// Output file path for the property violations
+    #[arg(long, requires = "properties")]
+    property_report: Option<String>,
+    /// File with one protocol decoder per line: `uart|spi|i2c|handshake <name> <key>=<value>...`,
+    /// e.g. `uart console baud=115200 tx=top/uart_tx`
+    #[arg(long, requires = "transactions")]
+    decoders: Option<String>,
+    /// Output file path for the decoded transactions (CSV, or JSON with `--format json`)
+    #[arg(long, requires = "decoders")]
+    transactions: Option<String>,
+    /// List the clocks (period, jitter, duty cycle) and resets in the report
+    #[arg(long)]
+    detect_clocks_resets: bool,
+    /// Leave the clocks and resets out of the coverage totals
+    #[arg(long)]
+    exclude_clocks_resets: bool,
+    /// Result file, text or JSON, of a previous analysis to compare the coverage against
+    #[arg(short, long)]
+    baseline: Option<String>,
+    /// Output file path for the coverage diff (default: <out_file>.diff)
+    #[arg(long, requires = "baseline")]
+    diff_file: Option<String>,
+    /// Format of the coverage diff
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    diff_format: Format,
+    /// Minimum total coverage [%]
+    #[arg(long, value_parser = parse_percent)]
+    min_total: Option<f64>,
+    /// File with per-module coverage thresholds
+    #[arg(long)]
+    thresholds: Option<String>,
+    /// Fail if any bit lost coverage against the baseline
+    #[arg(long, requires = "baseline")]
+    no_new_uncovered: bool,
+    /// Only count transitions in <start>..<end>. Bounds are times or <signal path>=<value>
+    /// conditions, e.g. `top/rst_n=1..`. Repeat for one report per window
+    #[arg(short, long = "window")]
+    windows: Vec<TimeWindow>,
+    /// Derived signal `<name>=<expression>` to write to the derived file, e.g.
+    /// `hit=top/valid && top/ready` or `page=top/addr[31:12]`. Repeat for more signals
+    #[arg(long = "derive", requires = "derived_file")]
+    derivations: Vec<String>,
+    /// Output VCD file path for the derived signals. They are evaluated in a second reading
+    /// of the dump after the coverage analysis, skipped when the analysis is cancelled
+    #[arg(long, requires = "derivations")]
+    derived_file: Option<String>,
+}
+
+impl From<Format> for ReportFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Text => ReportFormat::Text,
+            Format::Json => ReportFormat::Json,
+        }
+    }
+}
+
+fn fail(error: &str) -> ! {
+    Log::write(Priority::Error, error);
+    Log::flush();
+    exit(EXIT_ERROR);
+}
+
+fn exit_on_violations(violations: usize) {
+    if violations > 0 {
+        exit(EXIT_VIOLATIONS);
+    }
 }
 
 fn main() {
     let args = Args::parse();
+    Log::add(Box::new(stdout().lock()));
+    let value_bins = match &args.value_bins {
+        Some(path) => ValueBin::from_file(path).unwrap_or_else(|e| fail(&e)),
+        None => vec![],
+    };
+    let fsms = match &args.fsm {
+        Some(path) => FsmDescription::from_file(path).unwrap_or_else(|e| fail(&e)),
+        None => vec![],
+    };
+    let properties = match &args.properties {
+        Some(path) => Property::from_file(path).unwrap_or_else(|e| fail(&e)),
+        None => vec![],
+    };
+    let decoders = match &args.decoders {
+        Some(path) => DecoderDescription::from_file(path).unwrap_or_else(|e| fail(&e)),
+        None => vec![],
+    };
+    let cancel = CancelToken::new();
+    {
+        let cancel = cancel.clone();
+        let handler = ctrlc::set_handler(move || {
+            if cancel.is_cancelled() {
+                exit(EXIT_CANCELLED);
+            }
+            eprintln!("\nCancelling, press Ctrl-C again to quit without a report");
+            cancel.cancel();
+        });
+        if let Err(e) = handler {
+            Log::write(Priority::Warn, &format!("Ctrl-C not handled: {}", e));
+        }
+    }
+    let in_file = args.in_file.clone();
     let c = Configuration {
         in_file: args.in_file,
-        out_file: args.out_file,
+        out_file: args.out_file.clone(),
         separator: args.separator,
-        use_spinner: true,
+        progress: Some(Arc::new(TerminalProgress::new())),
+        cancel: cancel.clone(),
+        windows: args.windows,
+        statistics: args.statistics || args.saif.is_some(),
+        xz_analysis: args.xz_report.is_some(),
+        reset_end: args.reset_end,
+        format: args.format.into(),
+        value_coverage: args.value_coverage.is_some(),
+        value_bins,
+        fsms,
+        detect_fsms: args.detect_fsms,
+        detect_clocks_resets: args.detect_clocks_resets,
+        exclude_clocks_resets: args.exclude_clocks_resets,
+        properties,
+        decoders,
+        protocols: Default::default(),
     };
-    Log::add(Box::new(stdout().lock()));
-    if let Err(e) = perform_analysis_and_save(c) {
-        Log::write(logger::Priority::Error, &e.to_string());
+    let mut thresholds = match &args.thresholds {
+        Some(path) => Thresholds::from_file(path).unwrap_or_else(|e| fail(&e)),
+        None => Thresholds::default(),
+    };
+    if args.min_total.is_some() {
+        thresholds.min_total = args.min_total;
+    }
+    let baseline = args
+        .baseline
+        .as_ref()
+        .map(|path| CoverageReport::from_file(path).unwrap_or_else(|e| fail(&e)));
+
+    let format = c.format;
+    let vcds = perform_windowed_analysis(c).unwrap_or_else(|e| fail(&e));
+    let mut failures = vec![];
+    for (index, vcd) in vcds.iter().enumerate() {
+        let result_file = window_out_file(&args.out_file, index, vcds.len());
+        if let Err(e) = vcd.save(&result_file, format) {
+            fail(&e);
+        }
+        if let Some(xz_report) = &args.xz_report {
+            if let Err(e) = vcd.save_xz(&window_out_file(xz_report, index, vcds.len()), format) {
+                fail(&e);
+            }
+        }
+        if let Some(value_coverage) = &args.value_coverage {
+            let path = window_out_file(value_coverage, index, vcds.len());
+            if let Err(e) = vcd.save_value_coverage(&path, format) {
+                fail(&e);
+            }
+        }
+        if let Some(fsm_report) = &args.fsm_report {
+            if let Err(e) = vcd.save_fsm(&window_out_file(fsm_report, index, vcds.len()), format) {
+                fail(&e);
+            }
+        }
+        if let Some(property_report) = &args.property_report {
+            let path = window_out_file(property_report, index, vcds.len());
+            if let Err(e) = vcd.save_properties(&path, format) {
+                fail(&e);
+            }
+        }
+        if let Some(transactions) = &args.transactions {
+            let path = window_out_file(transactions, index, vcds.len());
+            if let Err(e) = vcd.save_transactions(&path, format) {
+                fail(&e);
+            }
+        }
+        if let Some(saif) = &args.saif {
+            if let Err(e) = vcd.save_saif(&window_out_file(saif, index, vcds.len())) {
+                fail(&e);
+            }
+        }
+        let report = CoverageReport::from(vcd);
+        if let Some(baseline) = &baseline {
+            let diff_file = match &args.diff_file {
+                Some(diff_file) => window_out_file(diff_file, index, vcds.len()),
+                None => format!("{}.diff", result_file),
+            };
+            if let Err(e) =
+                CoverageDiff::new(baseline, &report).save(&diff_file, args.diff_format.into())
+            {
+                fail(&e);
+            }
+        }
+        let gate_baseline = baseline.as_ref().filter(|_| args.no_new_uncovered);
+        failures.extend(
+            check_gates(&report, &thresholds, gate_baseline)
+                .into_iter()
+                .map(|failure| (result_file.clone(), failure)),
+        );
+    }
+    if let Some(derived_file) = &args.derived_file {
+        if cancel.is_cancelled() {
+            Log::write(
+                Priority::Warn,
+                "Analysis cancelled: derived signals not written",
+            );
+        } else if let Err(e) =
+            derived::save_derived(&in_file, args.separator, &args.derivations, derived_file)
+        {
+            fail(&e);
+        }
+    }
+    Log::flush();
+    if cancel.is_cancelled() {
+        eprintln!("Analysis cancelled: the reports are incomplete");
+        exit(EXIT_CANCELLED);
+    }
+    let violations: usize = vcds.iter().map(|vcd| vcd.violations()).sum();
+    if violations > 0 {
+        eprintln!("Property violations: {}", violations);
+    }
+    if thresholds.min_total.is_none() && thresholds.modules.is_empty() && !args.no_new_uncovered {
+        exit_on_violations(violations);
+        return;
+    }
+    if failures.is_empty() {
+        eprintln!("Coverage gate passed");
+        exit_on_violations(violations);
+        return;
+    }
+    eprintln!("Coverage gate failed:");
+    for (result_file, failure) in failures.iter() {
+        match vcds.len() {
+            1 => eprintln!("  {}", failure.to_result_string()),
+            _ => eprintln!("  {}: {}", result_file, failure.to_result_string()),
+        }
     }
+    exit(failures[0].1.exit_code());
 }
@@ -0,0 +1,80 @@
+use std::{
+    io::{stderr, IsTerminal, Write},
+    sync::Mutex,
+    time::Instant,
+};
+
+use vcd_statistical_analysis::{Phase, ProgressSink};
+
+const BAR_WIDTH: usize = 30;
+
+/// Progress bar on stderr, drawn only when stderr is a terminal.
+pub struct TerminalProgress {
+    enabled: bool,
+    start: Instant,
+    state: Mutex<(Phase, u64, u64)>,
+}
+
+impl TerminalProgress {
+    pub fn new() -> Self {
+        TerminalProgress {
+            enabled: stderr().is_terminal(),
+            start: Instant::now(),
+            state: Mutex::new((Phase::Definitions, 0, 0)),
+        }
+    }
+
+    fn draw(&self, phase: Phase, read: u64, total: u64) {
+        let fraction = match total {
+            0 => 0.0,
+            _ => read as f64 / total as f64,
+        };
+        let filled = (fraction * BAR_WIDTH as f64) as usize;
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let eta = match fraction > 0.0 && fraction < 1.0 {
+            true => format!(" ETA {:.0}s", elapsed / fraction - elapsed),
+            false => String::new(),
+        };
+        let mut err = stderr().lock();
+        let _ = write!(
+            err,
+            "\r[{}{}] {:3.0}% {}{}\x1b[K",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            fraction * 100.0,
+            phase,
+            eta
+        );
+        let _ = err.flush();
+    }
+}
+
+impl ProgressSink for TerminalProgress {
+    fn phase(&self, phase: Phase) {
+        if !self.enabled {
+            return;
+        }
+        let (read, total) = {
+            let mut state = self.state.lock().unwrap();
+            state.0 = phase;
+            (state.1, state.2)
+        };
+        self.draw(phase, read, total);
+        if phase == Phase::Finished {
+            eprintln!();
+        }
+    }
+
+    fn bytes(&self, read: u64, total: u64) {
+        if !self.enabled {
+            return;
+        }
+        let phase = {
+            let mut state = self.state.lock().unwrap();
+            state.1 = read;
+            state.2 = total;
+            state.0
+        };
+        self.draw(phase, read, total);
+    }
+}